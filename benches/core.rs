@@ -0,0 +1,87 @@
+//! Micro-benchmarks of the core protocol.
+//!
+//! ```text
+//! cargo bench --bench core
+//! ```
+//!
+//! Each is run for payloads of 8, 64 and 512 bytes.  Save a baseline with
+//! `-- --save-baseline before`, and compare a change against it with
+//! `-- --baseline before`.
+
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+use seqloq::Seqloq;
+
+type Group<'a> = criterion::BenchmarkGroup<'a, criterion::measurement::WallTime>;
+
+fn id<const N: usize>() -> BenchmarkId {
+    BenchmarkId::from_parameter(N * 8)
+}
+
+/// Run `f` while a background thread spins doing `background`.
+fn with_background<B, F>(background: B, f: F)
+    where B: Fn() + Sync,
+          F: FnOnce(),
+{
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| {
+            while !stop.load(Ordering::Relaxed) {
+                background();
+            }
+        });
+        f();
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+fn uncontended_read<const N: usize>(g: &mut Group) {
+    let x = Seqloq::new([0u64; N]);
+    g.bench_function(id::<N>(), |b| b.iter(|| black_box(x.read())));
+}
+
+fn contended_read<const N: usize>(g: &mut Group) {
+    let x = Seqloq::new([0u64; N]);
+    with_background(|| { black_box(x.read()); }, || {
+        g.bench_function(id::<N>(), |b| b.iter(|| black_box(x.read())));
+    });
+}
+
+fn uncontended_lock<const N: usize>(g: &mut Group) {
+    let x = Seqloq::new([0u64; N]);
+    g.bench_function(id::<N>(), |b| b.iter(|| {
+        x.lock()[0] += 1;
+    }));
+}
+
+fn read_while_writing<const N: usize>(g: &mut Group) {
+    let x = Seqloq::new([0u64; N]);
+    with_background(|| x.update(|v| v[0] += 1), || {
+        g.bench_function(id::<N>(), |b| b.iter(|| black_box(x.read())));
+    });
+}
+
+fn core(c: &mut Criterion) {
+    macro_rules! group {
+        ($bench:ident) => ({
+            let mut g = c.benchmark_group(stringify!($bench));
+            g.throughput(Throughput::Elements(1));
+            $bench::<1>(&mut g);
+            $bench::<8>(&mut g);
+            $bench::<64>(&mut g);
+            g.finish();
+        })
+    }
+
+    group!(uncontended_read);
+    group!(contended_read);
+    group!(uncontended_lock);
+    group!(read_while_writing);
+}
+
+criterion_group!(benches, core);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+//! A sequence of reads and writes from one thread, checked against a model.
+//! Every snapshot must carry a valid checksum and equal the last value
+//! written.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+use seqloq::Seqloq;
+use seqloq::tests::Checksummed;
+
+type Payload = Checksummed<8>;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Read,
+    Peek,
+    Write(u64),
+    Update(u64),
+    /// Lock, and write each value in turn, publishing in between.
+    Lock(Vec<u64>),
+    TryLock(u64),
+    /// Stage a value, then commit it or abort.
+    Staged(u64, bool),
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let x = Seqloq::new(Payload::new(0));
+    let mut model = Payload::new(0);
+    let check = |p: Payload, model: Payload| {
+        assert!(p.is_valid(), "torn read {:?}", p);
+        assert_eq!(p, model);
+    };
+    for op in ops {
+        match op {
+            Op::Read => check(x.read(), model),
+            Op::Peek => check(x.peek(|p| unsafe { *p }), model),
+            Op::Write(seed) => {
+                model = Payload::new(seed);
+                x.write(model);
+            }
+            Op::Update(seed) => {
+                model = Payload::new(seed);
+                x.update(|p| *p = model);
+            }
+            Op::Lock(seeds) => {
+                let mut g = x.lock();
+                for seed in seeds {
+                    model = Payload::new(seed);
+                    *g = model;
+                    g.publish();
+                }
+            }
+            Op::TryLock(seed) => if let Some(mut g) = x.try_lock() {
+                model = Payload::new(seed);
+                *g = model;
+            },
+            Op::Staged(seed, commit) => {
+                let mut g = x.lock_staged();
+                *g = Payload::new(seed);
+                if commit {
+                    model = *g;
+                    g.commit();
+                }
+            }
+        }
+        assert_eq!(x.version() % 2, 0);
+    }
+    check(x.read(), model);
+});
@@ -0,0 +1,67 @@
+//! Writers and readers racing on one `Seqloq`.  Writers store their values
+//! a word at a time, yielding in between, so that readers have every
+//! chance to catch a half-written payload; readers check the checksum of
+//! every snapshot they take, so a torn read can't slip past.
+
+#![no_main]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+use seqloq::Seqloq;
+use seqloq::tests::Checksummed;
+
+type Payload = Checksummed<8>;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Seeds for each writer's values, in order.
+    writers: Vec<Vec<u64>>,
+    /// For each reader, whether to `peek` instead of `read`.
+    readers: Vec<bool>,
+}
+
+fuzz_target!(|input: Input| {
+    let writers: Vec<Vec<u64>> = input.writers.into_iter().take(4)
+        .map(|seeds| seeds.into_iter().take(64).collect())
+        .collect();
+    let readers: Vec<bool> = input.readers.into_iter().take(4).collect();
+    if writers.is_empty() || readers.is_empty() {
+        return;
+    }
+
+    let x = &Seqloq::new(Payload::new(0));
+    let running = &AtomicUsize::new(writers.len());
+    thread::scope(|s| {
+        for seeds in &writers {
+            s.spawn(move || {
+                for &seed in seeds {
+                    let new = Payload::new(seed);
+                    let mut g = x.lock();
+                    for (i, &w) in new.words.iter().enumerate() {
+                        g.words[i] = w;
+                        thread::yield_now();
+                    }
+                    g.sum = new.sum;
+                }
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        for &peek in &readers {
+            s.spawn(move || {
+                // At least one read after the writers finish, too.
+                loop {
+                    let done = running.load(Ordering::SeqCst) == 0;
+                    let p = if peek { x.peek(|p| unsafe { *p }) } else { x.read() };
+                    assert!(p.is_valid(), "torn read {:?}", p);
+                    if done {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+});
@@ -0,0 +1,46 @@
+/* C interface to seqloq.  Build the crate with the `ffi` feature.
+ *
+ * This file can be regenerated with `cbindgen --config cbindgen.toml`. */
+
+#ifndef SEQLOQ_H
+#define SEQLOQ_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* A handle to a seqloq: a 16-byte header (sequence number, writer lock
+ * word, writer process ID), followed by the data.  The seqloq itself must
+ * be 8-byte aligned. */
+typedef struct seqloq seqloq;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+size_t seqloq_size(size_t data_len);
+
+seqloq *seqloq_create(size_t data_len);
+
+void seqloq_destroy(seqloq *s);
+
+seqloq *seqloq_init(void *region, size_t len);
+
+seqloq *seqloq_attach(void *region, size_t len);
+
+size_t seqloq_data_len(const seqloq *s);
+
+uint64_t seqloq_read(const seqloq *s, void *buf, size_t len);
+
+uint64_t seqloq_version(const seqloq *s);
+
+void seqloq_write(seqloq *s, const void *buf, size_t len);
+
+void *seqloq_lock(seqloq *s);
+
+void seqloq_unlock(seqloq *s);
+
+#ifdef __cplusplus
+}  /* extern "C" */
+#endif
+
+#endif  /* SEQLOQ_H */
@@ -0,0 +1,183 @@
+//! `#[derive(Seqloqed)]`, re-exported by `seqloq` with the `derive`
+//! feature.  See the documentation there.
+
+#![deny(warnings)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Error, Fields, Ident, LitStr, parse_macro_input};
+
+/// Generate a shared version of a struct, with each field in its own
+/// seqlock.
+///
+/// For a struct `State`, this generates `StateSeqloqed` (or the name given
+/// by `#[seqloqed(name = "...")]`), with:
+///
+/// * `new(State)`, and `From<State>`;
+/// * a getter and a `set_` method for each field, which only disturb
+///   readers of that field;
+/// * `snapshot()`, which reads every field as of a single instant;
+/// * `write(State)`, which replaces every field at once;
+/// * `version()`, which changes on every write to any field.
+///
+/// The fields must be `Copy + Send`, and can't be named like another
+/// generated method, such as `new` or `set_x` alongside `x`.  Writers to different fields still
+/// exclude one another, for the sake of `snapshot`.
+///
+/// ```ignore
+/// use seqloq::Seqloqed;
+///
+/// #[derive(Seqloqed, Clone, Copy)]
+/// #[seqloqed(name = "Shared")]
+/// pub struct State {
+///     pub position: [f32; 3],
+///     pub health: u32,
+/// }
+///
+/// let s = Shared::new(State { position: [0.0; 3], health: 100 });
+/// s.set_health(90);
+/// assert_eq!(s.health(), 90);
+/// assert_eq!(s.snapshot().position, [0.0; 3]);
+/// ```
+#[proc_macro_derive(Seqloqed, attributes(seqloqed))]
+pub fn derive_seqloqed(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics,
+            "#[derive(Seqloqed)] doesn't support generic structs"));
+    }
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => &f.named,
+            _ => return Err(Error::new_spanned(name,
+                "#[derive(Seqloqed)] needs a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(name,
+            "#[derive(Seqloqed)] only works on structs")),
+    };
+
+    let mut shared = format_ident!("{}Seqloqed", name);
+    for attr in &input.attrs {
+        if attr.path().is_ident("seqloqed") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    let s: LitStr = meta.value()?.parse()?;
+                    shared = Ident::new(&s.value(), s.span());
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `name = \"...\"`"))
+                }
+            })?;
+        }
+    }
+
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    for ident in &idents {
+        let field = ident.to_string();
+        let clashes = ["new", "snapshot", "write", "version"].contains(&&*field)
+            || field.starts_with("__seqloq")
+            || field.strip_prefix("set_").is_some_and(|f| idents.iter().any(|i| *i == f));
+        if clashes {
+            return Err(Error::new_spanned(ident, format!(
+                "#[derive(Seqloqed)] can't generate a getter for `{}`, \
+                 which clashes with another generated item", field)));
+        }
+    }
+    let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let vises: Vec<_> = fields.iter().map(|f| &f.vis).collect();
+    let setters: Vec<_> = idents.iter()
+        .map(|i| Ident::new(&format!("set_{}", i), Span::call_site()))
+        .collect();
+    let doc = format!("`{}` with each field in its own seqlock.  \
+        Generated by `#[derive(Seqloqed)]`.", name);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #shared {
+            __seqloq_lock: ::seqloq::WordLock,
+            __seqloq_seq: ::seqloq::SeqCount,
+            #( #idents: ::seqloq::Seqloq<#types>, )*
+        }
+
+        impl #shared {
+            #vis fn new(init: #name) -> #shared {
+                #shared {
+                    __seqloq_lock: ::seqloq::WordLock::new(),
+                    __seqloq_seq: ::seqloq::SeqCount::new(),
+                    #( #idents: ::seqloq::Seqloq::new(init.#idents), )*
+                }
+            }
+
+            /// All the fields, as of a single instant.
+            #vis fn snapshot(&self) -> #name {
+                loop {
+                    let start = self.__seqloq_seq.read_begin();
+                    let snapshot = #name {
+                        #( #idents: self.#idents.read(), )*
+                    };
+                    if !self.__seqloq_seq.read_retry(start) {
+                        return snapshot;
+                    }
+                }
+            }
+
+            /// Replace all the fields at once.
+            #vis fn write(&self, new: #name) {
+                self.__seqloq_locked(|| {
+                    #( self.#idents.write(new.#idents); )*
+                })
+            }
+
+            /// The whole-struct version, which changes on every write to
+            /// any field.
+            #vis fn version(&self) -> u64 {
+                self.__seqloq_seq.version()
+            }
+
+            #(
+                #vises fn #idents(&self) -> #types {
+                    self.#idents.read()
+                }
+
+                #vises fn #setters(&self, v: #types) {
+                    self.__seqloq_locked(|| self.#idents.write(v))
+                }
+            )*
+
+            fn __seqloq_locked<F: FnOnce()>(&self, f: F) {
+                use ::seqloq::RawMutex;
+
+                // Ends the write even if `f` unwinds, so that `snapshot`
+                // doesn't spin forever.
+                struct Unlock<'a>(&'a #shared);
+                impl ::std::ops::Drop for Unlock<'_> {
+                    fn drop(&mut self) {
+                        self.0.__seqloq_seq.write_end();
+                        unsafe {
+                            self.0.__seqloq_lock.unlock();
+                        }
+                    }
+                }
+
+                self.__seqloq_lock.lock();
+                self.__seqloq_seq.write_begin();
+                let _unlock = Unlock(self);
+                f();
+            }
+        }
+
+        impl ::std::convert::From<#name> for #shared {
+            fn from(init: #name) -> #shared {
+                #shared::new(init)
+            }
+        }
+    })
+}
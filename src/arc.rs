@@ -0,0 +1,131 @@
+//! Read-copy-update for data that can't be `Copy`.
+//!
+//! `SeqloqArc` holds an `Arc<T>`.  Writers publish a whole new `Arc` by
+//! swapping a pointer; readers take a cheap reference-counted handle to
+//! whichever value is current.  Neither side blocks the other: a reader
+//! only has to bump a reference count, and a writer only waits for readers
+//! caught in the middle of doing so before it drops the old value's count.
+
+use std::sync::Arc;
+
+use crate::{RawMutex, RawGuard, WordLock};
+use crate::leftright::Readers;
+use crate::sync::{AtomicPtr, Ordering};
+
+/// An atomically replaceable `Arc<T>`.
+pub struct SeqloqArc<T, M = WordLock> {
+    mutex: M,
+    ptr: AtomicPtr<T>,
+    readers: Readers,
+}
+
+unsafe impl<T: Send + Sync, M: Send> Send for SeqloqArc<T, M> { }
+unsafe impl<T: Send + Sync, M: Sync> Sync for SeqloqArc<T, M> { }
+
+impl<T> SeqloqArc<T> {
+    #[inline]
+    pub fn new(t: T) -> SeqloqArc<T> {
+        SeqloqArc::from_arc(Arc::new(t))
+    }
+
+    #[inline]
+    pub fn from_arc(a: Arc<T>) -> SeqloqArc<T> {
+        SeqloqArc::with_mutex(a, WordLock::new())
+    }
+}
+
+impl<T, M: RawMutex> SeqloqArc<T, M> {
+    /// Use `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(a: Arc<T>, mutex: M) -> SeqloqArc<T, M> {
+        SeqloqArc {
+            mutex,
+            ptr: AtomicPtr::new(Arc::into_raw(a) as *mut T),
+            readers: Readers::new(),
+        }
+    }
+
+    /// A handle to the current value.
+    #[inline]
+    pub fn load(&self) -> Arc<T> {
+        let readers = self.readers.enter();
+        let p = self.ptr.load(Ordering::SeqCst);
+        // The writer won't release its count on `p` until we leave.
+        unsafe {
+            Arc::increment_strong_count(p);
+        }
+        readers.fetch_sub(1, Ordering::Release);
+        unsafe { Arc::from_raw(p) }
+    }
+
+    /// Publish a new value, returning the old one.
+    pub fn swap(&self, new: Arc<T>) -> Arc<T> {
+        let _guard = RawGuard::lock(&self.mutex);
+        self.swap_locked(new)
+    }
+
+    /// Publish a new value.
+    #[inline]
+    pub fn store(&self, new: Arc<T>) {
+        drop(self.swap(new));
+    }
+
+    /// Publish a value computed from the current one.
+    ///
+    /// Other writers are excluded while `f` runs, so no update is lost.
+    pub fn update<F>(&self, f: F) -> Arc<T>
+        where F: FnOnce(&T) -> T,
+    {
+        let _guard = RawGuard::lock(&self.mutex);
+        let cur = unsafe { &*self.ptr.load(Ordering::Relaxed) };
+        let new = Arc::new(f(cur));
+        drop(self.swap_locked(new.clone()));
+        new
+    }
+
+    fn swap_locked(&self, new: Arc<T>) -> Arc<T> {
+        let old = self.ptr.swap(Arc::into_raw(new) as *mut T, Ordering::SeqCst);
+        self.readers.wait_for_readers();
+        unsafe { Arc::from_raw(old) }
+    }
+}
+
+impl<T, M> Drop for SeqloqArc<T, M> {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Arc::from_raw(self.ptr.load(Ordering::Relaxed)));
+        }
+    }
+}
+
+#[test]
+fn arc() {
+    let x = SeqloqArc::new(String::from("a"));
+    let a = x.load();
+    x.store(Arc::new("b".into()));
+    assert_eq!(*a, "a");
+    assert_eq!(*x.load(), "b");
+    assert_eq!(*x.update(|s| s.clone() + "c"), "bc");
+    assert_eq!(*x.swap(Arc::new("d".into())), "bc");
+    assert_eq!(Arc::strong_count(&x.load()), 2);
+}
+
+#[test]
+fn arc_threads() {
+    let x = SeqloqArc::new(vec![0u64; 8]);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 1..=1000 {
+                x.store(Arc::new(vec![i; 8]));
+            }
+        });
+        loop {
+            let v = x.load();
+            assert!(v.iter().all(|&n| n == v[0]));
+            if v[0] == 1000 {
+                break;
+            }
+            std::thread::yield_now();
+        }
+    });
+}
@@ -0,0 +1,316 @@
+//! Command-line options.
+
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use seqloq::tests::{BenchMode, Cpus, Mix, ThreadSpec};
+
+pub const USAGE: &str = "\
+usage: bench [options]
+
+  --impl LIST           implementations to measure, comma-separated
+                        (mutex, rwlock, seqloq, seqloq-peek, and with the
+                        compare feature, parking-lot-rwlock, seqlock,
+                        atomic-cell and arc-swap; default all)
+  --mode LIST           read, write, or both (default both)
+  --payload WORDS       size of the protected data, in 64-bit words: a
+                        power of two up to 256 (default 4)
+  --samples N           samples per run (default 10000)
+  --warmup N            operations to run and discard before sampling
+                        (default 1000)
+  --trim-outliers       drop samples beyond the outlier fence (the third
+                        quartile plus three times the interquartile range,
+                        or twice the median if that's more) before
+                        summarizing and writing results; otherwise they're
+                        only counted
+  --readers N           background reader threads (default 200)
+  --reader-delay US     delay per element while checking, in us (default 2)
+  --reader-pause US     pause between reads, in us (default 0)
+  --writers N           background writer threads (default 3)
+  --writer-delay US     delay per element while writing, in us (default 2)
+  --writer-pause US     pause between writes, in us (default 2000)
+  --sleep               sleep for delays and pauses, as the OS allows,
+                        instead of spinning through those under 1 ms
+  --reader-cpus A-B     pin reader threads to CPUs A to B, round-robin, or
+                        with spread, alternately to each NUMA node
+  --writer-cpus A-B     pin writer threads likewise
+  --measure-cpu N       pin the measuring thread to CPU N; to isolate it,
+                        keep N out of the reader and writer ranges
+  --data-node N         allocate the protected data on NUMA node N, and
+                        measure from each node in turn, reporting runs
+                        as IMPL@local or IMPL@nodeM
+  --perf                count cycles, instructions, cache misses and branch
+                        mispredicts per sampled operation; needs Linux and
+                        the perf feature
+  --all-threads         time every background thread's operations as well,
+                        printing summaries by role and writing each run's
+                        samples to IMPL_MODE_threads.csv, by thread
+  --mix R:W             make the reader threads a single population which
+                        reads and writes in the ratio R:W (say 99:1), with
+                        no separate writers unless --writers is given
+  --out DIR             where to write results (default target)
+  --format FORMAT       dat, for one file of nanosecond samples per run,
+                        or json, for bench.json with parameters, summary
+                        statistics and samples for every run, or csv, for
+                        bench.csv with every sample and a header describing
+                        the machine, build and thread specs (default dat)
+  --throughput SECS     instead of timing single operations, count the
+                        reads and writes all threads complete in SECS
+                        seconds; --*-delay and --*-pause still apply
+  --sweep DIM=VALUES    repeat for each value of readers, writers,
+                        reader-pause, writer-pause, reader-delay,
+                        writer-delay or payload; VALUES is a list like
+                        1,2,3 or a range like 1..64, which doubles each
+                        step.  Writes a table per implementation, and
+                        lists where their order changes.  Sweep
+                        writer-delay to see how write hold time matters
+  --repeat K            run everything K times, and report the mean,
+                        standard deviation and 95% confidence interval of
+                        each implementation's p50 and p99, or throughput,
+                        and whether each differs significantly from the
+                        first implementation; writes repeat.csv
+  --plot                write cumulative latency curves and a gnuplot script
+                        comparing the implementations, and run gnuplot
+  --multi-process       run readers and writers as separate processes,
+                        sharing a SeqloqC of four words; --readers and
+                        --writers count processes (default 8 and 3).
+                        Only seqloq is measured, without --*-delay
+  -h, --help            show this message
+";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format { Dat, Json, Csv }
+
+/// A parameter which `--sweep` can vary.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dimension { Readers, Writers, ReaderPause, WriterPause, ReaderDelay, WriterDelay, Payload }
+
+#[derive(Clone)]
+pub struct Sweep {
+    pub dimension: Dimension,
+    pub values: Vec<u64>,
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub impls: Vec<String>,
+    pub modes: Vec<BenchMode>,
+    pub payload: u64,
+    pub samples: u64,
+    pub warmup: u64,
+    pub trim_outliers: bool,
+    pub readers: ThreadSpec,
+    pub writers: ThreadSpec,
+    pub measure_cpu: Option<usize>,
+    pub data_node: Option<usize>,
+    pub sleep: bool,
+    pub all_threads: bool,
+    pub perf: bool,
+    pub out: PathBuf,
+    pub format: Format,
+    pub throughput: Option<Duration>,
+    pub sweep: Option<Sweep>,
+    pub repeat: Option<u64>,
+    pub plot: bool,
+    pub multi_process: bool,
+}
+
+impl Options {
+    /// Parse `args`, which don't include the program name.  Exits on
+    /// `--help` or a bad option.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I, impls: &[&str]) -> Options {
+        let mut opts = Options {
+            impls: impls.iter().map(|s| s.to_string()).collect(),
+            modes: vec![BenchMode::Reader, BenchMode::Writer],
+            payload: 4,
+            samples: 10_000,
+            warmup: 1000,
+            trim_outliers: false,
+            readers: ThreadSpec { qty: 200, pause: 0, ..Default::default() },
+            writers: ThreadSpec { qty: 3, pause: 2000, ..Default::default() },
+            measure_cpu: None,
+            data_node: None,
+            sleep: false,
+            all_threads: false,
+            perf: false,
+            out: PathBuf::from("target"),
+            format: Format::Dat,
+            throughput: None,
+            sweep: None,
+            repeat: None,
+            plot: false,
+            multi_process: false,
+        };
+        let (mut readers, mut writers) = (None, None);
+        let mut chose_impls = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value", arg)));
+            match &arg[..] {
+                "--impl" => {
+                    chose_impls = true;
+                    opts.impls = list(&value());
+                    for i in &opts.impls {
+                        if !impls.contains(&&i[..]) {
+                            fail(&format!("unknown implementation {:?}", i));
+                        }
+                    }
+                }
+                "--mode" => {
+                    opts.modes = list(&value()).iter().map(|m| match &m[..] {
+                        "read" => BenchMode::Reader,
+                        "write" => BenchMode::Writer,
+                        _ => fail(&format!("unknown mode {:?}", m)),
+                    }).collect();
+                }
+                "--payload" => opts.payload = payload(&arg, &value()),
+                "--samples" => opts.samples = number(&arg, &value()),
+                "--warmup" => opts.warmup = number(&arg, &value()),
+                "--trim-outliers" => opts.trim_outliers = true,
+                "--readers" => readers = Some(number(&arg, &value())),
+                "--reader-delay" => opts.readers.delay = number(&arg, &value()),
+                "--reader-pause" => opts.readers.pause = number(&arg, &value()),
+                "--writers" => writers = Some(number(&arg, &value())),
+                "--writer-delay" => opts.writers.delay = number(&arg, &value()),
+                "--writer-pause" => opts.writers.pause = number(&arg, &value()),
+                "--sleep" => opts.sleep = true,
+                "--reader-cpus" => cpus(&mut opts.readers, &arg, &value()),
+                "--writer-cpus" => cpus(&mut opts.writers, &arg, &value()),
+                "--measure-cpu" => opts.measure_cpu = Some(number(&arg, &value())),
+                "--data-node" => opts.data_node = Some(number(&arg, &value())),
+                "--all-threads" => opts.all_threads = true,
+                "--perf" => opts.perf = true,
+                "--mix" => {
+                    let v = value();
+                    let (r, w) = v.split_once(':')
+                        .unwrap_or_else(|| fail(&format!("--mix expects R:W, not {:?}", v)));
+                    opts.readers.mix = Some(Mix { reads: number(&arg, r), writes: number(&arg, w) });
+                }
+                "--out" => opts.out = value().into(),
+                "--format" => {
+                    opts.format = match &value()[..] {
+                        "dat" => Format::Dat,
+                        "json" => Format::Json,
+                        "csv" => Format::Csv,
+                        f => fail(&format!("unknown format {:?}", f)),
+                    };
+                }
+                "--throughput" => {
+                    let secs: f64 = number(&arg, &value());
+                    opts.throughput = Some(Duration::try_from_secs_f64(secs)
+                        .unwrap_or_else(|_| fail(&format!("bad duration {}", secs))));
+                }
+                "--sweep" => {
+                    let v = value();
+                    let (dim, values) = v.split_once('=')
+                        .unwrap_or_else(|| fail(&format!("--sweep expects DIM=VALUES, not {:?}", v)));
+                    opts.sweep = Some(Sweep {
+                        dimension: Dimension::parse(dim),
+                        values: crate::sweep::values(values),
+                    });
+                }
+                "--repeat" => {
+                    let k = number(&arg, &value());
+                    if k < 2 {
+                        fail("--repeat needs at least 2 runs");
+                    }
+                    opts.repeat = Some(k);
+                }
+                "--plot" => opts.plot = true,
+                "--multi-process" => opts.multi_process = true,
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => fail(&format!("unknown option {:?}", arg)),
+            }
+        }
+
+        if opts.multi_process && (opts.throughput.is_some() || opts.readers.mix.is_some()) {
+            fail("--throughput and --mix don't work with --multi-process");
+        }
+        if opts.multi_process && opts.sweep.is_some() {
+            fail("--sweep doesn't work with --multi-process");
+        }
+        if opts.multi_process && opts.payload != 4 {
+            fail("--payload doesn't work with --multi-process");
+        }
+        if opts.multi_process && chose_impls {
+            fail("--impl doesn't work with --multi-process, which only measures SeqloqC");
+        }
+        if opts.multi_process && (opts.readers.delay != 0 || opts.writers.delay != 0) {
+            fail("--reader-delay and --writer-delay don't work with --multi-process");
+        }
+        if let Some(Sweep { dimension: Dimension::Payload, values }) = &opts.sweep {
+            for v in values {
+                payload("--sweep payload", &v.to_string());
+            }
+        }
+        if opts.all_threads && (opts.multi_process || opts.throughput.is_some()) {
+            fail("--all-threads doesn't work with --multi-process or --throughput");
+        }
+        if opts.repeat.is_some() && (opts.multi_process || opts.sweep.is_some()) {
+            fail("--repeat doesn't work with --multi-process or --sweep");
+        }
+        if opts.perf && (opts.multi_process || opts.throughput.is_some()) {
+            fail("--perf doesn't work with --multi-process or --throughput");
+        }
+        let pinned = |s: &ThreadSpec| s.cpus.is_some() || s.spread;
+        if opts.multi_process && (pinned(&opts.readers) || pinned(&opts.writers)) {
+            fail("--reader-cpus and --writer-cpus don't work with --multi-process");
+        }
+        if opts.data_node.is_some() {
+            if opts.multi_process || opts.throughput.is_some() || opts.sweep.is_some() {
+                fail("--data-node doesn't work with --multi-process, --throughput or --sweep");
+            }
+            if opts.measure_cpu.is_some() {
+                fail("--data-node chooses the measuring CPUs itself; drop --measure-cpu");
+            }
+        }
+        let (r, w) = match (opts.multi_process, opts.readers.mix) {
+            (true, _) => (8, 3),
+            (false, Some(_)) => (200, 0),
+            (false, None) => (200, 3),
+        };
+        opts.readers.qty = readers.unwrap_or(r);
+        opts.writers.qty = writers.unwrap_or(w);
+        opts
+    }
+}
+
+/// Parse `A-B`, a single CPU, or `spread`.
+fn cpus(spec: &mut ThreadSpec, opt: &str, s: &str) {
+    if s == "spread" {
+        spec.spread = true;
+        return;
+    }
+    let (a, b) = s.split_once('-').unwrap_or((s, s));
+    let (first, last): (usize, usize) = (number(opt, a), number(opt, b));
+    if last < first {
+        fail(&format!("{} expects A-B with A <= B, not {:?}", opt, s));
+    }
+    spec.cpus = Some(Cpus { first, count: last - first + 1 });
+}
+
+fn payload(opt: &str, s: &str) -> u64 {
+    let words = number(opt, s);
+    if !crate::PAYLOADS.contains(&words) {
+        fail(&format!("{} must be one of {:?}, not {}", opt, crate::PAYLOADS, words));
+    }
+    words
+}
+
+fn list(s: &str) -> Vec<String> {
+    s.split(',').filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+fn number<T: FromStr>(opt: &str, s: &str) -> T {
+    s.parse().unwrap_or_else(|_| fail(&format!("{} expects a number, not {:?}", opt, s)))
+}
+
+pub fn fail(msg: &str) -> ! {
+    eprintln!("bench: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,223 @@
+#![deny(warnings)]
+
+use std::sync::{Mutex, RwLock};
+use std::fs;
+use std::time::Duration;
+
+use seqloq::Seqloq;
+use seqloq::tests::{TestArray, BenchRequest, TestOutcome, ThreadSpec, Throughput};
+use seqloq::tests::{SeqloqPeek, numa_nodes, reader_writer_test, throughput_test};
+
+use cli::Options;
+use report::Run;
+
+mod cli;
+mod meta;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod perf;
+#[cfg(not(all(feature = "perf", target_os = "linux")))]
+#[path = "noperf.rs"]
+mod perf;
+mod plot;
+mod repeat;
+mod report;
+mod summary;
+mod sweep;
+mod table;
+#[cfg(target_os = "linux")]
+mod shm;
+
+/// An implementation to measure.
+struct Impl {
+    name: &'static str,
+    latency: fn(ThreadSpec, ThreadSpec, Option<BenchRequest<'_>>) -> TestOutcome,
+    throughput: fn(ThreadSpec, ThreadSpec, Duration) -> Throughput,
+}
+
+macro_rules! impls {
+    ($($(#[$attr:meta])* $name:literal => $mutex:ty),*) => {
+        &[$($(#[$attr])* Impl {
+            name: $name,
+            latency: reader_writer_test::<$mutex>,
+            throughput: throughput_test::<$mutex>,
+        }),*]
+    }
+}
+
+/// The implementations, protecting `N` words.
+struct Table<const N: usize>;
+
+impl<const N: usize> Table<N> {
+    const IMPLS: &'static [Impl] = impls![
+        "mutex" => Mutex<TestArray<N>>,
+        "rwlock" => RwLock<TestArray<N>>,
+        "seqloq" => Seqloq<TestArray<N>>,
+        "seqloq-peek" => SeqloqPeek<TestArray<N>>,
+        #[cfg(feature = "compare")]
+        "parking-lot-rwlock" => parking_lot::RwLock<TestArray<N>>,
+        #[cfg(feature = "compare")]
+        "seqlock" => seqlock::SeqLock<TestArray<N>>,
+        #[cfg(feature = "compare")]
+        "atomic-cell" => crossbeam_utils::atomic::AtomicCell<TestArray<N>>,
+        #[cfg(feature = "compare")]
+        "arc-swap" => arc_swap::ArcSwap<TestArray<N>>
+    ];
+}
+
+/// Payload sizes which `--payload` accepts, in words.  Each one is
+/// compiled separately.
+pub const PAYLOADS: &[u64] = &[1, 2, 4, 8, 16, 32, 64, 128, 256];
+
+fn impls(payload: u64) -> &'static [Impl] {
+    match payload {
+        1 => Table::<1>::IMPLS,
+        2 => Table::<2>::IMPLS,
+        4 => Table::<4>::IMPLS,
+        8 => Table::<8>::IMPLS,
+        16 => Table::<16>::IMPLS,
+        32 => Table::<32>::IMPLS,
+        64 => Table::<64>::IMPLS,
+        128 => Table::<128>::IMPLS,
+        256 => Table::<256>::IMPLS,
+        _ => cli::fail(&format!("payload must be one of {:?} words", PAYLOADS)),
+    }
+}
+
+fn find(name: &str, payload: u64) -> &'static Impl {
+    impls(payload).iter().find(|i| i.name == name).unwrap()
+}
+
+/// Run `name`'s `throughput_test` with `opts`' threads and payload.
+/// Torn reads are fatal, as in the latency runs.
+fn throughput(name: &str, opts: &Options, window: Duration) -> Throughput {
+    let t = (find(name, opts.payload).throughput)(opts.readers, opts.writers, window);
+    if t.failed_checks != 0 {
+        eprintln!("{} failed", name);
+    }
+    assert_eq!(t.failed_checks, 0);
+    t
+}
+
+pub fn main() {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut args = std::env::args().skip(1).peekable();
+    #[cfg(target_os = "linux")]
+    match args.peek().map(|s| &s[..]) {
+        Some("shm-reader") | Some("shm-writer") => {
+            let role = args.next().unwrap();
+            return shm::child(&args.next().unwrap(), role == "shm-writer");
+        }
+        _ => { }
+    }
+
+    let names: Vec<&str> = impls(4).iter().map(|i| i.name).collect();
+    let opts = Options::parse(args, &names);
+    seqloq::tests::set_spin_delays(!opts.sleep);
+    fs::create_dir_all(&opts.out).unwrap();
+
+    if let Some(sweep) = &opts.sweep {
+        sweep::run(&opts, sweep).unwrap();
+        return;
+    }
+
+    if let Some(k) = opts.repeat {
+        repeat::run(&opts, k).unwrap();
+        return;
+    }
+
+    if let Some(window) = opts.throughput {
+        let results: Vec<(&str, Throughput)> = opts.impls.iter()
+            .map(|name| (&name[..], throughput(name, &opts, window)))
+            .collect();
+        report::print_throughput(&results);
+        report::write_throughput(&opts, &results).unwrap();
+        table::report(&opts, &table::throughput(&results)).unwrap();
+        return;
+    }
+
+    let runs = if opts.multi_process {
+        #[cfg(target_os = "linux")]
+        { shm::bench(&opts) }
+        #[cfg(not(target_os = "linux"))]
+        cli::fail("--multi-process is only supported on Linux")
+    } else {
+        threads(&opts)
+    };
+    let runs = if opts.trim_outliers { summary::trim_outliers(runs) } else { runs };
+    report::print(&runs);
+    report::write(&opts, &runs).unwrap();
+    if opts.plot {
+        plot::plot(&opts.out, &runs).unwrap();
+    }
+    table::report(&opts, &table::latency(&runs)).unwrap();
+}
+
+/// Measure each implementation, in each mode, with threads in this process.
+fn threads(opts: &Options) -> Vec<Run> {
+    let mut runs = vec![];
+    let mut counters = opts.perf.then(|| perf::Counters::open()
+        .unwrap_or_else(|e| cli::fail(&format!("can't count perf events: {}", e))));
+    for &mode in &opts.modes {
+        for name in &opts.impls {
+            for (label, cpu, data_cpu) in placements(opts) {
+                let run = find(name, opts.payload).latency;
+                let mut samples = Vec::with_capacity(opts.samples as usize);
+                let mut threads = vec![];
+                let mut retries = vec![];
+                {
+                    let mut section = |start| if let Some(c) = &mut counters {
+                        c.section(start);
+                    };
+                    let bench = BenchRequest {
+                        mode,
+                        warmup: opts.warmup,
+                        num_samples: opts.samples,
+                        samples: &mut samples,
+                        cpu,
+                        data_cpu,
+                        thread_samples: opts.all_threads.then_some(&mut threads),
+                        retries: Some(&mut retries),
+                        around: Some(&mut section),
+                    };
+                    let outcome = run(opts.readers, opts.writers, Some(bench));
+                    if !outcome.is_ok() {
+                        eprintln!("{} failed", name);
+                    }
+                    outcome.assert_ok();
+                }
+
+                runs.push(Run {
+                    implementation: format!("{}{}", name, label),
+                    mode,
+                    readers: opts.readers,
+                    writers: opts.writers,
+                    perf: counters.as_ref().map_or(vec![], |c| c.per_op(samples.len())),
+                    samples,
+                    threads,
+                    retries,
+                });
+            }
+        }
+    }
+    runs
+}
+
+/// Where to measure from and where to put the data: a suffix for the
+/// run's name, the measuring CPU and the CPU to allocate from.  With
+/// `--data-node`, that's the first CPU of each NUMA node in turn.
+fn placements(opts: &Options) -> Vec<(String, Option<usize>, Option<usize>)> {
+    let Some(data_node) = opts.data_node else {
+        return vec![(String::new(), opts.measure_cpu, None)];
+    };
+    let nodes = numa_nodes();
+    let first = |n: usize| nodes.get(n).and_then(|cpus| cpus.first().copied());
+    let Some(data_cpu) = first(data_node) else {
+        cli::fail(&format!("there's no NUMA node {} with CPUs", data_node))
+    };
+    (0..nodes.len())
+        .filter_map(|n| {
+            let label = if n == data_node { "@local".into() } else { format!("@node{}", n) };
+            Some((label, Some(first(n)?), Some(data_cpu)))
+        })
+        .collect()
+}
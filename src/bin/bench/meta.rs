@@ -0,0 +1,59 @@
+//! Where and how a benchmark ran.
+
+use std::fs;
+use std::process::Command;
+use std::thread;
+
+/// Facts about the machine and build, each "unknown" if it can't be found.
+pub struct Metadata {
+    pub hostname: String,
+    pub cpu: String,
+    pub cores: usize,
+    pub rustc: String,
+    pub commit: String,
+}
+
+fn command(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    let s = String::from_utf8(out.stdout).ok()?;
+    (out.status.success() && !s.trim().is_empty()).then(|| s.trim().to_string())
+}
+
+fn cpu_model() -> Option<String> {
+    let info = fs::read_to_string("/proc/cpuinfo").ok()?;
+    info.lines()
+        .find(|l| l.starts_with("model name"))
+        .and_then(|l| l.split(':').nth(1))
+        .map(|m| m.trim().to_string())
+}
+
+impl Metadata {
+    pub fn collect() -> Metadata {
+        let unknown = || "unknown".to_string();
+        Metadata {
+            hostname: fs::read_to_string("/proc/sys/kernel/hostname").ok()
+                .map(|h| h.trim().to_string())
+                .or_else(|| command("hostname", &[]))
+                .unwrap_or_else(unknown),
+            cpu: cpu_model()
+                .or_else(|| command("sysctl", &["-n", "machdep.cpu.brand_string"]))
+                .unwrap_or_else(unknown),
+            cores: thread::available_parallelism().map_or(0, |n| n.get()),
+            // The toolchain on the path, which is normally the one that
+            // built this binary.
+            rustc: command("rustc", &["--version"]).unwrap_or_else(unknown),
+            commit: command("git", &["describe", "--always", "--dirty"]).unwrap_or_else(unknown),
+        }
+    }
+
+    /// `(key, value)` pairs, in a fixed order.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("hostname", self.hostname.clone()),
+            ("cpu", self.cpu.clone()),
+            ("cores", self.cores.to_string()),
+            ("rustc", self.rustc.clone()),
+            ("commit", self.commit.clone()),
+        ]
+    }
+}
@@ -0,0 +1,18 @@
+//! Stands in for `perf.rs` where hardware counters aren't supported.
+
+use std::io;
+
+pub struct Counters;
+
+impl Counters {
+    pub fn open() -> io::Result<Counters> {
+        Err(io::Error::new(io::ErrorKind::Unsupported,
+            "bench was built without the perf feature, or not for Linux"))
+    }
+
+    pub fn section(&mut self, _start: bool) { }
+
+    pub fn per_op(&self, _ops: usize) -> Vec<(&'static str, f64)> {
+        vec![]
+    }
+}
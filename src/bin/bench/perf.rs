@@ -0,0 +1,103 @@
+//! Hardware performance counters, through `perf_event_open`.
+//!
+//! With `--perf`, the measuring thread counts cycles, instructions, cache
+//! misses and branch mispredicts over the sampled section of each run, so
+//! that cache lines bouncing between readers and the writer show up as
+//! numbers.  Counters the CPU or the kernel won't provide, as in most
+//! virtual machines, are left out.  Only user-space events are counted,
+//! which an unprivileged process is allowed to do with the default
+//! `perf_event_paranoid` setting.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::FromRawFd;
+
+/// `struct perf_event_attr`, as of `PERF_ATTR_SIZE_VER0`.
+#[repr(C)]
+#[derive(Default)]
+struct Attr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const EXCLUDE_KERNEL: u64 = 1 << 5;
+const EXCLUDE_HV: u64 = 1 << 6;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// The events, by `PERF_COUNT_HW_*` number.
+const EVENTS: &[(&str, u64)] = &[
+    ("cycles", 0),
+    ("instructions", 1),
+    ("cache-misses", 3),
+    ("branch-misses", 5),
+];
+
+/// Open counters for the calling thread.
+pub struct Counters {
+    events: Vec<(&'static str, File)>,
+    start: Vec<u64>,
+    end: Vec<u64>,
+}
+
+impl Counters {
+    /// Fails if no counter at all is available.
+    pub fn open() -> io::Result<Counters> {
+        let mut events = vec![];
+        let mut error = None;
+        for &(name, config) in EVENTS {
+            let attr = Attr {
+                kind: PERF_TYPE_HARDWARE,
+                size: size_of::<Attr>() as u32,
+                config,
+                flags: EXCLUDE_KERNEL | EXCLUDE_HV,
+                ..Default::default()
+            };
+            // This thread, on any CPU, in no group.
+            let fd = unsafe {
+                libc::syscall(libc::SYS_perf_event_open, &attr, 0, -1, -1, PERF_FLAG_FD_CLOEXEC)
+            };
+            if fd < 0 {
+                error = Some(io::Error::last_os_error());
+            } else {
+                events.push((name, unsafe { File::from_raw_fd(fd as i32) }));
+            }
+        }
+        match (events.is_empty(), error) {
+            (true, Some(e)) => Err(e),
+            _ => Ok(Counters { events, start: vec![], end: vec![] }),
+        }
+    }
+
+    fn read(&mut self) -> Vec<u64> {
+        self.events.iter_mut().map(|(_, f)| {
+            let mut buf = [0; 8];
+            f.read_exact(&mut buf).map_or(0, |()| u64::from_ne_bytes(buf))
+        }).collect()
+    }
+
+    /// Start or stop counting a section, as `BenchRequest::around`.
+    pub fn section(&mut self, start: bool) {
+        let now = self.read();
+        if start {
+            self.start = now;
+        } else {
+            self.end = now;
+        }
+    }
+
+    /// Each event's count over the last section, divided by `ops`.
+    pub fn per_op(&self, ops: usize) -> Vec<(&'static str, f64)> {
+        self.events.iter().zip(self.end.iter().zip(&self.start))
+            .map(|((name, _), (&b, &a))| (*name, b.saturating_sub(a) as f64 / ops.max(1) as f64))
+            .collect()
+    }
+}
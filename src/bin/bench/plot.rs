@@ -0,0 +1,69 @@
+//! Latency charts, drawn by gnuplot.
+//!
+//! `--plot` writes a cumulative distribution for each run, and a gnuplot
+//! script which compares the implementations in each mode.  If gnuplot is
+//! installed, it's run straight away to make `latency_read.png` and
+//! `latency_write.png`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::report::Run;
+
+/// Points per curve.  Enough for a smooth line, without huge files.
+const POINTS: usize = 1000;
+
+fn cdf(out: &Path, run: &Run) -> io::Result<String> {
+    let name = format!("{}_{}.cdf", run.implementation, run.mode_name());
+    let mut sorted = run.samples.clone();
+    sorted.sort_unstable();
+    let mut f = BufWriter::new(File::create(out.join(&name))?);
+    let step = sorted.len().div_ceil(POINTS).max(1);
+    let mut points: Vec<usize> = (0..sorted.len()).step_by(step).collect();
+    // Always end on the slowest sample.
+    if points.last() != Some(&(sorted.len() - 1)) {
+        points.push(sorted.len() - 1);
+    }
+    for i in points {
+        writeln!(f, "{} {}", sorted[i], (i + 1) as f64 / sorted.len() as f64)?;
+    }
+    f.flush()?;
+    Ok(name)
+}
+
+pub fn plot(out: &Path, runs: &[Run]) -> io::Result<()> {
+    let script = out.join("latency.gp");
+    let mut gp = BufWriter::new(File::create(&script)?);
+    writeln!(gp, "set terminal pngcairo size 1000,600")?;
+    writeln!(gp, "set logscale x")?;
+    writeln!(gp, "set xlabel 'latency (ns)'")?;
+    writeln!(gp, "set ylabel 'fraction of operations'")?;
+    writeln!(gp, "set key bottom right")?;
+    writeln!(gp, "set grid")?;
+    for mode in ["read", "write"] {
+        let curves: Vec<(&Run, String)> = runs.iter()
+            .filter(|r| r.mode_name() == mode && !r.samples.is_empty())
+            .map(|r| cdf(out, r).map(|name| (r, name)))
+            .collect::<io::Result<_>>()?;
+        if curves.is_empty() {
+            continue;
+        }
+        writeln!(gp, "set title '{} latency, cumulative'", mode)?;
+        writeln!(gp, "set output 'latency_{}.png'", mode)?;
+        let plots: Vec<String> = curves.iter()
+            .map(|(r, name)| format!("'{}' using 1:2 with lines lw 2 title '{}'", name, r.implementation))
+            .collect();
+        writeln!(gp, "plot {}", plots.join(", \\\n     "))?;
+    }
+    gp.flush()?;
+    drop(gp);
+
+    match Command::new("gnuplot").arg("latency.gp").current_dir(out).status() {
+        Ok(s) if s.success() => println!("wrote {}", out.join("latency_*.png").display()),
+        _ => println!("gnuplot failed or isn't installed; run it on {} to draw the charts",
+            script.display()),
+    }
+    Ok(())
+}
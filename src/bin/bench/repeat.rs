@@ -0,0 +1,204 @@
+//! Repeating a benchmark, to tell real differences from noise.
+//!
+//! `--repeat K` runs every configuration K times.  Each run is boiled down
+//! to a few numbers, the median and 99th percentile latencies or the
+//! throughputs, and those are summarized across runs with a mean, standard
+//! deviation and 95% confidence interval.  Every implementation is then
+//! compared with the first, using Welch's t-test, and the difference is
+//! called significant if p < 0.05.  The per-run numbers go in `repeat.csv`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::cli::Options;
+use crate::summary::{self, Summary};
+use crate::{threads, throughput};
+
+/// One number per run, for one implementation.
+struct Series {
+    implementation: String,
+    metric: String,
+    values: Vec<f64>,
+}
+
+pub fn run(opts: &Options, k: u64) -> io::Result<()> {
+    let mut series: Vec<Series> = vec![];
+    let mut record = |implementation: &str, metric: String, value: f64| {
+        match series.iter_mut().find(|s| s.implementation == implementation && s.metric == metric) {
+            Some(s) => s.values.push(value),
+            None => series.push(Series {
+                implementation: implementation.into(),
+                metric,
+                values: vec![value],
+            }),
+        }
+    };
+
+    for i in 0..k {
+        eprintln!("run {} of {}", i + 1, k);
+        match opts.throughput {
+            Some(window) => for name in &opts.impls {
+                let t = throughput(name, opts, window);
+                record(name, "reads/s".into(), t.reads_per_sec());
+                record(name, "writes/s".into(), t.writes_per_sec());
+            },
+            None => {
+                let mut runs = threads(opts);
+                if opts.trim_outliers {
+                    runs = summary::trim_outliers(runs);
+                }
+                for run in &runs {
+                    let s = Summary::of(&run.samples);
+                    record(&run.implementation, format!("{} p50", run.mode_name()), s.p50 as f64);
+                    record(&run.implementation, format!("{} p99", run.mode_name()), s.p99 as f64);
+                }
+            }
+        }
+    }
+
+    print(&series, opts.throughput.is_some());
+    write(opts, &series)
+}
+
+fn print(series: &[Series], throughput: bool) {
+    println!("mean ± 95% confidence interval over {} runs", series[0].values.len());
+    for s in series {
+        let (mean, sd) = mean_sd(&s.values);
+        println!("{} {}: {:.1} ± {:.1} (sd {:.1})",
+            s.implementation, s.metric, mean, interval(&s.values), sd);
+    }
+
+    println!();
+    let mut metrics: Vec<&str> = vec![];
+    for s in series {
+        if !metrics.contains(&&s.metric[..]) {
+            metrics.push(&s.metric);
+        }
+    }
+    for metric in metrics {
+        let mut of_metric = series.iter().filter(|s| s.metric == metric);
+        let Some(base) = of_metric.next() else { continue };
+        let (base_mean, _) = mean_sd(&base.values);
+        for s in of_metric {
+            let (mean, _) = mean_sd(&s.values);
+            let p = welch(&s.values, &base.values);
+            let better = if throughput { mean > base_mean } else { mean < base_mean };
+            let verdict = match (p < 0.05, better) {
+                (false, _) => "no significant difference",
+                (true, true) => "significantly better",
+                (true, false) => "significantly worse",
+            };
+            println!("{} vs {}, {}: {:+.1} ({:+.1}%), p = {:.4}, {}",
+                s.implementation, base.implementation, metric, mean - base_mean,
+                100.0 * (mean - base_mean) / base_mean, p, verdict);
+        }
+    }
+}
+
+fn write(opts: &Options, series: &[Series]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(opts.out.join("repeat.csv"))?);
+    writeln!(out, "implementation,metric,run,value")?;
+    for s in series {
+        for (i, v) in s.values.iter().enumerate() {
+            writeln!(out, "{},{},{},{}", s.implementation, s.metric, i, v)?;
+        }
+    }
+    out.flush()
+}
+
+/// The mean and sample standard deviation.
+fn mean_sd(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    (mean, var.sqrt())
+}
+
+/// Half the width of the 95% confidence interval for the mean.
+fn interval(xs: &[f64]) -> f64 {
+    let (_, sd) = mean_sd(xs);
+    let n = xs.len() as f64;
+    t_quantile(0.975, n - 1.0) * sd / n.sqrt()
+}
+
+/// The two-sided p-value of Welch's t-test, for whether `a` and `b` have
+/// the same mean.
+fn welch(a: &[f64], b: &[f64]) -> f64 {
+    let ((ma, sa), (mb, sb)) = (mean_sd(a), mean_sd(b));
+    let (va, vb) = (sa * sa / a.len() as f64, sb * sb / b.len() as f64);
+    if va + vb == 0.0 {
+        return if ma == mb { 1.0 } else { 0.0 };
+    }
+    let t = (ma - mb) / (va + vb).sqrt();
+    let df = (va + vb).powi(2)
+        / (va * va / (a.len() - 1) as f64 + vb * vb / (b.len() - 1) as f64);
+    2.0 * (1.0 - t_cdf(t.abs(), df))
+}
+
+/// Student's t distribution with `df` degrees of freedom.
+fn t_cdf(t: f64, df: f64) -> f64 {
+    let tail = 0.5 * incomplete_beta(df / 2.0, 0.5, df / (df + t * t));
+    if t >= 0.0 { 1.0 - tail } else { tail }
+}
+
+fn t_quantile(p: f64, df: f64) -> f64 {
+    let (mut lo, mut hi) = (-1e3, 1e3);
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if t_cdf(mid, df) < p { lo = mid } else { hi = mid }
+    }
+    (lo + hi) / 2.0
+}
+
+/// The regularized incomplete beta function, I_x(a, b), by its continued
+/// fraction.
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 || x >= 1.0 {
+        return x.clamp(0.0, 1.0);
+    }
+    // The continued fraction converges quickly only on this side.
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - incomplete_beta(b, a, 1.0 - x);
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b)
+        + a * x.ln() + b * (1.0 - x).ln()).exp() / a;
+
+    // Lentz's method.
+    const TINY: f64 = 1e-300;
+    let (mut c, mut d, mut f) = (1.0, 0.0, 1.0);
+    for i in 0..300 {
+        let m = (i / 2) as f64;
+        let numerator = match i {
+            0 => 1.0,
+            _ if i % 2 == 0 => m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            _ => -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        };
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        d = 1.0 / d;
+        c = 1.0 + numerator / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        f *= c * d;
+        if (c * d - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    front * (f - 1.0)
+}
+
+/// ln Γ(x), by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 9] = [
+        0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+        771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905,
+        -0.138_571_095_265_720_1, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum: f64 = G[0] + G[1..].iter().enumerate().map(|(i, g)| g / (x + i as f64 + 1.0)).sum::<f64>();
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
@@ -0,0 +1,281 @@
+//! Writing results out.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use seqloq::tests::{BenchMode, Thread, ThreadSample, ThreadSpec, Throughput};
+
+use crate::cli::{Format, Options};
+use crate::meta::Metadata;
+use crate::summary::{self, Summary};
+
+/// The samples from measuring one implementation in one mode.
+pub struct Run {
+    pub implementation: String,
+    pub mode: BenchMode,
+    pub readers: ThreadSpec,
+    pub writers: ThreadSpec,
+    /// Nanoseconds per operation.
+    pub samples: Vec<u64>,
+    /// Every thread's samples, with `--all-threads`; otherwise empty.
+    pub threads: Vec<ThreadSample>,
+    /// How many times each sampled read retried, for implementations
+    /// which count that; otherwise empty.
+    pub retries: Vec<u32>,
+    /// Hardware events per sampled operation, with `--perf`.
+    pub perf: Vec<(&'static str, f64)>,
+}
+
+impl Run {
+    /// `cycles 120.5, cache-misses 0.3, ...`
+    fn perf_summary(&self) -> String {
+        self.perf.iter().map(|(name, n)| format!("{} {:.1}", name, n)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl Run {
+    pub fn mode_name(&self) -> &'static str {
+        Run::name_of(self.mode)
+    }
+
+    pub fn name_of(mode: BenchMode) -> &'static str {
+        match mode {
+            BenchMode::Reader => "read",
+            BenchMode::Writer => "write",
+        }
+    }
+}
+
+/// Print percentiles and a histogram for each run.
+pub fn print(runs: &[Run]) {
+    for run in runs {
+        println!("{} {}: {}", run.implementation, run.mode_name(), Summary::of(&run.samples));
+        summary::print_histogram(&run.samples);
+        summary::print_retries(&run.samples, &run.retries);
+        if !run.perf.is_empty() {
+            println!("  per op: {}", run.perf_summary());
+        }
+        for (who, mode) in [("readers", BenchMode::Reader), ("writers", BenchMode::Writer)] {
+            for op in [BenchMode::Reader, BenchMode::Writer] {
+                let ns: Vec<u64> = run.threads.iter()
+                    .filter(|s| s.mode == op && role(s.thread) == Some(mode))
+                    .map(|s| s.ns)
+                    .collect();
+                if !ns.is_empty() {
+                    println!("  {} {}: {}", who, Run::name_of(op), Summary::of(&ns));
+                }
+            }
+        }
+        println!();
+    }
+}
+
+/// Whether a background thread was spawned as a reader or a writer.
+fn role(thread: Thread) -> Option<BenchMode> {
+    match thread {
+        Thread::Measuring => None,
+        Thread::Reader(_) => Some(BenchMode::Reader),
+        Thread::Writer(_) => Some(BenchMode::Writer),
+    }
+}
+
+/// `<impl>_<mode>_threads.csv` for each run with `--all-threads`.
+fn write_threads(opts: &Options, runs: &[Run]) -> io::Result<()> {
+    for run in runs.iter().filter(|r| !r.threads.is_empty()) {
+        let name = format!("{}_{}_threads.csv", run.implementation, run.mode_name());
+        let mut out = BufWriter::new(File::create(opts.out.join(name))?);
+        writeln!(out, "thread,mode,ns")?;
+        for s in &run.threads {
+            let thread = match s.thread {
+                Thread::Measuring => "measuring".into(),
+                Thread::Reader(i) => format!("reader{}", i),
+                Thread::Writer(i) => format!("writer{}", i),
+            };
+            writeln!(out, "{},{},{}", thread, Run::name_of(s.mode), s.ns)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+pub fn write(opts: &Options, runs: &[Run]) -> io::Result<()> {
+    write_threads(opts, runs)?;
+    match opts.format {
+        Format::Dat => {
+            for run in runs {
+                let name = format!("{}_{}.dat", run.implementation, run.mode_name());
+                let mut out = BufWriter::new(File::create(opts.out.join(name))?);
+                for sample in &run.samples {
+                    writeln!(out, "{}", sample)?;
+                }
+                out.flush()?;
+                if !run.retries.is_empty() {
+                    let name = format!("{}_{}_retries.dat", run.implementation, run.mode_name());
+                    let mut out = BufWriter::new(File::create(opts.out.join(name))?);
+                    for r in &run.retries {
+                        writeln!(out, "{}", r)?;
+                    }
+                    out.flush()?;
+                }
+            }
+            Ok(())
+        }
+        Format::Json => {
+            let mut out = BufWriter::new(File::create(opts.out.join("bench.json"))?);
+            json(&mut out, runs)?;
+            out.flush()
+        }
+        Format::Csv => {
+            let mut out = BufWriter::new(File::create(opts.out.join("bench.csv"))?);
+            csv(&mut out, opts, &Metadata::collect(), runs)?;
+            out.flush()
+        }
+    }
+}
+
+fn spec(s: &ThreadSpec) -> String {
+    let mix = s.mix.map_or(String::new(), |m| format!(" mix={}:{}", m.reads, m.writes));
+    let cpus = match (s.spread, s.cpus) {
+        (true, _) => " cpus=spread".into(),
+        (false, Some(c)) => format!(" cpus={}-{}", c.first, c.first + c.count - 1),
+        (false, None) => String::new(),
+    };
+    format!("qty={} steps={} delay_us={} pause_us={}{}{}", s.qty, s.steps, s.delay, s.pause, mix, cpus)
+}
+
+/// `# key: value` lines describing the run.
+fn header<W: Write>(out: &mut W, opts: &Options, meta: &Metadata) -> io::Result<()> {
+    for (key, value) in meta.fields() {
+        writeln!(out, "# {}: {}", key, value)?;
+    }
+    writeln!(out, "# processes: {}", if opts.multi_process { "multiple" } else { "one" })?;
+    writeln!(out, "# payload: {} words", opts.payload)?;
+    writeln!(out, "# readers: {}", spec(&opts.readers))?;
+    writeln!(out, "# writers: {}", spec(&opts.writers))?;
+    writeln!(out, "# delays: {}", if opts.sleep { "sleep" } else { "spin under 1 ms" })?;
+    if let Some(cpu) = opts.measure_cpu {
+        writeln!(out, "# measuring cpu: {}", cpu)?;
+    }
+    if let Some(node) = opts.data_node {
+        writeln!(out, "# data node: {}", node)?;
+    }
+    Ok(())
+}
+
+/// Samples, one per row, after a header of `# key: value` lines.
+fn csv<W: Write>(out: &mut W, opts: &Options, meta: &Metadata, runs: &[Run]) -> io::Result<()> {
+    header(out, opts, meta)?;
+    for run in runs.iter().filter(|r| !r.perf.is_empty()) {
+        writeln!(out, "# perf per op, {} {}: {}", run.implementation, run.mode_name(), run.perf_summary())?;
+    }
+    writeln!(out, "implementation,mode,sample,ns,retries")?;
+    for run in runs {
+        for (i, sample) in run.samples.iter().enumerate() {
+            let retries = run.retries.get(i).map_or(String::new(), |r| r.to_string());
+            writeln!(out, "{},{},{},{},{}", run.implementation, run.mode_name(), i, sample, retries)?;
+        }
+    }
+    Ok(())
+}
+
+fn json_spec<W: Write>(out: &mut W, spec: &ThreadSpec) -> io::Result<()> {
+    write!(out, "{{\"qty\": {}, \"steps\": {}, \"delay_us\": {}, \"pause_us\": {}",
+        spec.qty, spec.steps, spec.delay, spec.pause)?;
+    if let Some(mix) = spec.mix {
+        write!(out, ", \"mix\": {{\"reads\": {}, \"writes\": {}}}", mix.reads, mix.writes)?;
+    }
+    if spec.spread {
+        write!(out, ", \"cpus\": \"spread\"")?;
+    } else if let Some(cpus) = spec.cpus {
+        write!(out, ", \"cpus\": [{}, {}]", cpus.first, cpus.first + cpus.count - 1)?;
+    }
+    write!(out, "}}")
+}
+
+fn json<W: Write>(out: &mut W, runs: &[Run]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, run) in runs.iter().enumerate() {
+        let s = Summary::of(&run.samples);
+        writeln!(out, "  {{")?;
+        writeln!(out, "    \"implementation\": \"{}\",", run.implementation)?;
+        writeln!(out, "    \"mode\": \"{}\",", run.mode_name())?;
+        write!(out, "    \"readers\": ")?;
+        json_spec(out, &run.readers)?;
+        write!(out, ",\n    \"writers\": ")?;
+        json_spec(out, &run.writers)?;
+        writeln!(out, ",")?;
+        writeln!(out, "    \"summary_ns\": {{\"count\": {}, \"min\": {}, \"max\": {}, \
+            \"mean\": {:.1}, \"median\": {}, \"p95\": {}, \"p99\": {}, \"p999\": {}, \"outliers\": {}}},",
+            s.count, s.min, s.max, s.mean, s.p50, s.p95, s.p99, s.p999, s.outliers)?;
+        if !run.perf.is_empty() {
+            let events: Vec<String> = run.perf.iter()
+                .map(|(name, n)| format!("\"{}\": {:.3}", name, n))
+                .collect();
+            writeln!(out, "    \"perf_per_op\": {{{}}},", events.join(", "))?;
+        }
+        write!(out, "    \"samples_ns\": [")?;
+        for (j, sample) in run.samples.iter().enumerate() {
+            if j > 0 {
+                write!(out, ", ")?;
+            }
+            write!(out, "{}", sample)?;
+        }
+        write!(out, "]")?;
+        if !run.retries.is_empty() {
+            write!(out, ",\n    \"retries\": [")?;
+            for (j, r) in run.retries.iter().enumerate() {
+                if j > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{}", r)?;
+            }
+            write!(out, "]")?;
+        }
+        writeln!(out)?;
+        writeln!(out, "  }}{}", if i + 1 < runs.len() { "," } else { "" })?;
+    }
+    writeln!(out, "]")
+}
+
+pub fn print_throughput(results: &[(&str, Throughput)]) {
+    for (name, t) in results {
+        println!("{}: {:.0} reads/s, {:.0} writes/s", name, t.reads_per_sec(), t.writes_per_sec());
+    }
+}
+
+pub fn write_throughput(opts: &Options, results: &[(&str, Throughput)]) -> io::Result<()> {
+    let name = match opts.format {
+        Format::Dat => "throughput.dat",
+        Format::Json => "throughput.json",
+        Format::Csv => "throughput.csv",
+    };
+    let mut out = BufWriter::new(File::create(opts.out.join(name))?);
+    match opts.format {
+        Format::Dat => {
+            for (name, t) in results {
+                writeln!(out, "{} {:.0} {:.0}", name, t.reads_per_sec(), t.writes_per_sec())?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, (name, t)) in results.iter().enumerate() {
+                write!(out, "  {{\"implementation\": \"{}\", \"readers\": ", name)?;
+                json_spec(&mut out, &opts.readers)?;
+                write!(out, ", \"writers\": ")?;
+                json_spec(&mut out, &opts.writers)?;
+                write!(out, ", \"seconds\": {:.3}, \"reads\": {}, \"writes\": {}}}",
+                    t.elapsed.as_secs_f64(), t.reads, t.writes)?;
+                writeln!(out, "{}", if i + 1 < results.len() { "," } else { "" })?;
+            }
+            writeln!(out, "]")?;
+        }
+        Format::Csv => {
+            header(&mut out, opts, &Metadata::collect())?;
+            writeln!(out, "implementation,seconds,reads,writes")?;
+            for (name, t) in results {
+                writeln!(out, "{},{:.3},{},{}", name, t.elapsed.as_secs_f64(), t.reads, t.writes)?;
+            }
+        }
+    }
+    out.flush()
+}
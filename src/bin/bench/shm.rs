@@ -0,0 +1,140 @@
+//! Readers and writers in separate processes, sharing a `SeqloqC` through
+//! a memory-mapped file.  Run with `--multi-process`; the children are this
+//! same binary, run as `shm-reader` or `shm-writer`.
+
+use std::fs::OpenOptions;
+use std::os::fd::AsRawFd;
+use std::process::{Child, Command};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use seqloq::{SeqloqC, shm};
+use seqloq::tests::BenchMode;
+
+use crate::cli::Options;
+use crate::report::Run;
+
+/// Everything the processes share.
+#[repr(C)]
+struct Region {
+    seqloq: SeqloqC<[u64; 4]>,
+    shutdown: AtomicU32,
+    failed_checks: AtomicU64,
+    /// Pauses between operations, in microseconds.
+    reader_pause: AtomicU64,
+    writer_pause: AtomicU64,
+    /// Whether to sleep through the pauses instead of spinning.
+    sleep: AtomicU32,
+}
+
+const LEN: usize = std::mem::size_of::<Region>();
+
+fn map(path: &str, create: bool) -> *mut u8 {
+    let file = OpenOptions::new().read(true).write(true).create(create)
+        .truncate(create).open(path).unwrap();
+    file.set_len(LEN as u64).unwrap();
+    let p = unsafe {
+        libc::mmap(ptr::null_mut(), LEN, libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED, file.as_raw_fd(), 0)
+    };
+    assert_ne!(p, libc::MAP_FAILED);
+    p as *mut u8
+}
+
+fn check(v: &[u64; 4]) -> bool {
+    v.iter().all(|&e| e == v[0])
+}
+
+fn pause(us: &AtomicU64) {
+    seqloq::tests::delay(Duration::from_micros(us.load(Ordering::Relaxed)));
+}
+
+pub fn child(path: &str, writer: bool) {
+    let p = map(path, false);
+    let region = unsafe { &*(p as *const Region) };
+    let seqloq = unsafe { shm::attach::<[u64; 4]>(p, LEN) }.unwrap();
+    seqloq::tests::set_spin_delays(region.sleep.load(Ordering::SeqCst) == 0);
+    while region.shutdown.load(Ordering::SeqCst) == 0 {
+        if writer {
+            seqloq.update(|v| v.iter_mut().for_each(|e| *e += 1));
+            pause(&region.writer_pause);
+        } else {
+            if !check(&seqloq.read()) {
+                region.failed_checks.fetch_add(1, Ordering::SeqCst);
+            }
+            pause(&region.reader_pause);
+        }
+    }
+}
+
+pub fn bench(opts: &Options) -> Vec<Run> {
+    let path = opts.out.join("seqloq-bench.shm");
+    let path = path.to_str().unwrap();
+    let p = map(path, true);
+    let seqloq = unsafe { shm::from_mmap(p, LEN, [0u64; 4]) }.unwrap();
+    let region = unsafe { &*(p as *const Region) };
+    region.shutdown.store(0, Ordering::SeqCst);
+    region.failed_checks.store(0, Ordering::SeqCst);
+    region.reader_pause.store(opts.readers.pause, Ordering::SeqCst);
+    region.writer_pause.store(opts.writers.pause, Ordering::SeqCst);
+    region.sleep.store(opts.sleep as u32, Ordering::SeqCst);
+
+    let exe = std::env::current_exe().unwrap();
+    let spawn = |role: &str| -> Child {
+        Command::new(&exe).arg(role).arg(path).spawn().unwrap()
+    };
+    let mut children: Vec<Child> = (0..opts.readers.qty).map(|_| spawn("shm-reader"))
+        .chain((0..opts.writers.qty).map(|_| spawn("shm-writer")))
+        .collect();
+
+    if let Some(cpu) = opts.measure_cpu {
+        assert!(seqloq::tests::pin_to_cpu(cpu), "failed to pin to CPU {}", cpu);
+    }
+    let mut reads = Vec::with_capacity(opts.samples as usize);
+    let mut writes = Vec::with_capacity(opts.samples as usize);
+    let samples = |mode| if opts.modes.contains(&mode) { opts.warmup + opts.samples } else { 0 };
+    for i in 0..samples(BenchMode::Reader) {
+        let t0 = Instant::now();
+        let v = seqloq.read();
+        if i >= opts.warmup {
+            reads.push(t0.elapsed().as_nanos() as u64);
+        }
+        assert!(check(&v));
+        pause(&region.reader_pause);
+    }
+    for i in 0..samples(BenchMode::Writer) {
+        let t0 = Instant::now();
+        seqloq.update(|v| v.iter_mut().for_each(|e| *e += 1));
+        if i >= opts.warmup {
+            writes.push(t0.elapsed().as_nanos() as u64);
+        }
+        pause(&region.writer_pause);
+    }
+
+    region.shutdown.store(1, Ordering::SeqCst);
+    for c in children.iter_mut() {
+        assert!(c.wait().unwrap().success());
+    }
+    assert_eq!(region.failed_checks.load(Ordering::SeqCst), 0);
+
+    unsafe { libc::munmap(p as *mut _, LEN) };
+    let _ = std::fs::remove_file(path);
+
+    let mut runs = vec![];
+    for (mode, samples) in [(BenchMode::Reader, reads), (BenchMode::Writer, writes)] {
+        if !samples.is_empty() {
+            runs.push(Run {
+                implementation: "seqloq-shm".into(),
+                mode,
+                readers: opts.readers,
+                writers: opts.writers,
+                samples,
+                threads: vec![],
+                retries: vec![],
+                perf: vec![],
+            });
+        }
+    }
+    runs
+}
@@ -0,0 +1,140 @@
+//! Percentiles and histograms.
+
+use std::fmt;
+
+use crate::report::Run;
+
+/// Summary statistics, in nanoseconds.
+pub struct Summary {
+    pub count: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub p999: u64,
+    /// Samples beyond the outlier fence.
+    pub outliers: usize,
+}
+
+/// The nearest-rank percentile `q` (0 to 1) of sorted samples.
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// The outlier fence: Tukey's fence for far-out values, the third quartile
+/// plus three times the interquartile range, but at least twice the median.
+/// Fast operations are timed to within a few nanoseconds, so without the
+/// floor a sample 10% above the median can count as an outlier.
+fn fence(sorted: &[u64]) -> u64 {
+    let (q1, q3) = (percentile(sorted, 0.25), percentile(sorted, 0.75));
+    q3.saturating_add(3 * (q3 - q1)).max(2 * percentile(sorted, 0.5))
+}
+
+/// Drop each run's samples beyond the outlier fence.
+pub fn trim_outliers(mut runs: Vec<Run>) -> Vec<Run> {
+    for run in &mut runs {
+        let mut sorted = run.samples.clone();
+        sorted.sort_unstable();
+        let fence = fence(&sorted);
+        if !run.retries.is_empty() {
+            run.retries = run.samples.iter().zip(&run.retries)
+                .filter(|&(&s, _)| s <= fence)
+                .map(|(_, &r)| r)
+                .collect();
+        }
+        run.samples.retain(|&s| s <= fence);
+    }
+    runs
+}
+
+impl Summary {
+    pub fn of(samples: &[u64]) -> Summary {
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let n = sorted.len().max(1);
+        let fence = fence(&sorted);
+        Summary {
+            count: sorted.len(),
+            min: sorted.first().copied().unwrap_or(0),
+            max: sorted.last().copied().unwrap_or(0),
+            mean: sorted.iter().map(|&s| s as f64).sum::<f64>() / n as f64,
+            p50: percentile(&sorted, 0.50),
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+            p999: percentile(&sorted, 0.999),
+            outliers: sorted.iter().rev().take_while(|&&s| s > fence).count(),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "min {}  p50 {}  p95 {}  p99 {}  p99.9 {}  max {}  (ns, {} samples, {} outliers)",
+            self.min, self.p50, self.p95, self.p99, self.p999, self.max, self.count, self.outliers)
+    }
+}
+
+/// Bits of each value kept when bucketing, as in an HDR histogram with
+/// two significant binary digits: every bucket is a quarter of a power of
+/// two wide, so the relative error is the same at every scale.
+const PRECISION: u32 = 2;
+
+/// The lowest value in `v`'s bucket.
+fn bucket(v: u64) -> u64 {
+    let bits = 64 - v.leading_zeros();
+    if bits <= PRECISION + 1 {
+        return v;
+    }
+    let shift = bits - PRECISION - 1;
+    (v >> shift) << shift
+}
+
+/// A log-linear histogram, as `(lowest value, count)` for each non-empty
+/// bucket, in order.
+pub fn histogram(samples: &[u64]) -> Vec<(u64, usize)> {
+    let mut sorted: Vec<u64> = samples.iter().map(|&s| bucket(s)).collect();
+    sorted.sort_unstable();
+    let mut buckets: Vec<(u64, usize)> = vec![];
+    for b in sorted {
+        match buckets.last_mut() {
+            Some((last, n)) if *last == b => *n += 1,
+            _ => buckets.push((b, 1)),
+        }
+    }
+    buckets
+}
+
+/// For each number of retries, how many reads needed that many and their
+/// median latency, which tells slow reads that retried apart from reads
+/// that stalled.
+pub fn print_retries(samples: &[u64], retries: &[u32]) {
+    let mut by_count: Vec<(u32, Vec<u64>)> = vec![];
+    for (&s, &r) in samples.iter().zip(retries) {
+        match by_count.iter_mut().find(|(n, _)| *n == r) {
+            Some((_, v)) => v.push(s),
+            None => by_count.push((r, vec![s])),
+        }
+    }
+    by_count.sort_unstable_by_key(|&(n, _)| n);
+    for (n, mut v) in by_count {
+        v.sort_unstable();
+        println!("  {:>4} retries: {:>8} reads, p50 {} ns", n, v.len(), v[v.len() / 2]);
+    }
+}
+
+/// Print a histogram, with bars scaled to the biggest bucket.
+pub fn print_histogram(samples: &[u64]) {
+    const WIDTH: usize = 50;
+    let buckets = histogram(samples);
+    let most = buckets.iter().map(|&(_, n)| n).max().unwrap_or(1);
+    for (lo, n) in buckets {
+        let bar = (n * WIDTH).div_ceil(most);
+        println!("  {:>10} ns  {:<width$} {}", lo, "#".repeat(bar), n, width = WIDTH);
+    }
+}
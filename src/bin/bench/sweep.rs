@@ -0,0 +1,212 @@
+//! Measuring the same thing across a range of one parameter.
+//!
+//! `--sweep readers=1..64` runs the benchmark with 1, 2, 4, ... 64 reader
+//! threads, and writes one table per implementation, with a row per
+//! value: `sweep_readers_seqloq.dat` and so on.  With `--plot`, gnuplot
+//! draws them.
+//!
+//! `--sweep writer-delay=0..64` varies how long writers hold the lock,
+//! which is where seqlocks and rwlocks trade places: readers of a seqlock
+//! retry for as long as a write takes, while an rwlock's readers just wait
+//! their turn.  After the tables, the points where one implementation
+//! overtakes another are listed for each column.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::Command;
+
+use crate::cli::{Dimension, Options, Sweep, fail};
+use crate::report::Run;
+use crate::summary::{self, Summary};
+use crate::{threads, throughput};
+
+impl Dimension {
+    pub fn parse(s: &str) -> Dimension {
+        match s {
+            "readers" => Dimension::Readers,
+            "writers" => Dimension::Writers,
+            "reader-pause" => Dimension::ReaderPause,
+            "writer-pause" => Dimension::WriterPause,
+            "reader-delay" => Dimension::ReaderDelay,
+            "writer-delay" => Dimension::WriterDelay,
+            "payload" => Dimension::Payload,
+            _ => fail(&format!("can't sweep {:?}", s)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dimension::Readers => "readers",
+            Dimension::Writers => "writers",
+            Dimension::ReaderPause => "reader-pause",
+            Dimension::WriterPause => "writer-pause",
+            Dimension::ReaderDelay => "reader-delay",
+            Dimension::WriterDelay => "writer-delay",
+            Dimension::Payload => "payload",
+        }
+    }
+
+    fn set(self, opts: &mut Options, v: u64) {
+        match self {
+            Dimension::Readers => opts.readers.qty = v,
+            Dimension::Writers => opts.writers.qty = v,
+            Dimension::ReaderPause => opts.readers.pause = v,
+            Dimension::WriterPause => opts.writers.pause = v,
+            Dimension::ReaderDelay => opts.readers.delay = v,
+            Dimension::WriterDelay => opts.writers.delay = v,
+            Dimension::Payload => opts.payload = v,
+        }
+    }
+}
+
+/// Parse a comma-separated list of values, or `A..B` for powers of two
+/// times `A`, up to `B`.
+pub fn values(s: &str) -> Vec<u64> {
+    let number = |n: &str| n.parse::<u64>()
+        .unwrap_or_else(|_| fail(&format!("bad sweep value {:?}", n)));
+    match s.split_once("..") {
+        Some((a, b)) => {
+            let (mut v, b) = (number(a), number(b));
+            let mut vs = vec![];
+            while v <= b {
+                vs.push(v);
+                v = if v == 0 { 1 } else { v * 2 };
+            }
+            vs
+        }
+        None => s.split(',').map(number).collect(),
+    }
+}
+
+/// One row of a table: the swept value, then the columns.
+type Row = (u64, Vec<f64>);
+
+pub fn run(opts: &Options, sweep: &Sweep) -> io::Result<()> {
+    let columns: Vec<String> = match opts.throughput {
+        Some(_) => vec!["reads/s".into(), "writes/s".into()],
+        None => opts.modes.iter()
+            .flat_map(|&m| {
+                let m = Run::name_of(m);
+                [format!("{}_p50", m), format!("{}_p99", m), format!("{}_p999", m)]
+            })
+            .collect(),
+    };
+
+    let mut tables: Vec<(String, Vec<Row>)> =
+        opts.impls.iter().map(|i| (i.clone(), vec![])).collect();
+    for &v in &sweep.values {
+        let mut point = opts.clone();
+        sweep.dimension.set(&mut point, v);
+        eprintln!("{} = {}", sweep.dimension.name(), v);
+        for (name, rows) in tables.iter_mut() {
+            let cols = match opts.throughput {
+                Some(window) => {
+                    let t = throughput(name, &point, window);
+                    vec![t.reads_per_sec(), t.writes_per_sec()]
+                }
+                None => {
+                    point.impls = vec![name.clone()];
+                    let mut runs = threads(&point);
+                    if opts.trim_outliers {
+                        runs = summary::trim_outliers(runs);
+                    }
+                    runs.iter()
+                        .flat_map(|r| {
+                            let s = Summary::of(&r.samples);
+                            [s.p50 as f64, s.p99 as f64, s.p999 as f64]
+                        })
+                        .collect()
+                }
+            };
+            rows.push((v, cols));
+        }
+    }
+
+    for (name, rows) in &tables {
+        println!("{}", name);
+        println!("  {:>12} {}", sweep.dimension.name(),
+            columns.iter().map(|c| format!("{:>14}", c)).collect::<String>());
+        for (v, cols) in rows {
+            println!("  {:>12} {}", v, cols.iter().map(|c| format!("{:>14.0}", c)).collect::<String>());
+        }
+        println!();
+
+        let path = opts.out.join(format!("sweep_{}_{}.dat", sweep.dimension.name(), name));
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# {} {}", sweep.dimension.name(), columns.join(" "))?;
+        for (v, cols) in rows {
+            let cols: Vec<String> = cols.iter().map(|c| format!("{:.0}", c)).collect();
+            writeln!(out, "{} {}", v, cols.join(" "))?;
+        }
+        out.flush()?;
+    }
+
+    crossovers(sweep, &columns, &tables, opts.throughput.is_some());
+    if opts.plot {
+        plot(opts, sweep, &columns)?;
+    }
+    Ok(())
+}
+
+/// Print where each implementation overtakes or falls behind each other
+/// one, column by column.  Lower is better, except for throughput.
+fn crossovers(sweep: &Sweep, columns: &[String], tables: &[(String, Vec<Row>)], throughput: bool) {
+    let better = |a: f64, b: f64| if throughput { a > b } else { a < b };
+    let mut any = false;
+    for (c, column) in columns.iter().enumerate() {
+        for (i, (a, a_rows)) in tables.iter().enumerate() {
+            for (b, b_rows) in &tables[i + 1..] {
+                let mut ahead = None;
+                for ((v, a_cols), (_, b_cols)) in a_rows.iter().zip(b_rows) {
+                    let (x, y) = (a_cols[c], b_cols[c]);
+                    if x == y {
+                        continue;
+                    }
+                    let now = better(x, y);
+                    if ahead.is_some_and(|was| was != now) {
+                        let (winner, loser) = if now { (a, b) } else { (b, a) };
+                        println!("{}: {} overtakes {} at {} = {}",
+                            column, winner, loser, sweep.dimension.name(), v);
+                        any = true;
+                    }
+                    ahead = Some(now);
+                }
+            }
+        }
+    }
+    if !any {
+        println!("no crossovers: the implementations stay in the same order throughout");
+    }
+}
+
+/// A chart per column, with a line per implementation.
+fn plot(opts: &Options, sweep: &Sweep, columns: &[String]) -> io::Result<()> {
+    let dim = sweep.dimension.name();
+    let script = opts.out.join(format!("sweep_{}.gp", dim));
+    let mut gp = BufWriter::new(File::create(&script)?);
+    writeln!(gp, "set terminal pngcairo size 1000,600")?;
+    writeln!(gp, "set xlabel '{}'", dim)?;
+    writeln!(gp, "set key top left")?;
+    writeln!(gp, "set grid")?;
+    for (i, col) in columns.iter().enumerate() {
+        let file = col.replace('/', "_per_");
+        writeln!(gp, "set title '{} by {}'", col, dim)?;
+        writeln!(gp, "set ylabel '{}'", if opts.throughput.is_some() { "operations/s" } else { "ns" })?;
+        writeln!(gp, "set output 'sweep_{}_{}.png'", dim, file)?;
+        let plots: Vec<String> = opts.impls.iter()
+            .map(|name| format!("'sweep_{}_{}.dat' using 1:{} with linespoints lw 2 title '{}'",
+                dim, name, i + 2, name))
+            .collect();
+        writeln!(gp, "plot {}", plots.join(", \\\n     "))?;
+    }
+    gp.flush()?;
+    drop(gp);
+
+    let script_name = script.file_name().unwrap();
+    match Command::new("gnuplot").arg(script_name).current_dir(&opts.out).status() {
+        Ok(s) if s.success() => println!("wrote {}", opts.out.join(format!("sweep_{}_*.png", dim)).display()),
+        _ => println!("gnuplot failed or isn't installed; run it on {} to draw the charts",
+            script.display()),
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! A side-by-side comparison of the implementations, as a Markdown table.
+//!
+//! Each cell gives a result and its ratio to the baseline, which is
+//! `mutex` if it was measured and the first implementation otherwise.
+//! For latency, a ratio below 1 is better; for throughput, above.  The
+//! table is printed at the end of a run and written to `report.md`.
+
+use std::fs;
+use std::io;
+
+use seqloq::tests::Throughput;
+
+use crate::cli::Options;
+use crate::report::Run;
+use crate::summary::Summary;
+
+pub fn latency(runs: &[Run]) -> String {
+    let mut names: Vec<&str> = vec![];
+    let mut modes: Vec<&str> = vec![];
+    for run in runs {
+        if !names.contains(&&run.implementation[..]) {
+            names.push(&run.implementation);
+        }
+        if !modes.contains(&run.mode_name()) {
+            modes.push(run.mode_name());
+        }
+    }
+    let base = baseline(&names);
+
+    let mut header = vec!["implementation".to_string()];
+    for mode in &modes {
+        header.push(format!("{} p50", mode));
+        header.push(format!("{} p99", mode));
+    }
+    let summary = |name: &str, mode: &str| runs.iter()
+        .find(|r| r.implementation == name && r.mode_name() == mode)
+        .map(|r| Summary::of(&r.samples));
+    let rows = names.iter().map(|&name| {
+        let mut row = vec![name.to_string()];
+        for mode in &modes {
+            let (s, b) = (summary(name, mode), summary(base, mode));
+            let (s, b) = (s.as_ref(), b.as_ref());
+            row.push(cell(s.map(|s| s.p50 as f64), b.map(|b| b.p50 as f64), name == base, "ns"));
+            row.push(cell(s.map(|s| s.p99 as f64), b.map(|b| b.p99 as f64), name == base, "ns"));
+        }
+        row
+    }).collect();
+    markdown(header, rows)
+}
+
+pub fn throughput(results: &[(&str, Throughput)]) -> String {
+    let names: Vec<&str> = results.iter().map(|&(n, _)| n).collect();
+    let base = baseline(&names);
+    let of = |name: &str| results.iter().find(|&&(n, _)| n == name).map(|(_, t)| t);
+    let header = vec!["implementation".into(), "reads/s".into(), "writes/s".into()];
+    let rows = results.iter().map(|&(name, t)| {
+        let b = of(base);
+        vec![
+            name.to_string(),
+            cell(Some(t.reads_per_sec()), b.map(|b| b.reads_per_sec()), name == base, ""),
+            cell(Some(t.writes_per_sec()), b.map(|b| b.writes_per_sec()), name == base, ""),
+        ]
+    }).collect();
+    markdown(header, rows)
+}
+
+/// Print the table and write it to `report.md`.
+pub fn report(opts: &Options, table: &str) -> io::Result<()> {
+    println!("{}", table);
+    fs::write(opts.out.join("report.md"), table)
+}
+
+fn baseline<'a>(names: &[&'a str]) -> &'a str {
+    names.iter().copied().find(|&n| n == "mutex").or(names.first().copied()).unwrap_or("")
+}
+
+/// `39 ns (0.75×)`, or just `52 ns` for the baseline.
+fn cell(value: Option<f64>, base: Option<f64>, is_base: bool, unit: &str) -> String {
+    let Some(v) = value else { return "-".into() };
+    let unit = if unit.is_empty() { String::new() } else { format!(" {}", unit) };
+    match base {
+        Some(b) if !is_base && b > 0.0 => format!("{:.0}{} ({:.2}×)", v, unit, v / b),
+        _ => format!("{:.0}{}", v, unit),
+    }
+}
+
+/// Columns after the first are right-aligned, and padded so that the
+/// table reads well in a terminal too.
+fn markdown(header: Vec<String>, rows: Vec<Vec<String>>) -> String {
+    let width = |i: usize| rows.iter().map(|r| r[i].chars().count())
+        .chain([header[i].chars().count(), 3])
+        .max().unwrap();
+    let widths: Vec<usize> = (0..header.len()).map(width).collect();
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().zip(&widths).enumerate()
+            .map(|(i, (c, &w))| if i == 0 { format!("{:<w$}", c) } else { format!("{:>w$}", c) })
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut out = line(&header);
+    let rule: Vec<String> = widths.iter().enumerate()
+        .map(|(i, &w)| if i == 0 { "-".repeat(w) } else { format!("{}:", "-".repeat(w - 1)) })
+        .collect();
+    out += &format!("| {} |\n", rule.join(" | "));
+    for row in &rows {
+        out += &line(row);
+    }
+    out
+}
@@ -0,0 +1,179 @@
+//! A soak test: `reader_writer_test` over and over, for hours, to shake
+//! out rare torn reads on new architectures.
+//!
+//! Each round runs one implementation with one payload size and one mix
+//! of thread counts, cycling through all of them.  Payloads carry a
+//! checksum, so any torn read is caught.  Every so often a line of health
+//! statistics is printed; on the first failure, the round's settings and
+//! seed are printed and the process exits with status 1.
+
+#![deny(warnings)]
+
+use std::process;
+use std::time::{Duration, Instant};
+
+use seqloq::Seqloq;
+use seqloq::tests::{Checksummed, SeqloqPeek, TestOutcome, ThreadSpec, reader_writer_test};
+
+const USAGE: &str = "\
+usage: soak [options]
+
+  --duration TIME       how long to run, like 8h, 30m or 90s (default 8h)
+  --report TIME         how often to print health statistics (default 1m)
+  --steps N             operations per thread per round (default 1000)
+  --delay US            delay per word while reading or writing, in us
+                        (default 0)
+  --pause US            mean pause between operations, in us (default 10)
+  -h, --help            show this message
+";
+
+/// An implementation and payload size to soak.
+struct Target {
+    name: &'static str,
+    words: usize,
+    run: fn(ThreadSpec, ThreadSpec) -> TestOutcome,
+}
+
+macro_rules! targets {
+    ($($name:literal => $mutex:ident, [$($words:literal),*]);*) => {
+        &[$($(Target {
+            name: $name,
+            words: $words,
+            run: |r, w| reader_writer_test::<$mutex<Checksummed<$words>>>(r, w, None),
+        }),*),*]
+    }
+}
+
+const TARGETS: &[Target] = targets![
+    "seqloq" => Seqloq, [1, 4, 16, 64, 256];
+    "seqloq-peek" => SeqloqPeek, [1, 4, 16, 64, 256]
+];
+
+const READERS: &[u64] = &[1, 2, 4, 8, 16, 32];
+const WRITERS: &[u64] = &[1, 2, 4];
+
+struct Options {
+    duration: Duration,
+    report: Duration,
+    steps: u64,
+    delay: u64,
+    pause: u64,
+}
+
+/// Totals since the start.
+#[derive(Default)]
+struct Health {
+    rounds: u64,
+    reads: u64,
+    writes: u64,
+}
+
+fn main() {
+    let opts = parse(std::env::args().skip(1));
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut health = Health::default();
+    println!("soaking for {}, reporting every {}",
+        show(opts.duration), show(opts.report));
+
+    while start.elapsed() < opts.duration {
+        let i = health.rounds as usize;
+        let target = &TARGETS[i % TARGETS.len()];
+        let readers = READERS[i / TARGETS.len() % READERS.len()];
+        let writers = WRITERS[i / (TARGETS.len() * READERS.len()) % WRITERS.len()];
+        let spec = |qty| ThreadSpec {
+            qty,
+            steps: opts.steps,
+            delay: opts.delay,
+            pause: opts.pause,
+            jitter: true,
+            ..Default::default()
+        };
+
+        let outcome = (target.run)(spec(readers), spec(writers));
+        health.rounds += 1;
+        for t in &outcome.per_thread_stats {
+            health.reads += t.reads;
+            health.writes += t.writes;
+        }
+        if !outcome.is_ok() {
+            eprintln!("FAILED after {}, in round {}: {}, {} words, {} readers, {} writers",
+                show(start.elapsed()), health.rounds, target.name, target.words, readers, writers);
+            eprintln!("{} torn reads", outcome.failed_checks);
+            for p in &outcome.panics {
+                eprintln!("{:?} thread panicked: {}", p.thread, p.message);
+            }
+            eprintln!("re-run the round with SEQLOQ_SEED={}", outcome.seed);
+            process::exit(1);
+        }
+
+        if last_report.elapsed() >= opts.report {
+            last_report = Instant::now();
+            let secs = start.elapsed().as_secs_f64();
+            println!("[{}] {} rounds, {} reads ({:.0}/s), {} writes ({:.0}/s), no torn reads",
+                show(start.elapsed()), health.rounds, health.reads, health.reads as f64 / secs,
+                health.writes, health.writes as f64 / secs);
+        }
+    }
+    println!("done: {} rounds, {} reads, {} writes, no torn reads",
+        health.rounds, health.reads, health.writes);
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Options {
+    let mut opts = Options {
+        duration: Duration::from_secs(8 * 3600),
+        report: Duration::from_secs(60),
+        steps: 1000,
+        delay: 0,
+        pause: 10,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value", arg)));
+        match &arg[..] {
+            "--duration" => opts.duration = time(&arg, &value()),
+            "--report" => opts.report = time(&arg, &value()),
+            "--steps" => opts.steps = number(&arg, &value()),
+            "--delay" => opts.delay = number(&arg, &value()),
+            "--pause" => opts.pause = number(&arg, &value()),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => fail(&format!("unknown option {:?}", arg)),
+        }
+    }
+    opts
+}
+
+/// Parse a number with an `h`, `m` or `s` suffix.
+fn time(opt: &str, s: &str) -> Duration {
+    let (n, unit) = s.split_at(s.len().saturating_sub(1));
+    let scale = match unit {
+        "h" => 3600.0,
+        "m" => 60.0,
+        "s" => 1.0,
+        _ => fail(&format!("{} expects a time like 8h, 30m or 90s, not {:?}", opt, s)),
+    };
+    let n: f64 = number(opt, n);
+    Duration::try_from_secs_f64(n * scale)
+        .unwrap_or_else(|_| fail(&format!("bad time {:?}", s)))
+}
+
+/// `1h02m03s`, or less, for shorter times.
+fn show(d: Duration) -> String {
+    let s = d.as_secs();
+    match (s / 3600, s / 60 % 60, s % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+fn number<T: std::str::FromStr>(opt: &str, s: &str) -> T {
+    s.parse().unwrap_or_else(|_| fail(&format!("{} expects a number, not {:?}", opt, s)))
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("soak: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,128 @@
+//! Seqlocks with the payload on the heap.
+//!
+//! A `Seqloq<T>` is as big as `T`, which is a problem when it sits in the
+//! middle of a densely packed struct and `T` is a few kilobytes.
+//! `SeqloqBox` keeps only the mutex and sequence number inline, and moves the
+//! data to its own allocation.  Readers still copy straight out of that
+//! block, under the usual validation.
+
+use std::cell::UnsafeCell;
+
+use crate::{SeqloqGuard, SeqloqStagedGuard, SeqCount, RawMutex, WordLock, Parts};
+
+/// A seqlock whose data lives in a separate heap allocation.
+pub struct SeqloqBox<T, M = WordLock> {
+    mutex: M,
+    seqnum: SeqCount,
+    data: Box<UnsafeCell<T>>,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqBox<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqBox<T, M> { }
+
+impl<T> SeqloqBox<T>
+    where T: Send + Copy,
+{
+    /// Move `t` to the heap and protect it.
+    #[inline]
+    pub fn new(t: T) -> SeqloqBox<T> {
+        SeqloqBox::from_box(Box::new(t))
+    }
+
+    /// Protect an existing allocation.
+    ///
+    /// Use this for payloads too big to build on the stack.
+    #[inline]
+    pub fn from_box(b: Box<T>) -> SeqloqBox<T> {
+        SeqloqBox::with_mutex(b, WordLock::new())
+    }
+}
+
+impl<T, M> SeqloqBox<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Protect `b`, using `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(b: Box<T>, mutex: M) -> SeqloqBox<T, M> {
+        // UnsafeCell<T> has the same layout as T.
+        let data = unsafe { Box::from_raw(Box::into_raw(b) as *mut UnsafeCell<T>) };
+        SeqloqBox {
+            mutex,
+            seqnum: SeqCount::new(),
+            data,
+        }
+    }
+
+    /// Give back the allocation.
+    #[inline]
+    pub fn into_box(self) -> Box<T> {
+        unsafe { Box::from_raw(Box::into_raw(self.data) as *mut T) }
+    }
+
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, T, M> {
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &self.seqnum,
+            data: self.data.get(),
+        }
+    }
+
+    /// As `Seqloq::peek`.
+    #[inline]
+    pub fn peek<F, R>(&self, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts().peek_versioned(f).0
+    }
+
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.parts().read()
+    }
+
+    /// As `Seqloq::lock`.
+    #[inline]
+    pub fn lock(&self) -> SeqloqGuard<'_, T, M> {
+        self.parts().lock()
+    }
+
+    /// As `Seqloq::lock_staged`.
+    #[inline]
+    pub fn lock_staged(&self) -> SeqloqStagedGuard<'_, T, M> {
+        self.parts().lock_staged()
+    }
+
+    /// As `Seqloq::write`.
+    #[inline]
+    pub fn write(&self, t: T) {
+        self.parts().write(t)
+    }
+
+    /// As `Seqloq::update`.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts().update(f)
+    }
+}
+
+#[test]
+fn boxed() {
+    let x = SeqloqBox::new([0u64; 512]);
+    assert!(std::mem::size_of_val(&x) <= 24);
+
+    x.lock()[511] = 1;
+    {
+        let mut g = x.lock_staged();
+        g[0] = 2;
+        g.commit();
+    }
+    let v = x.read();
+    assert_eq!((v[0], v[1], v[511]), (2, 0, 1));
+    assert_eq!(x.into_box()[511], 1);
+}
@@ -0,0 +1,131 @@
+//! Configuring a `Seqloq` in one place.
+//!
+//! ```
+//! use seqloq::{Seqloq, ReaderPriority, Stats};
+//!
+//! let routes = Seqloq::builder()
+//!     .name("route_table")
+//!     .padded()
+//!     .policy(ReaderPriority::new)
+//!     .policy(Stats::new)
+//!     .build([0u32; 4]);
+//! routes.write([1; 4]);
+//! assert_eq!(routes.stats().writes, 1);
+//! assert_eq!(routes.name(), Some("route_table"));
+//! ```
+//!
+//! Everything here can also be done with `Seqloq::with_mutex` and the mutex
+//! wrappers directly.  The name is kept by a `Named` mutex, so the built
+//! `Seqloq` reports it from `Seqloq::name`, and the `tracing`, `metrics`,
+//! and `registry` integrations pick it up.
+
+use std::sync::Arc;
+
+use crate::{Seqloq, RawMutex, WordLock, Padded, Named};
+
+/// Builds a `Seqloq`.  See `Seqloq::builder`.
+#[derive(Debug)]
+pub struct Builder<M = WordLock> {
+    mutex: M,
+}
+
+impl Seqloq<()> {
+    /// Start configuring a `Seqloq`.
+    #[inline]
+    pub fn builder() -> Builder {
+        Builder {
+            mutex: WordLock::new(),
+        }
+    }
+}
+
+impl Builder {
+    /// Use `mutex` instead of a `WordLock`.  The name and policies wrap it,
+    /// so this comes first.
+    #[inline]
+    pub fn mutex<M: RawMutex>(self, mutex: M) -> Builder<M> {
+        Builder { mutex }
+    }
+}
+
+impl<M: RawMutex> Builder<M> {
+    /// Name the `Seqloq`, for diagnostics (see `Named`).
+    #[inline]
+    pub fn name(self, name: &'static str) -> Builder<Named<M>> {
+        self.policy(|m| Named::new(name, m))
+    }
+
+    /// The name given to `name`, if any.
+    #[inline]
+    pub fn get_name(&self) -> Option<&'static str> {
+        self.mutex.name()
+    }
+
+
+    /// Wrap the mutex configured so far, with a policy such as
+    /// `ReaderPriority::new` or `Stats::new`.
+    #[inline]
+    pub fn policy<N, F>(self, f: F) -> Builder<N>
+        where N: RawMutex,
+              F: FnOnce(M) -> N,
+    {
+        Builder {
+            mutex: f(self.mutex),
+        }
+    }
+
+    /// Keep the writer mutex on a cache line of its own (see `Padded`).
+    #[inline]
+    pub fn padded(self) -> Builder<Padded<M>> {
+        self.policy(Padded::new)
+    }
+
+    /// Report through `tracing`, under the configured name (see `Traced`).
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub fn traced(self) -> Builder<crate::Traced<M>> {
+        let name = self.get_name().unwrap_or("seqloq");
+        self.policy(|m| crate::Traced::new(name, m))
+    }
+
+    /// Report through `metrics`, under the configured name (see `Metered`).
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub fn metered(self) -> Builder<crate::Metered<M>> {
+        let name = self.get_name().unwrap_or("seqloq");
+        self.policy(|m| crate::Metered::new(name, m))
+    }
+
+    /// Create the `Seqloq`, holding `t`.
+    #[inline]
+    pub fn build<T: Send + Copy>(self, t: T) -> Seqloq<T, M> {
+        Seqloq::with_mutex(t, self.mutex)
+    }
+
+    /// Create the `Seqloq` in an `Arc`.  With the `registry` feature, a
+    /// named `Seqloq` is also registered.
+    pub fn build_arc<T: Send + Copy + 'static>(self, t: T) -> Arc<Seqloq<T, M>>
+        where M: 'static,
+    {
+        let s = Arc::new(self.build(t));
+        #[cfg(feature = "registry")]
+        if let Some(name) = s.name() {
+            s.register(name);
+        }
+        s
+    }
+}
+
+#[test]
+fn builder() {
+    let b = Seqloq::builder().mutex(crate::SpinLock::new()).name("test");
+    assert_eq!(b.get_name(), Some("test"));
+    let x = b.padded().build(1u32);
+    assert_eq!(std::mem::align_of_val(&x), 128);
+    assert_eq!(x.name(), Some("test"));
+    *x.lock() += 1;
+    assert_eq!(x.read(), 2);
+    let y = Seqloq::builder().build_arc(3u8);
+    assert_eq!(y.read(), 3);
+    assert_eq!(y.name(), None);
+}
@@ -0,0 +1,185 @@
+//! A cell which skips the sequence protocol when the payload fits in a word.
+//!
+//! When `T` is no bigger than the sequence counter, there's no need for a
+//! counter at all: the value itself can live in an atomic word.
+//! `SeqloqCell` makes that choice based on the size of `T`, so generic code
+//! gets the fast path for free.
+//!
+//! It does need `T: NoPadding`, even when `T` is too big for a word and ends
+//! up in a `Seqloq`, because moving padding bytes through an integer is
+//! undefined behavior, and nothing short of the bound tells whether `T` has
+//! any.  Generic code which only knows `T: Copy` should use `Seqloq`.
+
+use std::mem;
+use std::ptr;
+use std::marker::PhantomData;
+
+use crate::Seqloq;
+use crate::sync::{AtomicSeq, Seq, Ordering};
+
+/// Types whose every byte is initialized.
+///
+/// `SeqloqCell` copies these into an integer, which would be undefined
+/// behavior if any of the bytes were padding.
+///
+/// # Safety
+///
+/// The type must contain no padding bytes (or other uninitialized bytes).
+pub unsafe trait NoPadding: Copy { }
+
+macro_rules! no_padding {
+    ($($t:ty)*) => {
+        $(unsafe impl NoPadding for $t { })*
+    }
+}
+
+no_padding!(() bool char u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+unsafe impl<T: NoPadding, const N: usize> NoPadding for [T; N] { }
+
+/// Types for which every bit pattern is a valid value.
+///
+/// `Seqloq::restore` builds these from the bytes of a file, which may have
+/// been written for another type, or by someone else entirely.  Unlike
+/// `NoPadding`, this rules out `bool` and `char`.
+///
+/// # Safety
+///
+/// The type must contain no padding bytes, and any initialized bytes of
+/// the right size must be a valid value of it.
+pub unsafe trait AnyBitPattern: NoPadding { }
+
+macro_rules! any_bit_pattern {
+    ($($t:ty)*) => {
+        $(unsafe impl AnyBitPattern for $t { })*
+    }
+}
+
+any_bit_pattern!(() u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+unsafe impl<T: AnyBitPattern, const N: usize> AnyBitPattern for [T; N] { }
+
+enum Repr<T> {
+    Word(AtomicSeq, PhantomData<T>),
+    Locked(Seqloq<T>),
+}
+
+/// A `Seqloq`-like cell which is a single atomic word for small payloads.
+///
+/// Only for `NoPadding` payloads; see the module documentation.
+pub struct SeqloqCell<T> {
+    repr: Repr<T>,
+}
+
+#[inline(always)]
+fn fits<T>() -> bool {
+    mem::size_of::<T>() <= mem::size_of::<Seq>()
+        && mem::align_of::<T>() <= mem::align_of::<Seq>()
+}
+
+#[inline(always)]
+fn to_word<T: NoPadding>(t: T) -> Seq {
+    let mut w: Seq = 0;
+    unsafe {
+        ptr::copy_nonoverlapping(&t as *const T as *const u8,
+            &mut w as *mut Seq as *mut u8, mem::size_of::<T>());
+    }
+    w
+}
+
+#[inline(always)]
+fn from_word<T: NoPadding>(w: Seq) -> T {
+    // The bytes came from a valid T, by way of to_word.
+    unsafe { ptr::read(&w as *const Seq as *const T) }
+}
+
+impl<T> SeqloqCell<T>
+    where T: NoPadding + Send,
+{
+    #[inline]
+    pub fn new(t: T) -> SeqloqCell<T> {
+        SeqloqCell {
+            repr: if fits::<T>() {
+                Repr::Word(AtomicSeq::new(to_word(t)), PhantomData)
+            } else {
+                Repr::Locked(Seqloq::new(t))
+            },
+        }
+    }
+
+    /// Does this cell use a single atomic word, rather than a `Seqloq`?
+    #[inline]
+    pub fn is_lock_free(&self) -> bool {
+        matches!(self.repr, Repr::Word(..))
+    }
+
+    /// Read the value, as with `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        match self.repr {
+            Repr::Word(ref w, _) => from_word(w.load(Ordering::SeqCst)),
+            Repr::Locked(ref s) => s.read(),
+        }
+    }
+
+    /// Replace the value.
+    #[inline]
+    pub fn write(&self, t: T) {
+        match self.repr {
+            Repr::Word(ref w, _) => w.store(to_word(t), Ordering::SeqCst),
+            Repr::Locked(ref s) => s.write(t),
+        }
+    }
+
+    /// Replace the value with a function of the old value, returning the
+    /// new one.
+    ///
+    /// In the single-word case, `f` may run more than once.
+    #[inline]
+    pub fn update<F>(&self, mut f: F) -> T
+        where F: FnMut(T) -> T,
+    {
+        match self.repr {
+            Repr::Word(ref w, _) => {
+                let mut old = w.load(Ordering::SeqCst);
+                loop {
+                    let new = f(from_word(old));
+                    match w.compare_exchange_weak(old, to_word(new),
+                        Ordering::SeqCst, Ordering::SeqCst)
+                    {
+                        Ok(_) => return new,
+                        Err(cur) => old = cur,
+                    }
+                }
+            }
+            Repr::Locked(ref s) => s.update(|v| {
+                *v = f(*v);
+                *v
+            }),
+        }
+    }
+}
+
+#[test]
+fn word() {
+    let x = SeqloqCell::new(3u32);
+    assert!(x.is_lock_free());
+    assert_eq!(x.read(), 3);
+    x.write(4);
+    assert_eq!(x.read(), 4);
+    assert_eq!(x.update(|v| v * 2), 8);
+    assert_eq!(x.read(), 8);
+
+    let c = SeqloqCell::new(['a', 'b']);
+    assert_eq!(c.is_lock_free(), mem::size_of::<Seq>() >= 8);
+    assert_eq!(c.update(|[a, b]| [b, a]), ['b', 'a']);
+}
+
+#[test]
+fn locked() {
+    let x = SeqloqCell::new([1u64, 2, 3, 4]);
+    assert!(!x.is_lock_free());
+    x.write([5, 6, 7, 8]);
+    assert_eq!(x.update(|mut v| { v[0] = 0; v }), [0, 6, 7, 8]);
+    assert_eq!(x.read(), [0, 6, 7, 8]);
+}
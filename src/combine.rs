@@ -0,0 +1,236 @@
+//! Flat combining.
+//!
+//! Many small writes, each taking the lock and bumping the sequence number,
+//! keep readers retrying.  A `Combiner` lets writers queue their updates
+//! instead.  Whichever writer gets the lock applies everything queued so
+//! far, in a single write section, so readers see one change where there
+//! would have been many.  Alternatively, writers can `push` updates
+//! without applying them, and leave a dedicated thread to `flush` them.
+//!
+//! ```
+//! use seqloq::combine::Combiner;
+//!
+//! let hits = Combiner::new([0u64; 4]);
+//! std::thread::scope(|s| {
+//!     for i in 0..4 {
+//!         let hits = &hits;
+//!         s.spawn(move || {
+//!             for _ in 0..100 {
+//!                 hits.defer(move |h| h[i] += 1);
+//!             }
+//!         });
+//!     }
+//! });
+//! assert_eq!(hits.read(), [100; 4]);
+//! ```
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+
+use crate::{Seqloq, SeqloqGuard, RawMutex, WordLock};
+use crate::sync::{AtomicPtr, Ordering};
+
+type Update<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+struct Node<T> {
+    f: Update<T>,
+    next: *mut Node<T>,
+}
+
+/// A `Seqloq` whose writers can combine their updates.
+///
+/// Dereferences to the `Seqloq`, for reading, and for writes which don't go
+/// through the queue.  Updates queued while one of those holds the lock
+/// wait for the next `defer` or `flush`.
+pub struct Combiner<T, M = WordLock> {
+    seqloq: Seqloq<T, M>,
+    /// Queued updates, newest first.
+    queue: AtomicPtr<Node<T>>,
+    _nodes: PhantomData<Box<Node<T>>>,
+}
+
+unsafe impl<T: Send, M: Send> Send for Combiner<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for Combiner<T, M> { }
+
+impl<T> Combiner<T>
+    where T: Send + Copy,
+{
+    #[inline]
+    pub fn new(t: T) -> Combiner<T> {
+        Combiner::with_mutex(t, WordLock::new())
+    }
+}
+
+impl<T, M> Combiner<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Use `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(t: T, mutex: M) -> Combiner<T, M> {
+        Combiner {
+            seqloq: Seqloq::with_mutex(t, mutex),
+            queue: AtomicPtr::new(ptr::null_mut()),
+            _nodes: PhantomData,
+        }
+    }
+
+    /// Queue an update, and apply the queue if no other writer is.
+    ///
+    /// If another writer holds the lock, this returns straight away, and the
+    /// update is applied once that writer releases it.  Updates from one
+    /// thread are applied in the order they were queued.
+    pub fn defer<F>(&self, f: F)
+        where F: FnOnce(&mut T) + Send + 'static,
+    {
+        self.push(f);
+        self.combine();
+    }
+
+    /// Queue an update without applying it.  It's applied by the next
+    /// `defer` or `flush`.
+    pub fn push<F>(&self, f: F)
+        where F: FnOnce(&mut T) + Send + 'static,
+    {
+        let node = Box::into_raw(Box::new(Node {
+            f: Box::new(f),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.queue.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head; }
+            match self.queue.compare_exchange_weak(head, node,
+                Ordering::SeqCst, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    /// Apply the queue, waiting for the lock if need be.  Afterwards, every
+    /// update queued before the call has been published.
+    pub fn flush(&self) {
+        let mut guard = self.seqloq.lock();
+        self.apply(&mut guard);
+        drop(guard);
+        self.combine();
+    }
+
+    /// Is anything queued?
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        !self.queue.load(Ordering::SeqCst).is_null()
+    }
+
+    fn combine(&self) {
+        // Either we get the lock, or its holder is yet to check the queue
+        // after releasing it, and will find what we queued.
+        while self.is_pending() {
+            match self.seqloq.try_lock() {
+                Some(mut guard) => self.apply(&mut guard),
+                None => return,
+            }
+        }
+    }
+
+    /// Apply everything queued so far, oldest first.  With nothing queued,
+    /// the guard is left clean, so there's no write.
+    fn apply(&self, guard: &mut SeqloqGuard<'_, T, M>) {
+        let mut p = self.queue.swap(ptr::null_mut(), Ordering::SeqCst);
+        if p.is_null() {
+            return;
+        }
+        let mut batch = Vec::new();
+        while !p.is_null() {
+            let node = unsafe { Box::from_raw(p) };
+            p = node.next;
+            batch.push(node.f);
+        }
+        let t = &mut **guard;
+        for f in batch.into_iter().rev() {
+            f(t);
+        }
+    }
+}
+
+impl<T, M> Deref for Combiner<T, M> {
+    type Target = Seqloq<T, M>;
+
+    #[inline]
+    fn deref(&self) -> &Seqloq<T, M> {
+        &self.seqloq
+    }
+}
+
+impl<T, M> Drop for Combiner<T, M> {
+    fn drop(&mut self) {
+        let mut p = self.queue.load(Ordering::Relaxed);
+        while !p.is_null() {
+            let node = unsafe { Box::from_raw(p) };
+            p = node.next;
+        }
+    }
+}
+
+#[test]
+fn combine() {
+    let x = Combiner::new(0u64);
+    x.flush();
+    assert_eq!(x.version(), 0);
+    {
+        let _g = x.lock();
+        x.defer(|n| *n += 1);
+        x.defer(|n| *n *= 10);
+        assert!(x.is_pending());
+    }
+    // Nobody combined after the guard dropped, because it wasn't ours.
+    assert_eq!(x.read(), 0);
+    let v = x.version();
+    x.flush();
+    assert_eq!(x.read(), 10);
+    // Both updates went out in one write.
+    assert_eq!(x.version(), v + 2);
+    assert!(!x.is_pending());
+    // Nothing queued, so nothing to write.
+    x.flush();
+    assert_eq!(x.version(), v + 2);
+    x.push(|n| *n += 1);
+    assert_eq!(x.read(), 10);
+    x.defer(|n| *n += 1);
+    assert_eq!(x.read(), 12);
+}
+
+#[test]
+fn combine_threads() {
+    let x = Combiner::new(0u64);
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    x.defer(|n| *n += 1);
+                }
+            });
+        }
+    });
+    assert_eq!(x.read(), 8000);
+
+    // While the lock is held, writers only queue, and all their updates
+    // are published by a single write.
+    let v = x.version();
+    let g = x.lock();
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..100 {
+                    x.defer(|n| *n += 1);
+                }
+            });
+        }
+    });
+    drop(g);
+    assert_eq!(x.read(), 8000);
+    x.flush();
+    assert_eq!(x.read(), 8800);
+    assert_eq!(x.version(), v + 2);
+}
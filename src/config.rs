@@ -0,0 +1,238 @@
+//! Configuration that can be reloaded while the program runs.
+//!
+//! A `HotConfig` holds the current configuration, which needn't be `Copy`.
+//! Readers take a snapshot, without locking, and keep using it for as long
+//! as they like; a reload publishes a new value without disturbing them.
+//!
+//! ```
+//! use seqloq::config::HotConfig;
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Config { workers: usize, greeting: String }
+//!
+//! let config = HotConfig::new(Config { workers: 4, greeting: "hi".into() });
+//! let old = config.load();
+//! config.reload(|| Ok::<_, ()>(Config { workers: 8, greeting: "hello".into() })).unwrap();
+//! assert_eq!(old.workers, 4);
+//! assert_eq!(config.load().workers, 8);
+//! assert_eq!(config.generation(), 1);
+//! ```
+//!
+//! `HotConfig::watch_file` reloads from a file whenever it changes.  It
+//! polls the file's modification time, so it needs no platform-specific
+//! file watching.
+
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::{Seqloq, SeqloqArc, Subscription};
+
+/// A configuration value, as of some generation.
+///
+/// Dereferences to the configuration.  Cloning it is cheap.
+pub struct Snapshot<T>(Arc<(u64, T)>);
+
+impl<T> Snapshot<T> {
+    /// How many times the configuration had been replaced when this value
+    /// was published.  The initial value is generation 0.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.0.0
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    #[inline]
+    fn clone(&self) -> Snapshot<T> {
+        Snapshot(self.0.clone())
+    }
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0.1
+    }
+}
+
+/// A reloadable configuration.
+pub struct HotConfig<T> {
+    value: SeqloqArc<(u64, T)>,
+    /// Written after each new value is published, to wake waiters and
+    /// subscribers.  Its lock also orders concurrent reloads.
+    generation: Seqloq<u64>,
+}
+
+impl<T> HotConfig<T> {
+    pub fn new(t: T) -> HotConfig<T> {
+        HotConfig {
+            value: SeqloqArc::new((0, t)),
+            generation: Seqloq::new(0),
+        }
+    }
+
+    /// The current configuration.
+    #[inline]
+    pub fn load(&self) -> Snapshot<T> {
+        Snapshot(self.value.load())
+    }
+
+    /// The current generation, which goes up by one per reload.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation.read()
+    }
+
+    /// Replace the configuration, returning the new generation.
+    pub fn publish(&self, t: T) -> u64 {
+        let mut g = self.generation.lock();
+        let next = *g + 1;
+        self.value.store(Arc::new((next, t)));
+        *g = next;
+        next
+    }
+
+    /// Replace the configuration with what `loader` returns.  If it fails,
+    /// the old configuration stays.
+    pub fn reload<F, E>(&self, loader: F) -> Result<u64, E>
+        where F: FnOnce() -> Result<T, E>,
+    {
+        loader().map(|t| self.publish(t))
+    }
+
+    /// Block until a generation newer than `since` is published, and return
+    /// it.  Returns straight away if that already happened.
+    pub fn wait_for_change(&self, since: u64) -> Snapshot<T> {
+        self.generation.wait_until(|&g| g != since);
+        self.load()
+    }
+
+    /// Subscribe to reloads.  One message is sent per reload.
+    ///
+    /// The messages carry versions of an internal `Seqloq`, not
+    /// generations; use `load` to get the new configuration.
+    #[inline]
+    pub fn subscribe(&self) -> Subscription<'_> {
+        self.generation.subscribe()
+    }
+}
+
+impl<T: Send + Sync + 'static> HotConfig<T> {
+    /// Reload from `path` with `loader`, from a background thread, each
+    /// time the file's modification time or length changes.  It's checked
+    /// every `interval`.
+    ///
+    /// The file is loaded once straight away.  Errors leave the old
+    /// configuration in place; the latest is kept in the `Watcher`.
+    pub fn watch_file<P, F, E>(self: &Arc<Self>, path: P, interval: Duration, mut loader: F)
+        -> io::Result<Watcher<E>>
+        where P: AsRef<Path>,
+              F: FnMut(&Path) -> Result<T, E> + Send + 'static,
+              E: Send + 'static,
+    {
+        let path: PathBuf = path.as_ref().into();
+        let stamp = |path: &Path| -> io::Result<(SystemTime, u64)> {
+            let meta = path.metadata()?;
+            Ok((meta.modified()?, meta.len()))
+        };
+        let mut last = stamp(&path)?;
+
+        let error = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = Arc::clone(self);
+        let (errors, stopping) = (Arc::clone(&error), Arc::clone(&stop));
+        let thread = thread::spawn(move || {
+            let load = |loader: &mut F| {
+                if let Err(e) = config.reload(|| loader(&path)) {
+                    *errors.lock().unwrap() = Some(e);
+                }
+            };
+            load(&mut loader);
+            while !stopping.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                // A file that's briefly missing, say while being replaced,
+                // is checked again next time.
+                if let Ok(s) = stamp(&path) && s != last {
+                    last = s;
+                    load(&mut loader);
+                }
+            }
+        });
+        Ok(Watcher {
+            error,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Reloads a `HotConfig` from a file in the background.  Returned by
+/// `HotConfig::watch_file`.
+///
+/// Dropping it stops the thread.
+pub struct Watcher<E> {
+    error: Arc<Mutex<Option<E>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<E> Watcher<E> {
+    /// The latest error from the loader, if any, since this was last called.
+    pub fn take_error(&self) -> Option<E> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl<E> Drop for Watcher<E> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+#[test]
+fn reload() {
+    let config = HotConfig::new(String::from("a"));
+    let sub = config.subscribe();
+    let a = config.load();
+    assert_eq!(config.reload(|| Ok::<_, ()>("b".into())), Ok(1));
+    assert_eq!(config.reload(|| Err("bad")), Err("bad"));
+    assert_eq!((&a[..], a.generation()), ("a", 0));
+    let b = config.wait_for_change(0);
+    assert_eq!((&b[..], b.generation()), ("b", 1));
+    assert_eq!(sub.try_iter().count(), 1);
+}
+
+#[test]
+fn watch_file() {
+    let path = std::env::temp_dir().join(format!("seqloq-config-{}", std::process::id()));
+    std::fs::write(&path, "1").unwrap();
+    let parse = |p: &Path| -> Result<u32, String> {
+        let s = std::fs::read_to_string(p).map_err(|e| e.to_string())?;
+        s.trim().parse().map_err(|_| format!("bad number {:?}", s))
+    };
+
+    let config = Arc::new(HotConfig::new(0u32));
+    let watcher = config.watch_file(&path, Duration::from_millis(5), parse).unwrap();
+    assert_eq!(*config.wait_for_change(0), 1);
+
+    std::fs::write(&path, "two").unwrap();
+    while watcher.take_error().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(*config.load(), 1);
+
+    std::fs::write(&path, "33").unwrap();
+    assert_eq!(*config.wait_for_change(1), 33);
+    drop(watcher);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,298 @@
+//! Copying the protected data in and out.
+//!
+//! Readers copy the data while a writer may be modifying it.  The result is
+//! thrown away in that case, but the concurrent access is still a data race
+//! as far as the memory model is concerned, and Miri and ThreadSanitizer
+//! will say so.
+//!
+//! With the `atomic-copy` feature, both sides copy using relaxed atomic
+//! operations instead: a word at a time where alignment allows, and a byte at
+//! a time otherwise.  This makes the protocol race-free by construction, at
+//! some cost in speed.
+//!
+//! Payloads may contain padding, which is uninitialized, and the standard
+//! atomic types can't hold uninitialized bytes.  So the copies go through
+//! `atomic-maybe-uninit`, whose loads and stores are written in assembly and
+//! carry the bytes across as they are.  Miri can't run those, so under Miri
+//! the standard atomics are used after all, and Miri will object to any
+//! padding in a payload that's shared between threads.
+//!
+//! The `tsan` feature turns on `atomic-copy`, for programs run under
+//! ThreadSanitizer.  TSan doesn't instrument assembly, so it doesn't see the
+//! copies at all, but they're atomic, so there's no race for it to miss.
+//! Nor does it need annotations for the synchronization: it doesn't model
+//! the fences in `SeqCount`, but those only order the reads that are thrown
+//! away.  A read which succeeds began by loading the even count stored at
+//! the end of the write it saw, and that sequentially consistent load and
+//! store are a happens-before edge TSan does see.  `tests/tsan.rs` checks
+//! that edge, with plain accesses published through a `Seqloq`.
+
+#[cfg(feature = "atomic-copy")]
+use core::mem::{self, MaybeUninit};
+
+#[cfg(not(feature = "atomic-copy"))]
+use core::{cmp, mem, ptr};
+
+/// Copy a `T` out of shared memory.
+///
+/// `src` must be valid for reads and properly aligned.
+#[cfg(not(feature = "atomic-copy"))]
+#[inline(always)]
+pub(crate) unsafe fn load<T>(src: *const T) -> T {
+    unsafe { ptr::read(src) }
+}
+
+/// Copy a `T` into shared memory.
+///
+/// `dst` must be valid for writes and properly aligned.
+#[cfg(not(feature = "atomic-copy"))]
+#[inline(always)]
+pub(crate) unsafe fn store<T>(dst: *mut T, val: T) {
+    unsafe { ptr::write(dst, val) }
+}
+
+/// Copy an array out of shared memory, straight into `dst`.
+///
+/// The copy goes in fixed-size chunks, each a constant-length
+/// `copy_nonoverlapping` which the compiler reliably turns into vector loads
+/// and stores.  Returning a large array by value, as `load` does, costs an
+/// extra pass through a temporary.
+///
+/// `src` must be valid for reads and properly aligned.
+#[cfg(not(feature = "atomic-copy"))]
+#[inline]
+pub(crate) unsafe fn load_into<T, const N: usize>(src: *const [T; N], dst: &mut [T; N]) {
+    const CHUNK_BYTES: usize = 64;
+    let per_chunk = cmp::max(1, CHUNK_BYTES / cmp::max(1, mem::size_of::<T>()));
+
+    let src = src as *const T;
+    let dst = dst.as_mut_ptr();
+    let mut i = 0;
+    unsafe {
+        while i + per_chunk <= N {
+            ptr::copy_nonoverlapping(src.add(i), dst.add(i), per_chunk);
+            i += per_chunk;
+        }
+        ptr::copy_nonoverlapping(src.add(i), dst.add(i), N - i);
+    }
+}
+
+/// Copy `len` bytes out of shared memory.
+#[cfg(not(feature = "atomic-copy"))]
+#[cfg(any(test, feature = "ffi"))]
+#[inline]
+pub(crate) unsafe fn load_bytes(src: *const u8, dst: *mut u8, len: usize) {
+    unsafe { ptr::copy_nonoverlapping(src, dst, len) }
+}
+
+/// Copy `len` bytes into shared memory.
+#[cfg(not(feature = "atomic-copy"))]
+#[cfg(any(test, feature = "ffi"))]
+#[inline]
+pub(crate) unsafe fn store_bytes(src: *const u8, dst: *mut u8, len: usize) {
+    unsafe { ptr::copy_nonoverlapping(src, dst, len) }
+}
+
+#[cfg(feature = "atomic-copy")]
+const WORD: usize = mem::size_of::<usize>();
+
+/// One atomic copy of a word or byte, leaving uninitialized bytes as they
+/// are.
+#[cfg(all(feature = "atomic-copy", not(miri)))]
+mod unit {
+    use core::mem::MaybeUninit;
+    use core::sync::atomic::Ordering;
+
+    use atomic_maybe_uninit::AtomicMaybeUninit;
+
+    #[inline(always)]
+    pub unsafe fn load<T>(src: *const T) -> MaybeUninit<T>
+        where T: atomic_maybe_uninit::raw::AtomicLoad,
+    {
+        unsafe { AtomicMaybeUninit::from_ptr(src as *mut MaybeUninit<T>).load(Ordering::Relaxed) }
+    }
+
+    #[inline(always)]
+    pub unsafe fn store<T>(dst: *mut T, val: MaybeUninit<T>)
+        where T: atomic_maybe_uninit::raw::AtomicStore,
+    {
+        unsafe { AtomicMaybeUninit::from_ptr(dst as *mut MaybeUninit<T>).store(val, Ordering::Relaxed) }
+    }
+}
+
+/// The same, with the standard atomics, for Miri.
+#[cfg(all(feature = "atomic-copy", miri))]
+mod unit {
+    use core::mem::MaybeUninit;
+    use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+    pub trait Unit: Sized {
+        unsafe fn load(src: *const Self) -> Self;
+        unsafe fn store(dst: *mut Self, val: Self);
+    }
+
+    impl Unit for usize {
+        unsafe fn load(src: *const usize) -> usize {
+            unsafe { AtomicUsize::from_ptr(src as *mut usize).load(Ordering::Relaxed) }
+        }
+        unsafe fn store(dst: *mut usize, val: usize) {
+            unsafe { AtomicUsize::from_ptr(dst).store(val, Ordering::Relaxed) }
+        }
+    }
+
+    impl Unit for u8 {
+        unsafe fn load(src: *const u8) -> u8 {
+            unsafe { AtomicU8::from_ptr(src as *mut u8).load(Ordering::Relaxed) }
+        }
+        unsafe fn store(dst: *mut u8, val: u8) {
+            unsafe { AtomicU8::from_ptr(dst).store(val, Ordering::Relaxed) }
+        }
+    }
+
+    #[inline(always)]
+    pub unsafe fn load<T: Unit>(src: *const T) -> MaybeUninit<T> {
+        MaybeUninit::new(unsafe { T::load(src) })
+    }
+
+    #[inline(always)]
+    pub unsafe fn store<T: Unit>(dst: *mut T, val: MaybeUninit<T>) {
+        unsafe { T::store(dst, val.assume_init()) }
+    }
+}
+
+/// Number of leading bytes of a `T` that are copied a word at a time.
+#[cfg(feature = "atomic-copy")]
+#[inline(always)]
+fn word_bytes<T>() -> usize {
+    if mem::align_of::<T>() >= mem::align_of::<usize>() {
+        mem::size_of::<T>() / WORD * WORD
+    } else {
+        0
+    }
+}
+
+/// Copy a `T` out of shared memory.
+///
+/// `src` must be valid for reads and properly aligned.
+#[cfg(feature = "atomic-copy")]
+#[inline]
+pub(crate) unsafe fn load<T>(src: *const T) -> T {
+    let mut out = MaybeUninit::<T>::uninit();
+    unsafe {
+        load_raw(src, out.as_mut_ptr());
+        out.assume_init()
+    }
+}
+
+/// Copy an array out of shared memory, straight into `dst`.
+///
+/// `src` must be valid for reads and properly aligned.
+#[cfg(feature = "atomic-copy")]
+#[inline]
+pub(crate) unsafe fn load_into<T, const N: usize>(src: *const [T; N], dst: &mut [T; N]) {
+    unsafe { load_raw(src, dst) }
+}
+
+#[cfg(feature = "atomic-copy")]
+#[inline]
+unsafe fn load_raw<T>(src: *const T, dst: *mut T) {
+    let src = src as *const u8;
+    let dst = dst as *mut u8;
+    let words = word_bytes::<T>();
+
+    unsafe {
+        let mut i = 0;
+        while i < words {
+            let w = unit::load(src.add(i) as *const usize);
+            (dst.add(i) as *mut MaybeUninit<usize>).write(w);
+            i += WORD;
+        }
+        while i < mem::size_of::<T>() {
+            let b = unit::load(src.add(i));
+            (dst.add(i) as *mut MaybeUninit<u8>).write(b);
+            i += 1;
+        }
+    }
+}
+
+/// Copy a `T` into shared memory.
+///
+/// `dst` must be valid for writes and properly aligned.
+#[cfg(feature = "atomic-copy")]
+#[inline]
+pub(crate) unsafe fn store<T>(dst: *mut T, val: T) {
+    let src = &val as *const T as *const u8;
+    let dst = dst as *mut u8;
+    let words = word_bytes::<T>();
+
+    unsafe {
+        let mut i = 0;
+        while i < words {
+            let w = (src.add(i) as *const MaybeUninit<usize>).read();
+            unit::store(dst.add(i) as *mut usize, w);
+            i += WORD;
+        }
+        while i < mem::size_of::<T>() {
+            unit::store(dst.add(i), (src.add(i) as *const MaybeUninit<u8>).read());
+            i += 1;
+        }
+    }
+    mem::forget(val);
+}
+
+/// Copy `len` bytes out of shared memory.  Nothing is known about their
+/// alignment, so they go a byte at a time.
+#[cfg(feature = "atomic-copy")]
+#[cfg(any(test, feature = "ffi"))]
+#[inline]
+pub(crate) unsafe fn load_bytes(src: *const u8, dst: *mut u8, len: usize) {
+    for i in 0..len {
+        unsafe { (dst.add(i) as *mut MaybeUninit<u8>).write(unit::load(src.add(i))) };
+    }
+}
+
+/// Copy `len` bytes into shared memory, a byte at a time.
+#[cfg(feature = "atomic-copy")]
+#[cfg(any(test, feature = "ffi"))]
+#[inline]
+pub(crate) unsafe fn store_bytes(src: *const u8, dst: *mut u8, len: usize) {
+    for i in 0..len {
+        unsafe { unit::store(dst.add(i), (src.add(i) as *const MaybeUninit<u8>).read()) };
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut words = [0u64; 5];
+    unsafe { store(&mut words, [1, 2, 3, 4, 5]) };
+    assert_eq!(unsafe { load(&words) }, [1, 2, 3, 4, 5]);
+
+    // Three bytes of padding, which must survive the trip uninitialized.
+    let mut padded = (0u8, 0u32);
+    unsafe { store(&mut padded, (1, 2)) };
+    assert_eq!(unsafe { load(&padded) }, (1, 2));
+
+    let mut bytes = [0u8; 7];
+    unsafe { store(&mut bytes, *b"seqloq!") };
+    assert_eq!(unsafe { load(&bytes) }, *b"seqloq!");
+
+    let mut out = [0u8; 7];
+    unsafe {
+        store_bytes(b"bytes!!".as_ptr(), bytes.as_mut_ptr(), 7);
+        load_bytes(bytes.as_ptr(), out.as_mut_ptr(), 7);
+    }
+    assert_eq!(&out, b"bytes!!");
+}
+
+#[test]
+fn chunked() {
+    let src: [u8; 100] = std::array::from_fn(|i| i as u8);
+    let mut dst = [0u8; 100];
+    unsafe { load_into(&src, &mut dst) };
+    assert_eq!(src, dst);
+
+    let src = [(1u64, 2u32); 3];
+    let mut dst = [(0, 0); 3];
+    unsafe { load_into(&src, &mut dst) };
+    assert_eq!(src, dst);
+}
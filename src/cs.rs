@@ -0,0 +1,118 @@
+//! Writers excluded by a critical section, for interrupt handlers.
+//!
+//! On a bare-metal microcontroller the data is often produced in an
+//! interrupt handler and consumed by the main loop or a task.  A writer
+//! holding a `CsLock` runs inside a `critical-section` critical section,
+//! which on a single core means interrupts are off.  So an interrupt handler
+//! never lands in the middle of a write section, and can read or write
+//! without waiting:
+//!
+//! ```
+//! use seqloq::{Seqloq, CsLock};
+//!
+//! let reading = Seqloq::with_mutex((0u32, 0i16), CsLock::new());
+//!
+//! // In the ADC interrupt handler:
+//! reading.write((1, -40));
+//!
+//! // In the main loop:
+//! let (seq, temp) = reading.read();
+//! # assert_eq!((seq, temp), (1, -40));
+//! ```
+//!
+//! Write sections should be as short as any other critical section.
+//!
+//! On bare metal, turn off the default `std` feature:
+//!
+//! ```toml
+//! seqloq = { version = "0.0.1", default-features = false, features = ["critical-section"] }
+//! ```
+//!
+//! That leaves `Seqloq` with its reads, writes and guards, `SeqCount`,
+//! `CsLock` and `embedded::SeqloqWatch`, all built on `core` alone.  The
+//! program still has to provide a `critical-section` implementation, as
+//! usual.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use critical_section::RestoreState;
+
+use crate::RawMutex;
+use crate::sync::{AtomicBool, Ordering};
+
+/// A writer mutex which holds a critical section while locked.
+///
+/// `try_lock` fails if the lock is held, but otherwise enters the critical
+/// section, which can mean waiting on a multi-core implementation.
+pub struct CsLock {
+    held: AtomicBool,
+    /// How to leave the critical section.  Protected by it.
+    restore: UnsafeCell<MaybeUninit<RestoreState>>,
+}
+
+unsafe impl Send for CsLock { }
+unsafe impl Sync for CsLock { }
+
+impl Default for CsLock {
+    fn default() -> CsLock {
+        CsLock::new()
+    }
+}
+
+impl CsLock {
+    #[inline]
+    pub fn new() -> CsLock {
+        CsLock {
+            held: AtomicBool::new(false),
+            restore: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+unsafe impl RawMutex for CsLock {
+    #[inline]
+    fn lock(&self) {
+        let restore = unsafe { critical_section::acquire() };
+        self.held.store(true, Ordering::Relaxed);
+        unsafe { (*self.restore.get()).write(restore) };
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        if self.held.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.lock();
+        true
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe {
+            let restore = (*self.restore.get()).assume_init_read();
+            self.held.store(false, Ordering::Relaxed);
+            critical_section::release(restore);
+        }
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.held.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn cs_lock() {
+    let x = crate::Seqloq::with_mutex(0u32, CsLock::new());
+    {
+        let mut g = x.lock();
+        assert!(x.is_locked());
+        *g = 1;
+    }
+    assert!(!x.is_locked());
+    assert!(x.mutex.try_lock());
+    assert!(!x.mutex.try_lock());
+    unsafe { x.mutex.unlock() };
+    assert_eq!(x.read(), 1);
+}
@@ -0,0 +1,265 @@
+//! Waiting for writes on embedded async executors.
+//!
+//! The futures in the `future` module park wakers in a process-wide table
+//! behind a `std` mutex, which doesn't exist on bare metal and can't be
+//! touched from an interrupt handler anyway.  `SeqloqWatch` instead keeps a
+//! fixed number of waker slots inline, guarded by a critical section.  It
+//! builds without the `std` feature, so it suits Embassy, RTIC, and any
+//! other executor that only needs a `Waker`:
+//!
+//! ```
+//! use seqloq::embedded::SeqloqWatch;
+//!
+//! let reading: SeqloqWatch<i16> = SeqloqWatch::new(0);
+//!
+//! // In the ADC interrupt handler:
+//! reading.write(-40);
+//!
+//! // In a task:
+//! # let f = async {
+//! let (t, v) = reading.wait_for_change(0).await;
+//! # (t, v) };
+//! ```
+//!
+//! Up to `N` tasks can wait at once.  If more try, all of them are woken to
+//! re-register, and they take turns.
+
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use critical_section::Mutex;
+
+use crate::{CsLock, SeqCount, SeqloqGuard, Parts};
+
+/// A `Seqloq` with a writer `CsLock` and inline waker slots for up to `N`
+/// waiting tasks.
+pub struct SeqloqWatch<T, const N: usize = 4> {
+    mutex: CsLock,
+    seqnum: SeqCount,
+    wakers: Mutex<RefCell<[Option<Waker>; N]>>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for SeqloqWatch<T, N> { }
+unsafe impl<T: Send, const N: usize> Sync for SeqloqWatch<T, N> { }
+
+impl<T, const N: usize> SeqloqWatch<T, N>
+    where T: Send + Copy,
+{
+    pub fn new(t: T) -> SeqloqWatch<T, N> {
+        SeqloqWatch {
+            mutex: CsLock::new(),
+            seqnum: SeqCount::new(),
+            wakers: Mutex::new(RefCell::new([const { None }; N])),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, T, CsLock> {
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &self.seqnum,
+            data: self.data.get(),
+        }
+    }
+
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.parts().read()
+    }
+
+    /// As `Seqloq::read_versioned`.
+    #[inline]
+    pub fn read_versioned(&self) -> (T, u64) {
+        self.parts().peek_versioned(|p| unsafe { crate::copy::load(p) })
+    }
+
+    /// As `Seqloq::version`.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    /// As `Seqloq::lock`.  Waiting tasks are woken when the guard is
+    /// dropped.
+    #[inline]
+    pub fn lock(&self) -> WatchGuard<'_, T, N> {
+        let guard = self.parts().lock();
+        WatchGuard {
+            start: self.version(),
+            guard: Some(guard),
+            watch: self,
+        }
+    }
+
+    /// As `Seqloq::write`, and wake the waiting tasks.
+    #[inline]
+    pub fn write(&self, t: T) {
+        self.parts().write(t);
+        self.wake();
+    }
+
+    /// As `Seqloq::update`, and wake the waiting tasks.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        let r = self.parts().update(f);
+        self.wake();
+        r
+    }
+
+    /// Wait for a write newer than version `since`, then read the data.
+    pub async fn wait_for_change(&self, since: u64) -> (T, u64) {
+        self.changed_since(since).await;
+        self.read_versioned()
+    }
+
+    /// Wait for a write newer than version `since` to be published.
+    /// Resolves to the new version.
+    #[inline]
+    pub fn changed_since(&self, since: u64) -> Changed<'_, T, N> {
+        Changed {
+            watch: self,
+            since,
+        }
+    }
+
+    fn wake(&self) {
+        critical_section::with(|cs| {
+            for w in self.wakers.borrow_ref_mut(cs).iter_mut() {
+                if let Some(w) = w.take() {
+                    w.wake();
+                }
+            }
+        });
+    }
+
+    fn register(&self, waker: &Waker) {
+        critical_section::with(|cs| {
+            let mut wakers = self.wakers.borrow_ref_mut(cs);
+            if wakers.iter().flatten().any(|w| w.will_wake(waker)) {
+                return;
+            }
+            if !wakers.iter().any(Option::is_none) {
+                for w in wakers.iter_mut() {
+                    if let Some(w) = w.take() {
+                        w.wake();
+                    }
+                }
+            }
+            if let Some(slot) = wakers.iter_mut().find(|w| w.is_none()) {
+                *slot = Some(waker.clone());
+            }
+        });
+    }
+}
+
+/// Write access to a `SeqloqWatch`.  Waiting tasks are woken on drop, if
+/// anything was written.
+pub struct WatchGuard<'a, T: Send + Copy, const N: usize> {
+    guard: Option<SeqloqGuard<'a, T, CsLock>>,
+    watch: &'a SeqloqWatch<T, N>,
+    /// The version when the lock was taken.
+    start: u64,
+}
+
+impl<T: Send + Copy, const N: usize> core::ops::Deref for WatchGuard<'_, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T: Send + Copy, const N: usize> core::ops::DerefMut for WatchGuard<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T: Send + Copy, const N: usize> Drop for WatchGuard<'_, T, N> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        if self.watch.version() != self.start {
+            self.watch.wake();
+        }
+    }
+}
+
+/// Resolves once a write is published.  Returned by
+/// `SeqloqWatch::changed_since`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Changed<'a, T, const N: usize> {
+    watch: &'a SeqloqWatch<T, N>,
+    since: u64,
+}
+
+impl<T: Send + Copy, const N: usize> Future for Changed<'_, T, N> {
+    type Output = u64;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        let check = || {
+            let v = self.watch.version();
+            (v & 1 == 0 && v != self.since).then_some(v)
+        };
+        if let Some(v) = check() {
+            return Poll::Ready(v);
+        }
+        self.watch.register(cx.waker());
+        match check() {
+            Some(v) => Poll::Ready(v),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test]
+fn watch() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let count = Arc::new(Count(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let x: SeqloqWatch<u32, 1> = SeqloqWatch::new(0);
+    let mut f = std::pin::pin!(x.wait_for_change(0));
+    assert!(f.as_mut().poll(&mut cx).is_pending());
+    assert!(f.as_mut().poll(&mut cx).is_pending());
+    x.write(1);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    assert_eq!(f.as_mut().poll(&mut cx), Poll::Ready((1, 2)));
+
+    // A second waiter bumps the first out of the only slot.
+    let mut a = std::pin::pin!(x.changed_since(2));
+    let mut b = std::pin::pin!(x.changed_since(2));
+    assert!(a.as_mut().poll(&mut cx).is_pending());
+    let other = Waker::from(Arc::new(Count(AtomicUsize::new(0))));
+    assert!(b.as_mut().poll(&mut Context::from_waker(&other)).is_pending());
+    assert_eq!(count.0.load(Ordering::SeqCst), 2);
+
+    // Taking the lock without writing wakes nobody.
+    assert_eq!(*x.lock(), 1);
+    assert_eq!(count.0.load(Ordering::SeqCst), 2);
+    assert!(b.as_mut().poll(&mut cx).is_pending());
+
+    *x.lock() += 1;
+    assert_eq!(count.0.load(Ordering::SeqCst), 3);
+    assert_eq!(b.as_mut().poll(&mut cx), Poll::Ready(4));
+    assert_eq!(x.read(), 2);
+}
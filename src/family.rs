@@ -0,0 +1,125 @@
+//! Many seqlocked cells sharing one writer mutex and one allocation.
+//!
+//! An application with thousands of rarely written values pays for a mutex
+//! and a separate allocation per `Seqloq`.  A `SeqloqFamily` stores all the
+//! cells in one block, each with only its own sequence counter, and one
+//! mutex serializes writers to any of them.  Readers of one cell never
+//! retry because of a write to another.
+
+use std::cell::UnsafeCell;
+
+use crate::{SeqloqGuard, SeqloqStagedGuard, SeqCount, RawMutex, WordLock, Parts};
+
+struct Member<T> {
+    seqnum: SeqCount,
+    data: UnsafeCell<T>,
+}
+
+/// A group of cells with a shared writer mutex.
+pub struct SeqloqFamily<T, M = WordLock> {
+    mutex: M,
+    members: Box<[Member<T>]>,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqFamily<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqFamily<T, M> { }
+
+impl<T> SeqloqFamily<T>
+    where T: Send + Copy,
+{
+    /// Create a family with one cell per item.
+    #[inline]
+    pub fn new(items: Vec<T>) -> SeqloqFamily<T> {
+        SeqloqFamily::with_mutex(items, WordLock::new())
+    }
+}
+
+impl<T, M> SeqloqFamily<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// As `new`, using `mutex` to exclude concurrent writers.
+    pub fn with_mutex(items: Vec<T>, mutex: M) -> SeqloqFamily<T, M> {
+        SeqloqFamily {
+            mutex,
+            members: items.into_iter().map(|t| Member {
+                seqnum: SeqCount::new(),
+                data: UnsafeCell::new(t),
+            }).collect(),
+        }
+    }
+
+    /// The number of cells.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Panics if `i` is out of bounds.
+    #[inline(always)]
+    fn parts(&self, i: usize) -> Parts<'_, T, M> {
+        let m = &self.members[i];
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &m.seqnum,
+            data: m.data.get(),
+        }
+    }
+
+    /// Read cell `i`.
+    #[inline]
+    pub fn read(&self, i: usize) -> T {
+        self.parts(i).read()
+    }
+
+    /// As `Seqloq::peek`, for cell `i`.
+    #[inline]
+    pub fn peek<F, R>(&self, i: usize, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts(i).peek_versioned(f).0
+    }
+
+    /// Replace cell `i`.
+    #[inline]
+    pub fn write(&self, i: usize, t: T) {
+        self.parts(i).write(t)
+    }
+
+    /// As `Seqloq::update`, for cell `i`.
+    #[inline]
+    pub fn update<F, R>(&self, i: usize, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts(i).update(f)
+    }
+
+    /// Lock the family, for access to cell `i`.
+    #[inline]
+    pub fn lock(&self, i: usize) -> SeqloqGuard<'_, T, M> {
+        self.parts(i).lock()
+    }
+
+    /// As `lock`, staging writes in a private copy.
+    #[inline]
+    pub fn lock_staged(&self, i: usize) -> SeqloqStagedGuard<'_, T, M> {
+        self.parts(i).lock_staged()
+    }
+}
+
+#[test]
+fn family() {
+    let f = SeqloqFamily::new(vec![0u32; 1000]);
+    assert_eq!(f.len(), 1000);
+    f.write(10, 1);
+    f.update(999, |v| *v += 2);
+    *f.lock(0) = 3;
+    assert_eq!((f.read(0), f.read(10), f.read(999), f.read(500)), (3, 1, 2, 0));
+    assert_eq!(f.members[500].seqnum.version(), 0);
+}
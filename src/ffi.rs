@@ -0,0 +1,260 @@
+//! A C interface.
+//!
+//! With the `ffi` feature, these functions are exported unmangled, so C and
+//! C++ code linked into the same program can take part in the same seqlocks
+//! as Rust code, in the same process or through shared memory.  The
+//! declarations are in `include/seqloq.h`, which `cbindgen` can regenerate
+//! using `cbindgen.toml`.
+//!
+//! C sees a `seqloq *`, a handle to a `SeqloqC` (see the `layout` module)
+//! which knows how many bytes of data follow the 16-byte header.  Reads and
+//! writes are kept within them.  A `SeqloqC<T>` created on the Rust side can
+//! be passed to C as `seqloq_attach(ptr, size_of::<SeqloqC<T>>())`, as long
+//! as `T` needs no more than 16-byte alignment.
+
+use std::alloc::{self, Layout};
+use std::ffi::c_void;
+use std::mem::align_of;
+use std::ptr;
+
+use crate::{SeqloqC, RawMutex, copy, read_section};
+
+/// The part of a `SeqloqC` before the data.
+pub type SeqloqHeader = SeqloqC<[u8; 0]>;
+
+const HEADER: usize = 16;
+
+/// What C holds: where the seqloq is, and how much data it has.
+pub struct SeqloqHandle {
+    header: *mut SeqloqHeader,
+    data_len: usize,
+    /// Allocated by `seqloq_create`, rather than set up in a caller's region.
+    owned: bool,
+}
+
+impl SeqloqHandle {
+    #[inline]
+    fn data(&self) -> *mut u8 {
+        unsafe { (self.header as *mut u8).add(HEADER) }
+    }
+}
+
+fn layout(data_len: usize) -> Option<Layout> {
+    Layout::from_size_align(HEADER.checked_add(data_len)?, align_of::<SeqloqHeader>()).ok()
+}
+
+fn handle(header: *mut SeqloqHeader, data_len: usize, owned: bool) -> *mut SeqloqHandle {
+    Box::into_raw(Box::new(SeqloqHandle { header, data_len, owned }))
+}
+
+/// The number of bytes a seqloq with `data_len` bytes of data occupies, or
+/// 0 if that's more than a `size_t` can hold.
+#[unsafe(no_mangle)]
+pub extern "C" fn seqloq_size(data_len: usize) -> usize {
+    HEADER.checked_add(data_len).unwrap_or(0)
+}
+
+/// Allocate a seqloq with `data_len` bytes of data, all zero.  Returns null
+/// if allocation fails.
+#[unsafe(no_mangle)]
+pub extern "C" fn seqloq_create(data_len: usize) -> *mut SeqloqHandle {
+    let Some(l) = layout(data_len) else {
+        return ptr::null_mut();
+    };
+    // An all-zero header is unlocked at version 0.
+    let header = unsafe { alloc::alloc_zeroed(l) as *mut SeqloqHeader };
+    if header.is_null() {
+        return ptr::null_mut();
+    }
+    handle(header, data_len, true)
+}
+
+/// Free a seqloq handle, and with it the seqloq if it came from
+/// `seqloq_create`.  A region given to `seqloq_init` or `seqloq_attach` is
+/// left alone.
+///
+/// # Safety
+///
+/// `s` must be null or come from one of those functions, and nothing may use
+/// it afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_destroy(s: *mut SeqloqHandle) {
+    if s.is_null() {
+        return;
+    }
+    let h = unsafe { Box::from_raw(s) };
+    if let (true, Some(l)) = (h.owned, layout(h.data_len)) {
+        unsafe { alloc::dealloc(h.header as *mut u8, l) };
+    }
+}
+
+/// Set up an unlocked seqloq at version 0 at the start of `region`, with
+/// the rest of its `len` bytes as data, left as they are.  Returns null if
+/// `region` is too small or misaligned.
+///
+/// # Safety
+///
+/// `region` must be valid for `len` bytes of writes, and nothing else may
+/// use it while this runs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_init(region: *mut c_void, len: usize) -> *mut SeqloqHandle {
+    let s = unsafe { seqloq_attach(region, len) };
+    if !s.is_null() {
+        unsafe { ptr::write_bytes(region as *mut u8, 0, HEADER) };
+    }
+    s
+}
+
+/// Use a seqloq already set up at the start of `region`, with the rest of
+/// its `len` bytes as data.  Returns null if `region` is too small or
+/// misaligned.
+///
+/// # Safety
+///
+/// If the result isn't null, `region` must hold a seqloq and stay valid for
+/// `len` bytes for as long as the result is used.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_attach(region: *mut c_void, len: usize) -> *mut SeqloqHandle {
+    if len < HEADER || !(region as usize).is_multiple_of(align_of::<SeqloqHeader>()) {
+        return ptr::null_mut();
+    }
+    handle(region as *mut SeqloqHeader, len - HEADER, false)
+}
+
+/// The number of bytes of data.
+///
+/// # Safety
+///
+/// `s` must be a valid seqloq handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_data_len(s: *const SeqloqHandle) -> usize {
+    unsafe { (*s).data_len }
+}
+
+/// Copy the first `len` bytes of data into `buf`, or all of it if there's
+/// less.  Returns the version they were read at.
+///
+/// # Safety
+///
+/// `s` must be a valid seqloq handle, and `buf` must be valid for `len`
+/// bytes of writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_read(s: *const SeqloqHandle, buf: *mut c_void, len: usize)
+    -> u64
+{
+    let s = unsafe { &*s };
+    let h = unsafe { &*s.header };
+    let len = len.min(s.data_len);
+    read_section(&h.mutex, &h.seqnum, || unsafe {
+        copy::load_bytes(s.data(), buf as *mut u8, len)
+    }).1
+}
+
+/// The current version, which is odd while a write is in progress.
+///
+/// # Safety
+///
+/// `s` must be a valid seqloq handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_version(s: *const SeqloqHandle) -> u64 {
+    unsafe { (*(*s).header).seqnum.version() }
+}
+
+/// Replace the first `len` bytes of data with those at `buf`, in a single
+/// write.  Bytes past the end of the data are ignored.
+///
+/// # Safety
+///
+/// `s` must be a valid seqloq handle, and `buf` must be valid for `len`
+/// bytes of reads.  The calling thread mustn't already hold the lock.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_write(s: *mut SeqloqHandle, buf: *const c_void, len: usize) {
+    unsafe {
+        let len = len.min((*s).data_len);
+        let d = seqloq_lock(s);
+        copy::store_bytes(buf as *const u8, d as *mut u8, len);
+        seqloq_unlock(s);
+    }
+}
+
+/// Take the writer lock and start a write section.  Returns a pointer to
+/// the data, `seqloq_data_len` bytes of which may be modified until
+/// `seqloq_unlock`.
+///
+/// # Safety
+///
+/// `s` must be a valid seqloq handle.  The calling thread mustn't already
+/// hold the lock.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_lock(s: *mut SeqloqHandle) -> *mut c_void {
+    unsafe {
+        let h = &*(*s).header;
+        h.mutex.lock();
+        h.seqnum.write_begin();
+        (*s).data() as *mut c_void
+    }
+}
+
+/// End the write section and release the writer lock.
+///
+/// # Safety
+///
+/// The calling thread must hold the lock, from `seqloq_lock`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn seqloq_unlock(s: *mut SeqloqHandle) {
+    unsafe {
+        let h = &*(*s).header;
+        h.seqnum.write_end();
+        h.mutex.unlock();
+    }
+}
+
+#[test]
+fn header() {
+    assert_eq!(std::mem::size_of::<SeqloqHeader>(), HEADER);
+    assert_eq!(seqloq_size(32), 48);
+    assert_eq!(seqloq_size(usize::MAX - 15), 0);
+    assert!(seqloq_create(usize::MAX - 15).is_null());
+}
+
+#[test]
+fn c_api() {
+    unsafe {
+        let s = seqloq_create(8);
+        let mut buf = [0u8; 8];
+        assert_eq!(seqloq_read(s, buf.as_mut_ptr() as *mut c_void, 8), 0);
+        seqloq_write(s, b"seqloq!!".as_ptr() as *const c_void, 8);
+        let d = seqloq_lock(s) as *mut u8;
+        assert_eq!(seqloq_version(s), 3);
+        *d = b'S';
+        seqloq_unlock(s);
+        assert_eq!(seqloq_read(s, buf.as_mut_ptr() as *mut c_void, 8), 4);
+        assert_eq!(&buf, b"Seqloq!!");
+
+        // Reads and writes past the end stop there.
+        let mut long = [0u8; 12];
+        seqloq_write(s, b"overflowing!".as_ptr() as *const c_void, 12);
+        seqloq_read(s, long.as_mut_ptr() as *mut c_void, 12);
+        assert_eq!(&long, b"overflow\0\0\0\0");
+        assert_eq!(seqloq_data_len(s), 8);
+        seqloq_destroy(s);
+    }
+}
+
+#[test]
+fn shared_with_rust() {
+    let x = SeqloqC::new([1u64, 2]);
+    unsafe {
+        let s = seqloq_attach(&x as *const _ as *mut c_void, std::mem::size_of::<SeqloqC<[u64; 2]>>());
+        assert!(!s.is_null());
+        seqloq_write(s, [3u64, 4].as_ptr() as *const c_void, 16);
+        assert_eq!(x.read_versioned(), ([3, 4], 2));
+        x.write([5, 6]);
+        let mut buf = [0u64; 2];
+        seqloq_read(s, buf.as_mut_ptr() as *mut c_void, 16);
+        assert_eq!(buf, [5, 6]);
+        assert_eq!(seqloq_data_len(s), 16);
+        seqloq_destroy(s);
+    }
+    assert_eq!(x.read(), [5, 6]);
+}
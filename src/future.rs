@@ -0,0 +1,337 @@
+//! Futures for waiting on writes.
+//!
+//! These work with any executor: a waiting task's waker is woken when a
+//! writer releases the lock, and nothing else is needed.  Enable the `async`
+//! feature to use them, and the `stream` feature for `Snapshots`.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use crate::{Seqloq, SeqloqGuard, SeqCount, RawMutex};
+use crate::notify::Notify;
+
+#[cfg(feature = "event-listener")]
+use event_listener::EventListener;
+
+/// Identifies a waker registered with `Seqloq::register_waker`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WakerKey(u64);
+
+impl<T, M> Seqloq<T, M> {
+    /// Wake `waker` the next time a writer releases the lock.
+    ///
+    /// This is one-shot: once woken, the waker is forgotten, and must be
+    /// registered again for the next write.  As with any waker-based
+    /// wait, re-check for a change after registering, in case it happened
+    /// first.
+    ///
+    /// The `Seqloq` must not move while a waker is registered, or the waker
+    /// may miss its write.
+    #[inline]
+    pub fn register_waker(&self, waker: &Waker) -> WakerKey {
+        let key = WakerKey(crate::notify::new_id());
+        self.reregister_waker(key, waker);
+        key
+    }
+
+    /// Register `waker` under an existing key, replacing any waker still
+    /// registered under it.  Use this to re-arm after a wakeup.
+    #[inline]
+    pub fn reregister_waker(&self, key: WakerKey, waker: &Waker) {
+        self.notify.park(key.0, waker);
+    }
+
+    /// Forget the waker registered under `key`, if any.
+    #[inline]
+    pub fn deregister_waker(&self, key: WakerKey) {
+        self.notify.unpark(key.0);
+    }
+}
+
+/// Resolves once a write is published.  Returned by `Seqloq::changed`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Changed<'a> {
+    notify: &'a Notify,
+    seqnum: &'a SeqCount,
+    since: u64,
+    #[cfg(not(feature = "event-listener"))]
+    id: Option<u64>,
+    #[cfg(feature = "event-listener")]
+    listener: Option<EventListener>,
+}
+
+impl<T, M> Seqloq<T, M> {
+    /// Wait for the next write to be published.
+    ///
+    /// A write already in progress counts.  Resolves to the new version.
+    #[inline]
+    pub fn changed(&self) -> Changed<'_> {
+        self.changed_since(self.seqnum.version() & !1)
+    }
+
+    /// Wait for a write newer than version `since` to be published.
+    #[inline]
+    pub fn changed_since(&self, since: u64) -> Changed<'_> {
+        Changed {
+            notify: &self.notify,
+            seqnum: &self.seqnum,
+            since,
+            #[cfg(not(feature = "event-listener"))]
+            id: None,
+            #[cfg(feature = "event-listener")]
+            listener: None,
+        }
+    }
+}
+
+impl Changed<'_> {
+    /// The new version, if it's ready.
+    #[inline]
+    fn check(&self) -> Option<u64> {
+        let v = self.seqnum.version();
+        (v & 1 == 0 && v != self.since).then_some(v)
+    }
+}
+
+impl Future for Changed<'_> {
+    type Output = u64;
+
+    #[cfg(not(feature = "event-listener"))]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        if let Some(v) = self.check() {
+            return Poll::Ready(v);
+        }
+        let id = *self.id.get_or_insert_with(crate::notify::new_id);
+        self.notify.park(id, cx.waker());
+        match self.check() {
+            Some(v) => Poll::Ready(v),
+            None => Poll::Pending,
+        }
+    }
+
+    #[cfg(feature = "event-listener")]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        loop {
+            if let Some(v) = self.check() {
+                return Poll::Ready(v);
+            }
+            let notify = self.notify;
+            match &mut self.listener {
+                None => self.listener = Some(notify.listen()),
+                Some(l) => {
+                    if Pin::new(l).poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    self.listener = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "event-listener"))]
+impl Drop for Changed<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.notify.unpark(id);
+        }
+    }
+}
+
+/// A stream of snapshots, yielding the current value and then each newly
+/// published one.  Returned by `Seqloq::snapshots`.
+///
+/// A slow consumer doesn't see every value: writes published between two
+/// polls are coalesced into the latest.
+#[cfg(feature = "stream")]
+#[must_use = "streams do nothing unless polled"]
+pub struct Snapshots<'a, T, M> {
+    seqloq: &'a Seqloq<T, M>,
+    changed: Option<Changed<'a>>,
+}
+
+#[cfg(feature = "stream")]
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// A stream of `(value, version)` snapshots.
+    #[inline]
+    pub fn snapshots(&self) -> Snapshots<'_, T, M> {
+        Snapshots {
+            seqloq: self,
+            changed: None,
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T, M> futures_core::Stream for Snapshots<'_, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    type Item = (T, u64);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<(T, u64)>> {
+        if let Some(c) = &mut self.changed
+            && Pin::new(c).poll(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let (t, v) = self.seqloq.read_versioned();
+        self.changed = Some(self.seqloq.changed_since(v));
+        Poll::Ready(Some((t, v)))
+    }
+}
+
+/// Resolves to a write guard once the lock is free.  Returned by
+/// `Seqloq::lock_async`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Lock<'a, T, M> {
+    seqloq: &'a Seqloq<T, M>,
+    id: Option<u64>,
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Lock for exclusive access, without blocking the thread.
+    ///
+    /// A task waiting for the lock is woken when the holder releases it.
+    /// This only works if every writer releases the lock through this
+    /// `Seqloq`: a guard, a transaction, `write` or `update`.
+    #[inline]
+    pub fn lock_async(&self) -> Lock<'_, T, M> {
+        Lock {
+            seqloq: self,
+            id: None,
+        }
+    }
+}
+
+impl<'a, T, M> Future for Lock<'a, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    type Output = SeqloqGuard<'a, T, M>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<SeqloqGuard<'a, T, M>> {
+        let seqloq = self.seqloq;
+        if let Some(g) = seqloq.try_lock() {
+            return Poll::Ready(g);
+        }
+        let id = *self.id.get_or_insert_with(crate::notify::new_id);
+        seqloq.notify.park(id, cx.waker());
+        match seqloq.try_lock() {
+            Some(g) => Poll::Ready(g),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T, M> Drop for Lock<'_, T, M> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.seqloq.notify.unpark(id);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut f = std::pin::pin!(f);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(r) => return r,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn changed() {
+    let x = Seqloq::new(0u32);
+    let c = x.changed();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            x.write(1);
+        });
+        assert_eq!(block_on(c), 2);
+    });
+    assert_eq!(block_on(x.changed_since(0)), 2);
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn snapshots() {
+    use futures_core::Stream;
+
+    let x = Seqloq::new(0u32);
+    let mut s = x.snapshots();
+    let mut next = || block_on(std::future::poll_fn(|cx| Pin::new(&mut s).poll_next(cx)));
+
+    assert_eq!(next(), Some((0, 0)));
+    x.write(1);
+    x.write(2);
+    assert_eq!(next(), Some((2, 4)));
+}
+
+#[test]
+fn register_waker() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let count = Arc::new(Count(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let x = Seqloq::new(0u32);
+
+    let key = x.register_waker(&waker);
+    x.write(1);
+    x.write(2);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+
+    x.reregister_waker(key, &waker);
+    x.deregister_waker(key);
+    x.write(3);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn lock_async() {
+    let x = Seqloq::new(0u32);
+    let g = x.lock();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            *block_on(x.lock_async()) += 1;
+        });
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        drop(g);
+    });
+    assert_eq!(x.read(), 1);
+}
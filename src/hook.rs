@@ -0,0 +1,145 @@
+//! Points in the protocol where the crate's own tests can stop a thread.
+//!
+//! A test installs a hook on a thread with `set`, and the protocol calls it
+//! whenever that thread reaches one of the `Point`s.  The hook can block,
+//! say on a channel, to hold the thread in that state while others run.
+//! That pins down an interleaving which sleeps could only make likely.
+//!
+//! Outside tests, and in tests without `std`, `at` does nothing and
+//! compiles away.
+
+/// A place in the protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Point {
+    /// A writer has made the count odd, and not touched the data yet.
+    WriteBegun,
+    /// A writer holds the lock and has a whole new value ready, but hasn't
+    /// started the write section to copy it in.
+    BeforeStore,
+    /// A reader has read the data, and is about to check the count.
+    BeforeValidate,
+}
+
+#[cfg(not(all(test, feature = "std")))]
+#[inline(always)]
+pub(crate) fn at(_: Point) { }
+
+#[cfg(all(test, feature = "std"))]
+use std::cell::Cell;
+
+#[cfg(all(test, feature = "std"))]
+type Hook = Box<dyn FnMut(Point)>;
+
+#[cfg(all(test, feature = "std"))]
+thread_local! {
+    static HOOK: Cell<Option<Hook>> = const { Cell::new(None) };
+}
+
+/// Call `hook` at every point this thread reaches, from now on.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn set<F: FnMut(Point) + 'static>(hook: F) {
+    HOOK.with(|h| h.set(Some(Box::new(hook))));
+}
+
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn at(point: Point) {
+    // Taken out while it runs, so that it can use a seqlock itself.
+    if let Some(mut hook) = HOOK.with(|h| h.take()) {
+        hook(point);
+        HOOK.with(|h| h.set(Some(hook)));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use std::sync::{Arc, mpsc};
+#[cfg(all(test, feature = "std"))]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(all(test, feature = "std"))]
+use std::thread;
+
+#[cfg(all(test, feature = "std"))]
+use crate::Seqloq;
+
+/// A thread which `stop_at` is holding.
+#[cfg(all(test, feature = "std"))]
+struct Stopped<R> {
+    go: mpsc::Sender<()>,
+    handle: thread::JoinHandle<R>,
+    /// How many times the thread has reached the point.
+    hits: Arc<AtomicU32>,
+}
+
+#[cfg(all(test, feature = "std"))]
+impl<R> Stopped<R> {
+    /// Let it go on, and wait for it to finish.
+    fn finish(self) -> R {
+        self.go.send(()).unwrap();
+        self.handle.join().unwrap()
+    }
+}
+
+/// Run `f` on a new thread, stopping it the first time it reaches `point`.
+/// Returns once it's stopped there.
+#[cfg(all(test, feature = "std"))]
+fn stop_at<F, R>(point: Point, f: F) -> Stopped<R>
+    where F: FnOnce() -> R + Send + 'static,
+          R: Send + 'static,
+{
+    let (stopped_tx, stopped_rx) = mpsc::channel();
+    let (go, go_rx) = mpsc::channel();
+    let hits = Arc::new(AtomicU32::new(0));
+    let counter = hits.clone();
+    let handle = thread::spawn(move || {
+        set(move |p| if p == point && counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            stopped_tx.send(()).unwrap();
+            go_rx.recv().unwrap();
+        });
+        f()
+    });
+    stopped_rx.recv().unwrap();
+    Stopped { go, handle, hits }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn write_during_read() {
+    let x: &'static Seqloq<[u64; 4]> = Box::leak(Box::new(Seqloq::new([0; 4])));
+    let reader = stop_at(Point::BeforeValidate, || x.read());
+    // The reader has its copy of the old value.  A whole write now must
+    // make it try again.
+    x.write([1; 4]);
+    let hits = reader.hits.clone();
+    assert_eq!(reader.finish(), [1; 4]);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn read_during_write() {
+    let x: &'static Seqloq<[u64; 4]> = Box::leak(Box::new(Seqloq::new([0; 4])));
+    let writer = stop_at(Point::WriteBegun, || x.write([1; 4]));
+    assert!(x.write_pending() && x.is_locked());
+    assert_eq!(x.version(), 1);
+    assert!(x.try_lock().is_none());
+
+    // A reader can't finish until the writer does, and then sees its value.
+    let reader = thread::spawn(|| x.read());
+    writer.finish();
+    assert_eq!(reader.join().unwrap(), [1; 4]);
+    assert_eq!(x.version(), 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn update_before_store() {
+    let x: &'static Seqloq<u64> = Box::leak(Box::new(Seqloq::new(1)));
+    let writer = stop_at(Point::BeforeStore, || x.update(|v| *v += 1));
+    // The new value is only in the writer's private copy so far, so
+    // readers go on undisturbed.
+    assert!(x.is_locked() && !x.write_pending());
+    assert_eq!(x.read(), 1);
+    assert_eq!(x.version(), 0);
+    writer.finish();
+    assert_eq!(x.read(), 2);
+    assert_eq!(x.version(), 2);
+}
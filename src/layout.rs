@@ -0,0 +1,208 @@
+//! A seqlock with a fixed, C-compatible layout.
+//!
+//! The layout of `Seqloq` is private and may change.  `SeqloqC` is
+//! `#[repr(C)]` and commits to this one:
+//!
+//! | offset | size | field                                    |
+//! |--------|------|------------------------------------------|
+//! | 0      | 8    | sequence number, a native-endian `u64`   |
+//! | 8      | 4    | writer lock word, a native-endian `u32`  |
+//! | 12     | 4    | writer process ID, a native-endian `u32` |
+//! | 16     |      | data, aligned as `T` requires            |
+//!
+//! so that anything which follows the same protocol, in another language or
+//! another process, can share it:
+//!
+//! * The lock word is 0 when unlocked, 1 when locked, and 2 when locked with
+//!   writers (possibly) sleeping on it as a futex.  Lock by compare-and-swap
+//!   from 0 to 1; unlock by swapping in 0, and wake one sleeper if the old
+//!   value was 2.
+//! * Having locked, store your process ID in the next word.  Store 0 there
+//!   before unlocking.
+//! * A writer holding the lock increments the sequence number, which makes
+//!   it odd, issues a release fence, writes the data, and increments the
+//!   sequence number again.
+//! * A reader loads the sequence number, waiting while it's odd, copies the
+//!   data, issues an acquire fence, and retries if the sequence number has
+//!   changed.
+//!
+//! All accesses to the sequence number are sequentially consistent, and all
+//! accesses to the lock word are atomic.  The data sits at offset 16 unless
+//! `T` needs more than 16-byte alignment.
+
+// The layout needs a 64-bit sequence number, so this module only exists on
+// targets with 64-bit atomics.
+
+use std::cell::UnsafeCell;
+
+use crate::{SeqloqGuard, SeqloqStagedGuard, SeqCount, SharedLock, Parts};
+
+/// A seqlock with a stable `#[repr(C)]` layout.  See the module
+/// documentation.
+///
+/// Writers always exclude each other with a `SharedLock`, since the lock word
+/// is part of the layout, and it may be shared with other processes (see the
+/// `shm` module).  There are no change notifications, which would
+/// need state outside the structure.
+#[repr(C)]
+pub struct SeqloqC<T> {
+    pub(crate) seqnum: SeqCount,
+    pub(crate) mutex: SharedLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SeqloqC<T> { }
+unsafe impl<T: Send> Sync for SeqloqC<T> { }
+
+impl<T> SeqloqC<T>
+    where T: Send + Copy,
+{
+    #[inline]
+    pub fn new(t: T) -> SeqloqC<T> {
+        SeqloqC {
+            seqnum: SeqCount::new(),
+            mutex: SharedLock::new(),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, T, SharedLock> {
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &self.seqnum,
+            data: self.data.get(),
+        }
+    }
+
+    /// As `Seqloq::peek`.
+    #[inline]
+    pub fn peek<F, R>(&self, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts().peek_versioned(f).0
+    }
+
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.parts().read()
+    }
+
+    /// As `Seqloq::read_versioned`.
+    #[inline]
+    pub fn read_versioned(&self) -> (T, u64) {
+        self.parts().peek_versioned(|p| unsafe { crate::copy::load(p) })
+    }
+
+    /// As `Seqloq::version`.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    /// As `Seqloq::lock`.
+    #[inline]
+    pub fn lock(&self) -> SeqloqGuard<'_, T, SharedLock> {
+        self.parts().lock()
+    }
+
+    /// As `Seqloq::try_lock`.
+    #[inline]
+    pub fn try_lock(&self) -> Option<SeqloqGuard<'_, T, SharedLock>> {
+        self.parts().try_lock()
+    }
+
+    /// As `Seqloq::lock_staged`.
+    #[inline]
+    pub fn lock_staged(&self) -> SeqloqStagedGuard<'_, T, SharedLock> {
+        self.parts().lock_staged()
+    }
+
+    /// As `Seqloq::write`.
+    #[inline]
+    pub fn write(&self, t: T) {
+        self.parts().write(t)
+    }
+
+    /// As `Seqloq::update`.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts().update(f)
+    }
+
+    /// Release the lock if it was left behind by a process which died.
+    /// Returns whether there was such a lock.
+    ///
+    /// If the process died in the middle of a write, readers would wait for
+    /// ever.  Here `repair` gets the chance to put the data back in order
+    /// before the write section is ended on the dead writer's behalf.
+    ///
+    /// A process that dies just after locking, before it records its ID,
+    /// can't be detected.
+    #[cfg(all(not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))]
+    pub fn recover<F>(&self, repair: F) -> bool
+        where F: FnOnce(&mut T),
+    {
+        if !self.mutex.take_over() {
+            return false;
+        }
+        if self.seqnum.version() & 1 == 1 {
+            // Readers are all waiting for the write section to end.
+            unsafe {
+                let mut t = crate::copy::load(self.data.get());
+                repair(&mut t);
+                crate::copy::store(self.data.get(), t);
+            }
+            self.seqnum.write_end();
+        }
+        unsafe { crate::RawMutex::unlock(&self.mutex) };
+        true
+    }
+}
+
+#[cfg(not(any(loom, shuttle)))]
+#[test]
+fn layout() {
+    use std::mem::{offset_of, size_of};
+
+    assert_eq!(offset_of!(SeqloqC<u8>, seqnum), 0);
+    assert_eq!(offset_of!(SeqloqC<u8>, mutex), 8);
+    assert_eq!(offset_of!(SeqloqC<u8>, data), 16);
+    assert_eq!(offset_of!(SeqloqC<[u64; 4]>, data), 16);
+    assert_eq!(size_of::<SeqloqC<[u64; 4]>>(), 48);
+
+    let x = SeqloqC::new([0u64; 4]);
+    x.lock()[1] = 1;
+    x.write([2; 4]);
+    assert_eq!(x.read_versioned(), ([2; 4], 4));
+}
+
+#[cfg(all(not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))]
+#[test]
+fn recover() {
+    use crate::RawMutex;
+
+    let x = SeqloqC::new(1u32);
+    assert!(!x.recover(|_| unreachable!()));
+
+    // Held by this process, which is alive.
+    x.mutex.lock();
+    assert_eq!(x.mutex.owner(), Some(std::process::id()));
+    assert!(!x.recover(|_| unreachable!()));
+
+    // Pretend the holder was a process which has since exited, in the
+    // middle of a write.
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let dead = child.id();
+    child.wait().unwrap();
+    x.mutex.owner.store(dead, crate::sync::Ordering::Relaxed);
+    x.seqnum.write_begin();
+    assert!(x.recover(|t| *t = 2));
+    assert_eq!(x.read_versioned(), (2, 2));
+    assert!(x.mutex.owner().is_none());
+    assert!(!x.mutex.is_locked());
+}
@@ -0,0 +1,183 @@
+//! Left-right: wait-free reads of data that isn't `Copy`.
+//!
+//! A `LeftRight` keeps two instances of the data.  Readers always use the
+//! active one, in place, without copying or retrying.  The writer applies
+//! each change to the inactive instance, makes it active, waits for readers
+//! of the old instance to leave, and then applies the same change again so
+//! the two stay in step.
+//!
+//! Writes therefore run twice and have to wait for readers, so this suits
+//! data which is read far more often than it's written.  The algorithm is
+//! Ramalhete and Correia's "Left-Right" (2015).
+
+use std::cell::UnsafeCell;
+use std::ops::Deref;
+
+use crate::{RawMutex, RawGuard, WordLock};
+use crate::sync::{AtomicU32, Ordering, yield_now};
+
+/// Two copies of `T`, kept in step, with wait-free readers.
+pub struct LeftRight<T, M = WordLock> {
+    mutex: M,
+    /// Which instance readers should use.
+    active: AtomicU32,
+    readers: Readers,
+    instances: [UnsafeCell<T>; 2],
+}
+
+unsafe impl<T: Send, M: Send> Send for LeftRight<T, M> { }
+unsafe impl<T: Send + Sync, M: Sync> Sync for LeftRight<T, M> { }
+
+/// Shared access to the active instance of a `LeftRight`.
+///
+/// The writer can't finish a write while this is held, so don't hold it for
+/// long.
+pub struct LeftRightGuard<'a, T> {
+    readers: &'a AtomicU32,
+    data: &'a T,
+}
+
+impl<T: Clone> LeftRight<T> {
+    #[inline]
+    pub fn new(t: T) -> LeftRight<T> {
+        LeftRight::with_mutex(t, WordLock::new())
+    }
+}
+
+impl<T: Clone, M: RawMutex> LeftRight<T, M> {
+    /// Use `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(t: T, mutex: M) -> LeftRight<T, M> {
+        LeftRight {
+            mutex,
+            active: AtomicU32::new(0),
+            readers: Readers::new(),
+            instances: [UnsafeCell::new(t.clone()), UnsafeCell::new(t)],
+        }
+    }
+}
+
+impl<T, M: RawMutex> LeftRight<T, M> {
+    /// Borrow the current data.
+    #[inline]
+    pub fn read(&self) -> LeftRightGuard<'_, T> {
+        let readers = self.readers.enter();
+        let i = self.active.load(Ordering::SeqCst);
+        LeftRightGuard {
+            readers,
+            data: unsafe { &*self.instances[i as usize].get() },
+        }
+    }
+
+    /// Apply `f` to the data.
+    ///
+    /// `f` runs twice, once on each instance, and must make the same change
+    /// both times.
+    pub fn write<F>(&self, mut f: F)
+        where F: FnMut(&mut T),
+    {
+        let _guard = RawGuard::lock(&self.mutex);
+
+        let active = self.active.load(Ordering::Relaxed);
+        f(unsafe { &mut *self.instances[1 - active as usize].get() });
+        self.active.store(1 - active, Ordering::SeqCst);
+        self.readers.wait_for_readers();
+        f(unsafe { &mut *self.instances[active as usize].get() });
+    }
+}
+
+/// Counts readers, in a way that lets a writer wait for the ones that
+/// started before some point without being starved by ones that start later.
+///
+/// Readers register in one of two counters, chosen by `version`.  To wait,
+/// the writer drains the counter not in use, switches new readers over to
+/// it, and then drains the other.  The caller must exclude other writers.
+pub(crate) struct Readers {
+    version: AtomicU32,
+    counts: [AtomicU32; 2],
+}
+
+impl Readers {
+    #[inline]
+    pub(crate) fn new() -> Readers {
+        Readers {
+            version: AtomicU32::new(0),
+            counts: [AtomicU32::new(0), AtomicU32::new(0)],
+        }
+    }
+
+    /// Register a reader.  Decrement the returned counter, with `Release`
+    /// ordering, when done.
+    #[inline]
+    pub(crate) fn enter(&self) -> &AtomicU32 {
+        let v = self.version.load(Ordering::SeqCst);
+        let count = &self.counts[v as usize];
+        count.fetch_add(1, Ordering::SeqCst);
+        count
+    }
+
+    /// Wait for every reader that entered before this call to leave.
+    pub(crate) fn wait_for_readers(&self) {
+        let v = self.version.load(Ordering::Relaxed);
+        self.drain(1 - v);
+        self.version.store(1 - v, Ordering::SeqCst);
+        self.drain(v);
+    }
+
+    #[inline]
+    fn drain(&self, v: u32) {
+        while self.counts[v as usize].load(Ordering::SeqCst) != 0 {
+            yield_now();
+        }
+    }
+}
+
+impl<T> Deref for LeftRightGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<T> Drop for LeftRightGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+#[test]
+fn left_right() {
+    let x = LeftRight::new(vec![1, 2]);
+    {
+        let r = x.read();
+        assert_eq!(*r, [1, 2]);
+    }
+    x.write(|v| v.push(3));
+    assert_eq!(*x.read(), [1, 2, 3]);
+    x.write(|v| v.retain(|&n| n != 2));
+    assert_eq!(*x.read(), [1, 3]);
+}
+
+#[test]
+fn left_right_threads() {
+    let x = LeftRight::new(Vec::new());
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..1000 {
+                x.write(|v| v.push(i));
+            }
+        });
+        loop {
+            let r = x.read();
+            assert!(r.iter().enumerate().all(|(i, &n)| i == n));
+            if r.len() == 1000 {
+                break;
+            }
+            drop(r);
+            std::thread::yield_now();
+        }
+    });
+}
@@ -1,39 +1,525 @@
-#![feature(unsafe_destructor)]
-#![feature(core, std_misc, io, test)]
 #![deny(warnings)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-extern crate time;
-extern crate test;
+// The test harness runs on the host, so tests can use `std` regardless.
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
 
-use std::mem;
-use std::ops::{Deref, DerefMut};
-use std::cell::UnsafeCell;
-use std::sync::{Mutex, MutexGuard};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use core::ops::{Deref, DerefMut};
+use core::cell::UnsafeCell;
 
+use crate::notify::Notify;
+
+#[cfg(test)]
+use crate::sync::{Seq, Ordering};
+
+#[cfg(feature = "std")]
+pub use arc::SeqloqArc;
+#[cfg(feature = "std")]
+pub use boxed::SeqloqBox;
+#[cfg(feature = "std")]
+pub use builder::Builder;
+#[cfg(feature = "std")]
+pub use cell::{SeqloqCell, NoPadding, AnyBitPattern};
+pub use seqcount::SeqCount;
+#[cfg(feature = "std")]
+pub use family::SeqloqFamily;
+#[cfg(feature = "std")]
+pub use leftright::{LeftRight, LeftRightGuard};
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub use layout::SeqloqC;
+#[cfg(feature = "std")]
+pub use map::SeqloqHashMap;
+pub use mutex::{RawMutex, WordLock, SpinLock, Padded, Named};
+#[cfg(feature = "std")]
+pub use mutex::{SharedLock, StdMutex};
+#[cfg(feature = "std")]
+pub use option::SeqloqOption;
+#[cfg(feature = "std")]
+pub use policy::{ReaderPriority, RateLimited};
+#[cfg(feature = "std")]
+pub use pair::{SeqloqPair, SeqloqTriple, SeqloqQuad};
+#[cfg(feature = "std")]
+pub use project::SeqloqRef;
+#[cfg(feature = "std")]
+pub use ptr::SeqloqPtr;
+#[cfg(feature = "std")]
+pub use replica::{SeqloqReplicated, ReplicaReader};
+#[cfg(feature = "std")]
+pub use slice::SeqloqSlice;
+#[cfg(feature = "std")]
+pub use split::{Writer, Reader};
+#[cfg(feature = "std")]
+pub use stats::{Stats, SeqloqStats};
+#[cfg(feature = "std")]
+pub use stamp::Timestamped;
+#[cfg(feature = "tracing")]
+pub use trace::Traced;
+#[cfg(feature = "metrics")]
+pub use meter::Metered;
+#[cfg(feature = "critical-section")]
+pub use cs::CsLock;
+/// Fields named like a generated method are rejected:
+///
+/// ```compile_fail
+/// #[derive(seqloq::Seqloqed, Clone, Copy)]
+/// struct Bad { version: u64 }
+/// ```
+#[cfg(feature = "derive")]
+pub use seqloq_derive::Seqloqed;
+#[cfg(feature = "std")]
+pub use subscribe::Subscription;
+#[cfg(feature = "std")]
+pub use triple::{triple_buffer, TripleWriter, TripleReader};
+#[cfg(feature = "std")]
+pub use tx::{SeqloqTx, Transaction, Conflict};
+#[cfg(feature = "std")]
+pub use updater::{Updater, Drain};
+#[cfg(feature = "std")]
+pub use vec::SeqloqVec;
+
+// Without the `std` feature, only the core of the crate is built: the
+// protocol, `Seqloq` itself, and the writer mutexes that need no operating
+// system.  That's enough for a bare-metal target, with `critical-section`.
+
+pub mod mutex;
+pub mod seqcount;
+
+#[cfg(feature = "std")]
+pub mod arc;
+#[cfg(feature = "std")]
+pub mod boxed;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod cell;
+#[cfg(feature = "std")]
+pub mod combine;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod family;
+#[cfg(feature = "std")]
+pub mod leftright;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod map;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod option;
+#[cfg(feature = "std")]
+pub mod pair;
+#[cfg(feature = "std")]
+pub mod persist;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod project;
+#[cfg(feature = "std")]
+pub mod ptr;
+#[cfg(feature = "std")]
+pub mod refresh;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod shm;
+#[cfg(feature = "std")]
+pub mod replica;
+#[cfg(feature = "std")]
+pub mod slice;
+#[cfg(feature = "std")]
+pub mod split;
+#[cfg(feature = "std")]
+pub mod stamp;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod subscribe;
+#[cfg(feature = "std")]
 pub mod tests;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod triple;
+#[cfg(feature = "std")]
+pub mod tx;
+#[cfg(feature = "std")]
+pub mod updater;
+#[cfg(feature = "std")]
+pub mod vec;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod volatile;
+
+mod copy;
+mod hook;
+mod notify;
+mod owner;
+mod sync;
+
+#[cfg(feature = "async")]
+pub mod future;
+
+#[cfg(feature = "lock-api")]
+pub mod raw;
+
+#[cfg(feature = "tokio")]
+pub mod watch;
+
+#[cfg(feature = "tracing")]
+pub mod trace;
+
+#[cfg(feature = "registry")]
+pub mod registry;
+
+#[cfg(feature = "metrics")]
+pub mod meter;
+
+#[cfg(all(feature = "ffi", target_has_atomic = "64"))]
+pub mod ffi;
+
+#[cfg(feature = "critical-section")]
+pub mod cs;
+
+#[cfg(feature = "critical-section")]
+pub mod embedded;
 
 /// Reader-writer lock with writer priority and optimistic reads.
-pub struct Seqloq<T> {
-    mutex: Mutex<()>,
-    seqnum: AtomicUsize,
+///
+/// Writers exclude each other using `M`, which by default is a one-word
+/// futex-based lock.
+///
+/// A reader is only fooled if the sequence number comes back around to the
+/// same value during a single read.  The counter is 64 bits on any target
+/// with 64-bit atomics, which rules that out.  On other targets it's
+/// pointer-sized, and a read would have to overlap 2^31 complete writes.
+pub struct Seqloq<T, M = WordLock> {
+    mutex: M,
+    notify: Notify,
+    seqnum: SeqCount,
     data: UnsafeCell<T>,
 }
 
+unsafe impl<T: Send, M: Send> Send for Seqloq<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for Seqloq<T, M> { }
+
+impl<T, M> Seqloq<T, M> {
+    /// Start a write section.  The caller must hold the writer mutex.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn begin_write(&self) {
+        self.seqnum.write_begin();
+    }
+
+    /// End a write section.  The caller must hold the writer mutex.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn end_write(&self) {
+        self.seqnum.write_end();
+    }
+
+    /// Take the writer mutex, waking waiters when it's released.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn guard(&self) -> RawGuard<'_, M>
+        where M: RawMutex,
+    {
+        RawGuard::lock_notify(&self.mutex, Some(&self.seqnum), Some(&self.notify))
+    }
+
+    /// Overwrite the data in a single write section.  The caller must hold
+    /// the writer mutex.
+    #[inline]
+    unsafe fn store(&self, t: T) {
+        unsafe {
+            store(&self.seqnum, self.data.get(), t);
+        }
+    }
+
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, T, M> {
+        Parts {
+            mutex: &self.mutex,
+            notify: Some(&self.notify),
+            seqnum: &self.seqnum,
+            data: self.data.get(),
+        }
+    }
+}
+
+/// Overwrite `*dst` in a single write section.  The caller must hold the
+/// writer mutex.
+#[inline]
+unsafe fn store<T>(seqnum: &SeqCount, dst: *mut T, t: T) {
+    hook::at(hook::Point::BeforeStore);
+    seqnum.write_begin();
+    unsafe {
+        copy::store(dst, t);
+    }
+    seqnum.write_end();
+}
+
+/// Run `f` as a read section until no write overlaps it, returning its
+/// result and the version it was validated against.  Retries are reported
+/// to the mutex, for the sake of fairness policies.
 #[inline(always)]
-fn non_atomic_increment(x: &AtomicUsize) {
-    let v = x.load(Ordering::SeqCst);
-    x.store(v+1, Ordering::SeqCst);
+fn read_section<M, F, R>(mutex: &M, seqnum: &SeqCount, f: F) -> (R, u64)
+    where M: RawMutex,
+          F: FnMut() -> R,
+{
+    let (res, version, _) = read_section_counted(mutex, seqnum, f);
+    (res, version)
 }
 
-unsafe impl<T: Send> Send for Seqloq<T> { }
-unsafe impl<T: Send> Sync for Seqloq<T> { }
+/// Like `read_section`, but also return the number of retries.
+#[inline(always)]
+fn read_section_counted<M, F, R>(mutex: &M, seqnum: &SeqCount, mut f: F) -> (R, u64, u32)
+    where M: RawMutex,
+          F: FnMut() -> R,
+{
+    let mut retries = 0;
+    loop {
+        let start = seqnum.read_begin();
+        let res = f();
+        if !seqnum.read_retry(start) {
+            mutex.reader_done(retries);
+            return (res, start, retries);
+        }
+        retries += 1;
+        mutex.reader_retried(retries);
+    }
+}
+
+/// Holds the writer mutex, and releases it on drop.  If there's a
+/// `Notify`, waiters are woken after the release, and subscribers are told
+/// if anything was written.
+struct RawGuard<'a, M: RawMutex + 'a> {
+    mutex: &'a M,
+    seqnum: Option<&'a SeqCount>,
+    notify: Option<&'a Notify>,
+    /// The version when the lock was taken.
+    start: u64,
+}
+
+impl<'a, M: RawMutex> RawGuard<'a, M> {
+    /// With no sequence count to go by, every release counts as a write.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn lock(mutex: &'a M) -> RawGuard<'a, M> {
+        RawGuard::lock_notify(mutex, None, None)
+    }
+
+    #[inline]
+    fn lock_notify(mutex: &'a M, seqnum: Option<&'a SeqCount>, notify: Option<&'a Notify>)
+        -> RawGuard<'a, M>
+    {
+        owner::check(addr(mutex));
+        mutex.lock();
+        owner::acquired(addr(mutex));
+        RawGuard::held(mutex, seqnum, notify)
+    }
+
+    #[inline]
+    fn try_lock_notify(mutex: &'a M, seqnum: Option<&'a SeqCount>, notify: Option<&'a Notify>)
+        -> Option<RawGuard<'a, M>>
+    {
+        mutex.try_lock().then(|| {
+            owner::acquired(addr(mutex));
+            RawGuard::held(mutex, seqnum, notify)
+        })
+    }
+
+    #[inline(always)]
+    fn held(mutex: &'a M, seqnum: Option<&'a SeqCount>, notify: Option<&'a Notify>)
+        -> RawGuard<'a, M>
+    {
+        RawGuard {
+            mutex,
+            seqnum,
+            notify,
+            start: seqnum.map_or(0, SeqCount::version),
+        }
+    }
+}
+
+#[inline(always)]
+fn addr<M>(mutex: &M) -> usize {
+    mutex as *const M as usize
+}
+
+impl<M: RawMutex> Drop for RawGuard<'_, M> {
+    #[inline]
+    fn drop(&mut self) {
+        let written = self.seqnum.is_none_or(|s| s.version() != self.start);
+        if written {
+            self.mutex.writer_published();
+        }
+        owner::released(addr(self.mutex));
+        unsafe {
+            self.mutex.unlock();
+        }
+        if let (Some(n), Some(seqnum)) = (self.notify, self.seqnum) {
+            if written {
+                n.notify(seqnum);
+            } else {
+                n.notify_release();
+            }
+        }
+    }
+}
 
 /// Represents exclusive, read/write access.
-pub struct SeqloqGuard<'a, T: 'a> {
-    seqloq: &'a Seqloq<T>,
-    #[allow(dead_code)] guard: MutexGuard<'a, ()>,
+///
+/// With the `atomic-copy` feature, writes go to a private copy which is
+/// published when the guard is dropped (or by `publish`).
+pub struct SeqloqGuard<'a, T: 'a, M: RawMutex + 'a = WordLock> {
+    seqnum: &'a SeqCount,
+    #[allow(dead_code)] guard: RawGuard<'a, M>,
     ptr: *mut T,
+    #[cfg(feature = "atomic-copy")]
+    shadow: T,
+    published: bool,
+}
+
+/// Represents exclusive access, with writes going to a private copy.
+///
+/// Nothing is visible to readers until `commit` is called.
+pub struct SeqloqStagedGuard<'a, T: 'a, M: RawMutex + 'a = WordLock> {
+    seqnum: &'a SeqCount,
+    #[allow(dead_code)] guard: RawGuard<'a, M>,
+    ptr: *mut T,
+    staged: T,
+}
+
+/// Iterator returned by `Seqloq::changes`.
+pub struct Changes<'a, T, M = WordLock> {
+    seqloq: &'a Seqloq<T, M>,
+    version: u64,
+}
+
+impl<T, M> Iterator for Changes<'_, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        let (t, v) = self.seqloq.wait_for_change(self.version);
+        self.version = v;
+        Some(t)
+    }
+}
+
+/// The pieces of a seqlock, wherever its data lives.
+///
+/// `Seqloq` and its heap, pointer and per-element variants are all
+/// implemented in terms of this.
+struct Parts<'a, T, M> {
+    mutex: &'a M,
+    notify: Option<&'a Notify>,
+    seqnum: &'a SeqCount,
+    data: *mut T,
+}
+
+impl<'a, T, M> Parts<'a, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    #[inline(always)]
+    fn guard(&self) -> RawGuard<'a, M> {
+        RawGuard::lock_notify(self.mutex, Some(self.seqnum), self.notify)
+    }
+
+    #[inline]
+    fn peek_versioned<F, R>(&self, f: F) -> (R, u64)
+        where F: FnMut(*const T) -> R,
+    {
+        let (res, version, _) = self.peek_counted(f);
+        (res, version)
+    }
+
+    /// Like `peek_versioned`, but also return the number of retries.
+    #[inline]
+    fn peek_counted<F, R>(&self, mut f: F) -> (R, u64, u32)
+        where F: FnMut(*const T) -> R,
+    {
+        read_section_counted(self.mutex, self.seqnum, || {
+            #[cfg(not(feature = "atomic-copy"))]
+            let res = f(self.data);
+
+            #[cfg(feature = "atomic-copy")]
+            let res = {
+                let snapshot = unsafe { copy::load(self.data) };
+                f(&snapshot)
+            };
+
+            res
+        })
+    }
+
+    #[inline]
+    fn read(&self) -> T {
+        self.peek_versioned(|x| unsafe { copy::load(x) }).0
+    }
+
+    #[inline]
+    fn lock(&self) -> SeqloqGuard<'a, T, M> {
+        self.lock_with(self.guard())
+    }
+
+    #[inline]
+    fn try_lock(&self) -> Option<SeqloqGuard<'a, T, M>> {
+        RawGuard::try_lock_notify(self.mutex, Some(self.seqnum), self.notify)
+            .map(|g| self.lock_with(g))
+    }
+
+    /// Build a guard around the mutex, already held.
+    ///
+    /// The write section doesn't start until the first mutable access, so
+    /// a guard which is only read through publishes nothing.
+    #[inline]
+    fn lock_with(&self, guard: RawGuard<'a, M>) -> SeqloqGuard<'a, T, M> {
+        SeqloqGuard {
+            seqnum: self.seqnum,
+            guard,
+            ptr: self.data,
+            #[cfg(feature = "atomic-copy")]
+            shadow: unsafe { *self.data },
+            published: true,
+        }
+    }
+
+    #[inline]
+    fn lock_staged(&self) -> SeqloqStagedGuard<'a, T, M> {
+        let guard = self.guard();
+        SeqloqStagedGuard {
+            seqnum: self.seqnum,
+            guard,
+            ptr: self.data,
+            staged: unsafe { *self.data },
+        }
+    }
+
+    #[inline]
+    fn write(&self, t: T) {
+        let _guard = self.guard();
+        unsafe {
+            store(self.seqnum, self.data, t);
+        }
+    }
+
+    #[inline]
+    fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        let _guard = self.guard();
+        let mut shadow = unsafe { *self.data };
+        let res = f(&mut shadow);
+        unsafe {
+            store(self.seqnum, self.data, shadow);
+        }
+        res
+    }
 }
 
 impl<T> Seqloq<T>
@@ -41,9 +527,21 @@ impl<T> Seqloq<T>
 {
     #[inline]
     pub fn new(t: T) -> Seqloq<T> {
+        Seqloq::with_mutex(t, WordLock::new())
+    }
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Create a `Seqloq` which uses `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(t: T, mutex: M) -> Seqloq<T, M> {
         Seqloq {
-            mutex: Mutex::new(()),
-            seqnum: AtomicUsize::new(0),
+            mutex,
+            notify: Notify::new(),
+            seqnum: SeqCount::new(),
             data: UnsafeCell::new(t),
         }
     }
@@ -58,24 +556,19 @@ impl<T> Seqloq<T>
     ///
     /// The callback will run more than once, if a concurrent write occurs.
     #[inline]
-    pub fn peek<F, R>(&self, mut f: F) -> R
+    pub fn peek<F, R>(&self, f: F) -> R
         where F: FnMut(*const T) -> R,
     {
-        loop {
-            let old = self.seqnum.load(Ordering::SeqCst);
-            if (old & 1) != 0 {
-                // A writer is active.
-                // FIXME: smarter spinlocking
-                continue;
-            }
-
-            let res = f(self.data.get());
+        self.peek_versioned(f).0
+    }
 
-            let new = self.seqnum.load(Ordering::SeqCst);
-            if new == old {
-                return res;
-            }
-        }
+    /// Like `peek`, but also return the sequence number that the
+    /// callback's result was validated against.
+    #[inline]
+    fn peek_versioned<F, R>(&self, f: F) -> (R, u64)
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts().peek_versioned(f)
     }
 
     /// Read the data without locking.
@@ -84,46 +577,283 @@ impl<T> Seqloq<T>
     /// just as fast as `peek`.
     #[inline]
     pub fn read(&self) -> T {
-        self.peek(|x| unsafe { *x })
+        self.parts().read()
+    }
+
+    /// Read the data, along with the version it was read at.
+    ///
+    /// Versions only ever go up, two per write.
+    #[inline]
+    pub fn read_versioned(&self) -> (T, u64) {
+        self.peek_versioned(|x| unsafe { copy::load(x) })
+    }
+
+    /// The current version.  It's odd while a write is in progress.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    /// The name given with `Seqloq::builder`, or by a `Named` or `Traced`
+    /// writer mutex.
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        self.mutex.name()
+    }
+
+    /// Is the writer mutex held?  A writer may hold it without having
+    /// started its write section yet, so reads may still succeed.
+    ///
+    /// Like `write_pending`, this is a snapshot which may be stale by the
+    /// time it's returned.  It's meant for monitoring, or for a reader
+    /// deciding whether to do something else first.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.mutex.is_locked()
+    }
+
+    /// Is a writer in its write section?  A read started now would have to
+    /// wait for it.
+    #[inline]
+    pub fn write_pending(&self) -> bool {
+        self.seqnum.version() & 1 == 1
+    }
+
+    /// Block until a write newer than version `since` is published, then
+    /// read the data.
+    ///
+    /// Returns straight away if that already happened.  Pass the version
+    /// from `read_versioned` or from the last call to this.
+    ///
+    /// Waiters are woken when the writer releases the lock, so changes made
+    /// visible with `SeqloqGuard::publish` wake them only once the guard is
+    /// dropped.
+    pub fn wait_for_change(&self, since: u64) -> (T, u64) {
+        loop {
+            let (t, v) = self.read_versioned();
+            if v != since {
+                return (t, v);
+            }
+            self.notify.wait_until(|| self.seqnum.version() != since);
+        }
+    }
+
+    /// Block until `pred` holds for the data, and return it.
+    ///
+    /// `pred` is checked against the current value, and then against each
+    /// newly published one.  Values that are overwritten before the waiter
+    /// gets to them are never checked.
+    pub fn wait_until<F>(&self, mut pred: F) -> T
+        where F: FnMut(&T) -> bool,
+    {
+        let (mut t, mut v) = self.read_versioned();
+        while !pred(&t) {
+            (t, v) = self.wait_for_change(v);
+        }
+        t
+    }
+
+    /// An endless iterator over newly published values, blocking between
+    /// them.
+    ///
+    /// The first item is the first value written after this call.  Values
+    /// overwritten before the iterator gets to them are skipped.
+    #[inline]
+    pub fn changes(&self) -> Changes<'_, T, M> {
+        Changes {
+            seqloq: self,
+            version: self.seqnum.version() & !1,
+        }
     }
 
     /// Lock for exclusive, read/write access.
     ///
     /// Readers will see changes, but will automatically re-try until they have
-    /// a consistent view.
+    /// a consistent view.  The write section starts at the first mutable
+    /// access through the guard; if there is none, the version doesn't
+    /// change, and subscribers aren't told of a write.
     #[inline]
-    pub fn lock<'a>(&'a self) -> SeqloqGuard<'a, T> {
-        let guard = self.mutex.lock().unwrap();
-        non_atomic_increment(&self.seqnum);
-        SeqloqGuard {
-            seqloq: self,
-            guard: guard,
-            ptr: self.data.get(),
+    pub fn lock(&self) -> SeqloqGuard<'_, T, M> {
+        self.parts().lock()
+    }
+
+    /// Lock for exclusive access, if no other writer holds the lock right
+    /// now.
+    #[inline]
+    pub fn try_lock(&self) -> Option<SeqloqGuard<'_, T, M>> {
+        self.parts().try_lock()
+    }
+
+    /// Lock for exclusive access, staging writes in a private copy.
+    ///
+    /// Readers don't retry at all while the guard is held.  The changes are
+    /// published by `commit`, and discarded by `abort` or by dropping the
+    /// guard.
+    #[inline]
+    pub fn lock_staged(&self) -> SeqloqStagedGuard<'_, T, M> {
+        self.parts().lock_staged()
+    }
+
+    /// Replace the data.
+    ///
+    /// Readers only retry while `t` is copied in, which is as short as a
+    /// write section gets.  Build the new value before calling this.
+    #[inline]
+    pub fn write(&self, t: T) {
+        self.parts().write(t)
+    }
+
+    /// Modify a private copy of the data, then publish it with `write`.
+    ///
+    /// `f` runs with the writer mutex held, but readers carry on undisturbed
+    /// until it returns.  Prefer this to `lock` when the change takes a
+    /// while.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts().update(f)
+    }
+
+    /// Replace the data with `t`, unless it's equal to what's there.
+    /// Returns whether anything was written.
+    ///
+    /// An equal value is spotted without taking the lock, so pollers which
+    /// keep republishing the same data don't disturb readers, or each other.
+    pub fn set_if_changed(&self, t: T) -> bool
+        where T: PartialEq,
+    {
+        if self.read() == t {
+            return false;
+        }
+        let _guard = self.parts().guard();
+        // Another writer may have stored the same value in the meantime.
+        if unsafe { *self.data.get() } == t {
+            return false;
+        }
+        unsafe {
+            self.store(t);
         }
+        true
+    }
+}
+
+impl<T, M, const N: usize> Seqloq<[T; N], M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Copy a consistent snapshot straight into `out`.
+    ///
+    /// For large arrays this is faster than `read`, which copies through a
+    /// temporary.  The copy is done in chunks the compiler can vectorize.
+    #[inline]
+    pub fn read_into(&self, out: &mut [T; N]) {
+        read_section(&self.mutex, &self.seqnum, || unsafe {
+            copy::load_into(self.data.get(), out);
+        });
     }
 }
 
-impl<'a, T> Deref for SeqloqGuard<'a, T> {
+impl<T, M: RawMutex> SeqloqGuard<'_, T, M> {
+    /// Make the current contents visible to readers, without giving up
+    /// the lock.
+    ///
+    /// Readers can complete in between stages of a long, multi-stage
+    /// update, instead of spinning for the whole write.  The next
+    /// mutable access through this guard will start a new write section.
+    #[inline]
+    pub fn publish(&mut self) {
+        if !self.published {
+            #[cfg(not(feature = "atomic-copy"))]
+            self.seqnum.write_end();
+
+            #[cfg(feature = "atomic-copy")]
+            unsafe {
+                store(self.seqnum, self.ptr, core::ptr::read(&self.shadow));
+            }
+
+            self.published = true;
+        }
+    }
+}
+
+impl<T: Copy, M: RawMutex> SeqloqStagedGuard<'_, T, M> {
+    /// Publish the staged changes and release the lock.
+    #[inline]
+    pub fn commit(self) {
+        unsafe {
+            store(self.seqnum, self.ptr, self.staged);
+        }
+    }
+
+    /// Discard the staged changes and release the lock.
+    ///
+    /// This is equivalent to dropping the guard.
+    #[inline]
+    pub fn abort(self) { }
+}
+
+impl<T, M: RawMutex> Deref for SeqloqStagedGuard<'_, T, M> {
     type Target = T;
 
     #[inline]
-    fn deref<'b>(&'b self) -> &'b T {
-        unsafe { mem::transmute(self.ptr) }
+    fn deref(&self) -> &T {
+        &self.staged
     }
 }
 
-impl<'a, T> DerefMut for SeqloqGuard<'a, T> {
+impl<T, M: RawMutex> DerefMut for SeqloqStagedGuard<'_, T, M> {
     #[inline]
-    fn deref_mut<'b>(&'b mut self) -> &'b mut T {
-        unsafe { mem::transmute(self.ptr) }
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.staged
     }
 }
 
-#[unsafe_destructor]
-impl<'a, T> Drop for SeqloqGuard<'a, T> {
+impl<T, M: RawMutex> Deref for SeqloqGuard<'_, T, M> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        #[cfg(not(feature = "atomic-copy"))]
+        unsafe { &*self.ptr }
+
+        #[cfg(feature = "atomic-copy")]
+        &self.shadow
+    }
+}
+
+impl<T, M: RawMutex> DerefMut for SeqloqGuard<'_, T, M> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        #[cfg(not(feature = "atomic-copy"))]
+        {
+            if self.published {
+                self.seqnum.write_begin();
+                self.published = false;
+            }
+            unsafe { &mut *self.ptr }
+        }
+
+        #[cfg(feature = "atomic-copy")]
+        {
+            self.published = false;
+            &mut self.shadow
+        }
+    }
+}
+
+impl<T, M: RawMutex> Drop for SeqloqGuard<'_, T, M> {
     #[inline]
     fn drop(&mut self) {
-        non_atomic_increment(&self.seqloq.seqnum);
+        if !self.published {
+            #[cfg(not(feature = "atomic-copy"))]
+            self.seqnum.write_end();
+
+            #[cfg(feature = "atomic-copy")]
+            unsafe {
+                store(self.seqnum, self.ptr, core::ptr::read(&self.shadow));
+            }
+        }
     }
 }
 
@@ -142,6 +872,202 @@ fn smoke_test() {
     assert_eq!(x.read(), 4);
 }
 
+#[test]
+fn publish() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+
+    let mut g = x.lock();
+    *g = 4;
+    g.publish();
+    assert_eq!(x.read(), 4);
+
+    // Publishing twice is harmless.
+    g.publish();
+    assert_eq!(x.read(), 4);
+
+    *g = 5;
+    #[cfg(not(feature = "atomic-copy"))]
+    assert_eq!(x.seqnum.version() & 1, 1);
+    drop(g);
+
+    assert_eq!(x.read(), 5);
+}
+
+#[test]
+fn staged() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+
+    {
+        let mut g = x.lock_staged();
+        *g = 4;
+        assert_eq!(*g, 4);
+        assert_eq!(x.read(), 3);
+        g.abort();
+    }
+    assert_eq!(x.read(), 3);
+
+    {
+        let mut g = x.lock_staged();
+        *g = 5;
+    }
+    assert_eq!(x.read(), 3);
+
+    {
+        let mut g = x.lock_staged();
+        *g = 6;
+        g.commit();
+    }
+    assert_eq!(x.read(), 6);
+    assert_eq!(x.seqnum.version(), 2);
+}
+
+#[test]
+fn read_into() {
+    let x = Seqloq::new([7u8; 1000]);
+    x.lock()[999] = 8;
+
+    let mut out = [0; 1000];
+    x.read_into(&mut out);
+    assert_eq!(&out[..999], &[7; 999][..]);
+    assert_eq!(out[999], 8);
+}
+
+#[test]
+fn write_and_update() {
+    let x = Seqloq::new([0u32; 4]);
+    x.write([1, 2, 3, 4]);
+    let sum = x.update(|v| {
+        v[0] = 10;
+        assert_eq!(x.seqnum.version() % 2, 0);
+        v.iter().sum::<u32>()
+    });
+    assert_eq!(sum, 19);
+    assert_eq!(x.read(), [10, 2, 3, 4]);
+    assert_eq!(x.seqnum.version(), 4);
+}
+
+#[test]
+fn wait_for_change() {
+    let x = Seqloq::new(0u32);
+    let (_, v) = x.read_versioned();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            x.write(1);
+        });
+        assert_eq!(x.wait_for_change(v), (1, 2));
+    });
+    assert_eq!(x.wait_for_change(0), (1, 2));
+}
+
+#[test]
+fn wait_until() {
+    let x = Seqloq::new(0u32);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 1..=10 {
+                x.write(i);
+            }
+        });
+        assert_eq!(x.wait_until(|&v| v == 10), 10);
+    });
+}
+
+#[test]
+fn changes() {
+    let x = Seqloq::new(0u32);
+    let mut it = x.changes();
+    x.write(1);
+    assert_eq!(it.next(), Some(1));
+    std::thread::scope(|s| {
+        s.spawn(|| x.write(2));
+        assert_eq!(it.next(), Some(2));
+    });
+}
+
+#[test]
+fn try_lock() {
+    let x = Seqloq::new(0u32);
+    let mut g = x.try_lock().unwrap();
+    // A failed attempt mustn't release the holder's lock.
+    assert!(x.try_lock().is_none());
+    assert!(x.try_lock().is_none());
+    *g = 1;
+    drop(g);
+    assert_eq!(x.read(), 1);
+}
+
+#[test]
+#[cfg(all(feature = "std", debug_assertions))]
+#[should_panic(expected = "already holds the writer lock")]
+fn relock() {
+    let x = Seqloq::new(0u32);
+    let _g = x.lock();
+    x.write(1);
+}
+
+#[test]
+fn probes() {
+    let x = Seqloq::new(0u32);
+    assert!(!x.is_locked());
+    let g = x.lock_staged();
+    assert!(x.is_locked());
+    assert!(!x.write_pending());
+    drop(g);
+    x.seqnum.write_begin();
+    assert!(x.write_pending());
+    x.seqnum.write_end();
+    assert!(!x.write_pending());
+    assert!(!x.is_locked());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn clean_guard() {
+    let x = Seqloq::new(3u32);
+    let sub = x.subscribe();
+    {
+        let g = x.lock();
+        assert_eq!(*g, 3);
+    }
+    assert_eq!(x.version(), 0);
+    let mut g = x.lock();
+    *g = 4;
+    drop(g);
+    assert_eq!(x.version(), 2);
+    assert_eq!(sub.try_iter().collect::<Vec<_>>(), [2]);
+}
+
+#[test]
+fn set_if_changed() {
+    let x = Seqloq::new([1u8, 2]);
+    assert!(!x.set_if_changed([1, 2]));
+    assert_eq!(x.version(), 0);
+    assert!(x.set_if_changed([1, 3]));
+    assert_eq!((x.read(), x.version()), ([1, 3], 2));
+}
+
+#[test]
+fn wrap_around() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+    x.seqnum.seq.store(Seq::MAX - 1, Ordering::SeqCst);
+
+    *x.lock() = 4;
+    assert_eq!(x.seqnum.version(), 0);
+    assert_eq!(x.read(), 4);
+}
+
+#[test]
+#[cfg(target_has_atomic = "64")]
+fn size() {
+    use std::mem::size_of;
+
+    // The writer lock and the `Notify` word are 32 bits each and share one
+    // 64-bit word, and the sequence number takes another.
+    assert_eq!(size_of::<Seqloq<()>>(), 16);
+    assert_eq!(size_of::<Seqloq<u64>>(), 24);
+}
+
 #[test]
 fn traits() {
     fn check<T: Send + Sync>(_: &T) { }
@@ -0,0 +1,217 @@
+//! A read-mostly hash map.
+//!
+//! `SeqloqHashMap` is a fixed-capacity, open-addressed table with a
+//! sequence counter on every bucket.  Lookups are optimistic: they read
+//! buckets without locking and retry only a bucket that changed under them.
+//! Writers take a single mutex, and each insert or removal changes exactly
+//! one bucket, so a reader never sees a half-done operation.
+//!
+//! Removed entries leave a tombstone, which a later insert can reuse.  The
+//! table never grows; inserting a new key once it holds `capacity()`
+//! entries panics.
+
+use std::cell::UnsafeCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{RawMutex, RawGuard, SeqCount, WordLock, Parts, store};
+use crate::sync::{AtomicUsize, Ordering};
+
+#[derive(Clone, Copy)]
+enum Slot<K, V> {
+    Empty,
+    Removed,
+    Full(K, V),
+}
+
+struct Bucket<K, V> {
+    seqnum: SeqCount,
+    slot: UnsafeCell<Slot<K, V>>,
+}
+
+/// A fixed-capacity hash map with lock-free reads.
+pub struct SeqloqHashMap<K, V, M = WordLock> {
+    mutex: M,
+    hasher: RandomState,
+    len: AtomicUsize,
+    buckets: Box<[Bucket<K, V>]>,
+}
+
+unsafe impl<K: Send, V: Send, M: Send> Send for SeqloqHashMap<K, V, M> { }
+unsafe impl<K: Send, V: Send, M: Sync> Sync for SeqloqHashMap<K, V, M> { }
+
+impl<K, V> SeqloqHashMap<K, V>
+    where K: Hash + Eq + Send + Copy,
+          V: Send + Copy,
+{
+    /// Create an empty map with room for at least `capacity` entries.
+    #[inline]
+    pub fn new(capacity: usize) -> SeqloqHashMap<K, V> {
+        SeqloqHashMap::with_mutex(capacity, WordLock::new())
+    }
+}
+
+impl<K, V, M> SeqloqHashMap<K, V, M>
+    where K: Hash + Eq + Send + Copy,
+          V: Send + Copy,
+          M: RawMutex,
+{
+    /// As `new`, using `mutex` to exclude concurrent writers.
+    pub fn with_mutex(capacity: usize, mutex: M) -> SeqloqHashMap<K, V, M> {
+        // Keep the load factor at most one half, so probe sequences stay
+        // short.
+        let n = capacity.max(1).saturating_mul(2).next_power_of_two();
+        SeqloqHashMap {
+            mutex,
+            hasher: RandomState::new(),
+            len: AtomicUsize::new(0),
+            buckets: (0..n).map(|_| Bucket {
+                seqnum: SeqCount::new(),
+                slot: UnsafeCell::new(Slot::Empty),
+            }).collect(),
+        }
+    }
+
+    /// The number of entries the map can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() / 2
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bucket indices to search for `k`, in order.
+    #[inline]
+    fn probe(&self, k: &K) -> impl Iterator<Item = usize> {
+        let mask = self.buckets.len() - 1;
+        let start = self.hasher.hash_one(k) as usize;
+        (0..self.buckets.len()).map(move |i| (start + i) & mask)
+    }
+
+    #[inline(always)]
+    fn parts(&self, i: usize) -> Parts<'_, Slot<K, V>, M> {
+        let b = &self.buckets[i];
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &b.seqnum,
+            data: b.slot.get(),
+        }
+    }
+
+    /// Look up `k` without locking.
+    pub fn get(&self, k: &K) -> Option<V> {
+        for i in self.probe(k) {
+            match self.parts(i).read() {
+                Slot::Empty => return None,
+                Slot::Full(k2, v) if k2 == *k => return Some(v),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    #[inline]
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.get(k).is_some()
+    }
+
+    /// Insert or replace an entry, returning the old value.
+    ///
+    /// Panics if `k` is new and the map already holds `capacity()` entries.
+    pub fn insert(&self, k: K, v: V) -> Option<V> {
+        let _guard = RawGuard::lock(&self.mutex);
+        let mut free = None;
+        for i in self.probe(&k) {
+            // Holding the mutex, so the bucket can't change.
+            match unsafe { *self.buckets[i].slot.get() } {
+                Slot::Full(k2, old) if k2 == k => {
+                    self.set(i, Slot::Full(k, v));
+                    return Some(old);
+                }
+                Slot::Full(..) => (),
+                Slot::Removed => { free.get_or_insert(i); }
+                Slot::Empty => {
+                    free.get_or_insert(i);
+                    break;
+                }
+            }
+        }
+
+        assert!(self.len() < self.capacity(), "SeqloqHashMap capacity exceeded");
+        // At most half the buckets are full, so the probe found a free one.
+        let i = free.unwrap();
+        self.set(i, Slot::Full(k, v));
+        self.len.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Remove an entry, returning its value.
+    pub fn remove(&self, k: &K) -> Option<V> {
+        let _guard = RawGuard::lock(&self.mutex);
+        for i in self.probe(k) {
+            match unsafe { *self.buckets[i].slot.get() } {
+                Slot::Empty => return None,
+                Slot::Full(k2, v) if k2 == *k => {
+                    self.set(i, Slot::Removed);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    return Some(v);
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// The caller must hold the writer mutex.
+    #[inline]
+    fn set(&self, i: usize, slot: Slot<K, V>) {
+        let b = &self.buckets[i];
+        unsafe {
+            store(&b.seqnum, b.slot.get(), slot);
+        }
+    }
+}
+
+#[test]
+fn map() {
+    let m = SeqloqHashMap::new(4);
+    assert_eq!(m.capacity(), 4);
+    assert_eq!(m.insert(1u32, 'a'), None);
+    assert_eq!(m.insert(2, 'b'), None);
+    assert_eq!(m.insert(1, 'c'), Some('a'));
+    assert_eq!(m.len(), 2);
+    assert_eq!(m.get(&1), Some('c'));
+    assert_eq!(m.get(&3), None);
+
+    assert_eq!(m.remove(&1), Some('c'));
+    assert_eq!(m.remove(&1), None);
+    assert!(!m.contains_key(&1));
+    assert!(m.contains_key(&2));
+
+    // Tombstones get reused.
+    for k in 0..100 {
+        m.insert(k + 10, 'x');
+        m.remove(&(k + 10));
+    }
+    assert_eq!(m.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "capacity exceeded")]
+fn map_full() {
+    let m = SeqloqHashMap::new(1);
+    assert_eq!(m.capacity(), 1);
+    m.insert(0u8, ());
+    // Replacing is fine when full; a new key isn't.
+    assert_eq!(m.insert(0, ()), Some(()));
+    m.insert(1, ());
+}
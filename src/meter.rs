@@ -0,0 +1,170 @@
+//! `metrics` integration.
+//!
+//! Wrap the writer mutex in `Metered` to publish through the `metrics`
+//! facade, labelled with a name:
+//!
+//! ```
+//! use seqloq::{Seqloq, Metered, WordLock};
+//!
+//! let routes = Seqloq::with_mutex(0u32, Metered::new("routes", WordLock::new()));
+//! routes.write(1);
+//! ```
+//!
+//! It reports
+//!
+//! * `seqloq_reader_retries_total`, a counter of read attempts thrown away,
+//! * `seqloq_writes_total`, a counter of times the writer mutex was taken,
+//! * `seqloq_write_wait_seconds`, a histogram of time spent waiting for it,
+//! * `seqloq_write_hold_seconds`, a histogram of time spent holding it,
+//!
+//! each with a `name` label.  Handles are taken from the recorder installed
+//! when the `Metered` is created.
+
+use std::cell::UnsafeCell;
+use std::time::Instant;
+
+use metrics::{Counter, Histogram, counter, histogram};
+
+use crate::RawMutex;
+
+/// A writer mutex which reports through `metrics`.
+pub struct Metered<M> {
+    inner: M,
+    retries: Counter,
+    writes: Counter,
+    wait: Histogram,
+    hold: Histogram,
+    /// When the current holder took the mutex.  Protected by `inner`.
+    since: UnsafeCell<Option<Instant>>,
+}
+
+unsafe impl<M: Send> Send for Metered<M> { }
+unsafe impl<M: Sync> Sync for Metered<M> { }
+
+impl<M: RawMutex> Metered<M> {
+    pub fn new(name: &'static str, inner: M) -> Metered<M> {
+        Metered {
+            inner,
+            retries: counter!("seqloq_reader_retries_total", "name" => name),
+            writes: counter!("seqloq_writes_total", "name" => name),
+            wait: histogram!("seqloq_write_wait_seconds", "name" => name),
+            hold: histogram!("seqloq_write_hold_seconds", "name" => name),
+            since: UnsafeCell::new(None),
+        }
+    }
+
+    /// Note that the lock was just taken.  The caller must hold `inner`.
+    #[inline]
+    fn acquired(&self) {
+        self.writes.increment(1);
+        unsafe { *self.since.get() = Some(Instant::now()) };
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Metered<M> {
+    #[inline]
+    fn lock(&self) {
+        let start = Instant::now();
+        self.inner.lock();
+        self.wait.record(start.elapsed());
+        self.acquired();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        let locked = self.inner.try_lock();
+        if locked {
+            self.acquired();
+        }
+        locked
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        if let Some(since) = unsafe { (*self.since.get()).take() } {
+            self.hold.record(since.elapsed());
+        }
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.retries.increment(1);
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<crate::SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+#[test]
+fn metered() {
+    // With no recorder installed the handles do nothing, but the mutex
+    // still has to work.
+    let x = crate::Seqloq::with_mutex(0u32,
+        Metered::new("test", crate::Stats::new(crate::WordLock::new())));
+    x.write(1);
+    *x.lock() += 1;
+    assert_eq!(x.read(), 2);
+    assert_eq!(x.mutex.stats().unwrap().writes, 2);
+}
+
+#[test]
+fn recorded() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let m = metrics::with_local_recorder(&recorder,
+        || Metered::new("test", crate::WordLock::new()));
+    let x = crate::Seqloq::with_mutex(0u32, m);
+    x.write(1);
+    *x.lock() += 1;
+    assert!(x.mutex.try_lock());
+    unsafe { x.mutex.unlock() };
+    x.mutex.reader_retried(1);
+    x.mutex.reader_retried(2);
+
+    let values = snapshotter.snapshot().into_vec();
+    let value = |name: &str| {
+        let (key, _, _, v) = values.iter().find(|(k, ..)| k.key().name() == name).unwrap();
+        assert!(key.key().labels().any(|l| l.key() == "name" && l.value() == "test"));
+        v
+    };
+    assert_eq!(value("seqloq_writes_total"), &DebugValue::Counter(3));
+    assert_eq!(value("seqloq_reader_retries_total"), &DebugValue::Counter(2));
+    // try_lock doesn't wait, so only two waits are timed.
+    match value("seqloq_write_wait_seconds") {
+        DebugValue::Histogram(v) => assert_eq!(v.len(), 2),
+        v => panic!("{:?}", v),
+    }
+    match value("seqloq_write_hold_seconds") {
+        DebugValue::Histogram(v) => {
+            assert_eq!(v.len(), 3);
+            assert!(v.iter().all(|s| s.0 >= 0.0 && s.0 < 60.0));
+        }
+        v => panic!("{:?}", v),
+    }
+}
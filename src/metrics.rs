@@ -0,0 +1,164 @@
+//! Counters that can be read consistently.
+//!
+//! Scraping a set of counters one at a time can produce totals that never
+//! existed together: a request counted among the errors but not yet among
+//! the requests, say.  Here each thread bumps its own plain counters and
+//! publishes them all at once, and a scraper sums the threads' published
+//! values into a total which it also publishes all at once.
+//!
+//! ```
+//! use seqloq::metrics::Counters;
+//!
+//! const REQUESTS: usize = 0;
+//! const ERRORS: usize = 1;
+//!
+//! let counters = Counters::<2>::new();
+//! std::thread::scope(|s| {
+//!     for _ in 0..4 {
+//!         let mut local = counters.local();
+//!         s.spawn(move || {
+//!             for i in 0..100 {
+//!                 local.incr(REQUESTS);
+//!                 if i % 10 == 0 {
+//!                     local.incr(ERRORS);
+//!                 }
+//!                 local.publish();
+//!             }
+//!         });
+//!     }
+//!     let total = counters.collect();
+//!     assert!(total[ERRORS] <= total[REQUESTS]);
+//! });
+//! assert_eq!(counters.collect(), [400, 40]);
+//! ```
+//!
+//! A thread's counts are only visible once it calls `publish`, or drops
+//! its `LocalCounters`.  Publishing costs a couple of atomic stores, so hot
+//! loops may want to publish every so often rather than every time.
+
+use std::sync::Mutex;
+
+use crate::{Seqloq, Reader, Writer, WordLock};
+
+/// A set of `N` counters, summed over threads.
+pub struct Counters<const N: usize> {
+    threads: Mutex<Vec<Reader<[u64; N], WordLock>>>,
+    total: Seqloq<[u64; N]>,
+}
+
+/// One thread's share of a `Counters`.
+pub struct LocalCounters<const N: usize> {
+    counts: [u64; N],
+    slot: Writer<[u64; N], WordLock>,
+}
+
+impl<const N: usize> Default for Counters<N> {
+    fn default() -> Counters<N> {
+        Counters::new()
+    }
+}
+
+impl<const N: usize> Counters<N> {
+    pub fn new() -> Counters<N> {
+        Counters {
+            threads: Mutex::new(Vec::new()),
+            total: Seqloq::new([0; N]),
+        }
+    }
+
+    /// Counters for one thread to bump.
+    ///
+    /// Each call adds a slot which lives as long as the `Counters` does, so
+    /// make one per thread rather than one per task.
+    pub fn local(&self) -> LocalCounters<N> {
+        let (slot, reader) = Seqloq::new([0; N]).split();
+        self.threads.lock().unwrap().push(reader);
+        LocalCounters {
+            counts: [0; N],
+            slot,
+        }
+    }
+
+    /// Sum what each thread has published, and publish the result.
+    ///
+    /// Each thread's counts in the sum are from a single `publish`, so any
+    /// relation that holds between one thread's counters at every
+    /// `publish` also holds between the totals.
+    pub fn collect(&self) -> [u64; N] {
+        // Holding the lock orders concurrent collections, so an older sum
+        // never replaces a newer one.
+        let mut total = self.total.lock();
+        let mut sum = [0; N];
+        for t in self.threads.lock().unwrap().iter() {
+            let counts = t.read();
+            for (s, c) in sum.iter_mut().zip(counts.iter()) {
+                *s += c;
+            }
+        }
+        *total = sum;
+        sum
+    }
+
+    /// The totals as of the last `collect`.
+    #[inline]
+    pub fn snapshot(&self) -> [u64; N] {
+        self.total.read()
+    }
+
+    /// The totals as of the last `collect`, and its version.
+    #[inline]
+    pub fn snapshot_versioned(&self) -> ([u64; N], u64) {
+        self.total.read_versioned()
+    }
+}
+
+impl<const N: usize> LocalCounters<N> {
+    /// Add one to counter `i`.  Not visible until `publish`.
+    #[inline]
+    pub fn incr(&mut self, i: usize) {
+        self.add(i, 1);
+    }
+
+    /// Add `n` to counter `i`.  Not visible until `publish`.
+    #[inline]
+    pub fn add(&mut self, i: usize, n: u64) {
+        self.counts[i] += n;
+    }
+
+    /// This thread's counts, including any not yet published.
+    #[inline]
+    pub fn get(&self) -> [u64; N] {
+        self.counts
+    }
+
+    /// Make this thread's counts visible to `Counters::collect`, all at
+    /// once.
+    #[inline]
+    pub fn publish(&mut self) {
+        self.slot.write(self.counts);
+    }
+}
+
+impl<const N: usize> Drop for LocalCounters<N> {
+    fn drop(&mut self) {
+        self.publish();
+    }
+}
+
+#[test]
+fn counters() {
+    let c = Counters::<3>::new();
+    let mut a = c.local();
+    let mut b = c.local();
+    a.incr(0);
+    a.add(2, 5);
+    b.incr(0);
+    assert_eq!(c.collect(), [0, 0, 0]);
+    a.publish();
+    assert_eq!(c.collect(), [1, 0, 5]);
+    drop(b);
+    assert_eq!(c.snapshot(), [1, 0, 5]);
+    assert_eq!(c.collect(), [2, 0, 5]);
+    assert_eq!(a.get(), [1, 0, 5]);
+    assert_eq!(c.snapshot_versioned(), ([2, 0, 5], 6));
+}
@@ -0,0 +1,641 @@
+//! Mutexes for excluding concurrent writers.
+//!
+//! A `Seqloq` only needs its mutex to keep writers away from each other;
+//! readers never touch it.  Any type implementing `RawMutex` will do.
+
+#[cfg(feature = "std")]
+use crate::SeqloqStats;
+use crate::sync::{AtomicBool, AtomicU32, Ordering, spin_loop, wait, wake_one};
+#[cfg(feature = "std")]
+use crate::sync::{Mutex, Condvar, wait_shared, wake_one_shared};
+
+/// A mutex which is locked and unlocked without a guard object.
+///
+/// # Safety
+///
+/// `Seqloq` relies on this for memory safety: between `lock` returning (or
+/// `try_lock` returning `true`) and the matching `unlock`, no other thread
+/// may acquire the mutex.
+pub unsafe trait RawMutex: Send + Sync {
+    /// Acquire the mutex, blocking the current thread until it's available.
+    fn lock(&self);
+
+    /// Acquire the mutex if it's available right now.
+    fn try_lock(&self) -> bool;
+
+    /// Release the mutex.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the holder of the mutex.
+    unsafe fn unlock(&self);
+
+    /// Is the mutex held right now?  The answer may be out of date by the
+    /// time it's returned, so it's only good for monitoring and heuristics.
+    ///
+    /// The default briefly takes the mutex to find out; implementations
+    /// should do better.
+    #[inline]
+    fn is_locked(&self) -> bool {
+        if self.try_lock() {
+            unsafe { self.unlock() };
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Called when a reader has had to retry, `retries` times so far.
+    ///
+    /// Readers never otherwise touch the mutex.  This hook, and `reader_done`,
+    /// are for fairness policies (see the `policy` module) and statistics
+    /// (see `Stats`), and by default do nothing.
+    #[inline(always)]
+    fn reader_retried(&self, retries: u32) {
+        let _ = retries;
+    }
+
+    /// Called when a read succeeds, after `retries` retries (often none).
+    #[inline(always)]
+    fn reader_done(&self, retries: u32) {
+        let _ = retries;
+    }
+
+    /// Called just before the mutex is released by a writer which changed
+    /// the data.  A writer which took the mutex and changed nothing, or
+    /// called `unlock` directly, doesn't call it.  By default, does nothing.
+    #[inline(always)]
+    fn writer_published(&self) { }
+
+    /// Statistics kept by this mutex, if it keeps any (see `Stats`).
+    /// Wrappers should pass this through from the mutex they wrap.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn stats(&self) -> Option<SeqloqStats> {
+        None
+    }
+
+    /// The name given to this mutex, if any (see `Named`).  Wrappers
+    /// should pass this through from the mutex they wrap.
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// The default writer mutex: a single 32-bit word.
+///
+/// Uncontended locking is a single compare-and-swap.  Contending writers
+/// spin briefly and then sleep on a futex (or the platform's equivalent).
+///
+/// It's kept apart from the sequence number, rather than taking the lock by
+/// making the sequence number odd, so that a writer can hold the lock
+/// without blocking readers: `lock_staged` and guards that never write rely
+/// on that.  Next to the 32-bit `Notify` word, it costs no extra space.
+#[repr(transparent)]
+pub struct WordLock {
+    /// 0: unlocked, 1: locked, 2: locked with (possible) sleepers.
+    state: AtomicU32,
+}
+
+impl Default for WordLock {
+    fn default() -> WordLock {
+        WordLock::new()
+    }
+}
+
+impl WordLock {
+    #[inline]
+    pub fn new() -> WordLock {
+        WordLock {
+            state: AtomicU32::new(0),
+        }
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        let mut spins = 0;
+        while self.state.load(Ordering::Relaxed) == 1 && spins < 100 {
+            spin_loop();
+            spins += 1;
+        }
+
+        if self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            return;
+        }
+
+        while self.state.swap(2, Ordering::Acquire) != 0 {
+            wait(&self.state, 2);
+        }
+    }
+}
+
+unsafe impl RawMutex for WordLock {
+    #[inline]
+    fn lock(&self) {
+        if self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.lock_contended();
+        }
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        if self.state.swap(0, Ordering::Release) == 2 {
+            wake_one(&self.state);
+        }
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// A `WordLock` which works between processes, when it's in shared memory.
+///
+/// The protocol is the same, but contended writers sleep on a
+/// process-shared futex.  On platforms other than Linux they yield in a
+/// loop instead.
+///
+/// The holder also records its process ID, so that a lock left behind by a
+/// process which died can be recovered (see `SeqloqC::recover`).
+#[cfg(feature = "std")]
+#[repr(C)]
+pub struct SharedLock {
+    /// 0: unlocked, 1: locked, 2: locked with (possible) sleepers.
+    state: AtomicU32,
+    /// The holder's process ID, or 0 if unlocked or not yet recorded.
+    pub(crate) owner: AtomicU32,
+}
+
+#[cfg(feature = "std")]
+impl Default for SharedLock {
+    fn default() -> SharedLock {
+        SharedLock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl SharedLock {
+    #[inline]
+    pub fn new() -> SharedLock {
+        SharedLock {
+            state: AtomicU32::new(0),
+            owner: AtomicU32::new(0),
+        }
+    }
+
+    /// The process holding the lock, if it's held and the holder has
+    /// recorded itself yet.
+    #[inline]
+    pub fn owner(&self) -> Option<u32> {
+        match self.owner.load(Ordering::Relaxed) {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+
+    /// If the lock is held by a process which no longer exists, take it
+    /// over.  The caller then holds the lock.
+    #[cfg(all(not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn take_over(&self) -> bool {
+        let pid = self.owner.load(Ordering::Acquire);
+        if pid == 0 || self.state.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        let dead = unsafe { libc::kill(pid as libc::pid_t, 0) } == -1
+            && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH);
+        // Only one of several recovering processes gets it.
+        dead && self.owner.compare_exchange(pid, std::process::id(),
+            Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        let mut spins = 0;
+        while self.state.load(Ordering::Relaxed) == 1 && spins < 100 {
+            spin_loop();
+            spins += 1;
+        }
+
+        if self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            return;
+        }
+
+        while self.state.swap(2, Ordering::Acquire) != 0 {
+            wait_shared(&self.state, 2);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl RawMutex for SharedLock {
+    #[inline]
+    fn lock(&self) {
+        if self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.lock_contended();
+        }
+        self.owner.store(std::process::id(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        let locked = self.state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok();
+        if locked {
+            self.owner.store(std::process::id(), Ordering::Relaxed);
+        }
+        locked
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        self.owner.store(0, Ordering::Relaxed);
+        if self.state.swap(0, Ordering::Release) == 2 {
+            wake_one_shared(&self.state);
+        }
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// A pure spinlock, for writes that are only a handful of stores.
+///
+/// Waiting writers never sleep, so this is only a good idea when the write
+/// sections are very short and writers rarely collide:
+///
+/// ```
+/// use seqloq::{Seqloq, SpinLock};
+///
+/// let x = Seqloq::with_mutex(0u64, SpinLock::new());
+/// *x.lock() += 1;
+/// assert_eq!(x.read(), 1);
+/// ```
+pub struct SpinLock {
+    locked: AtomicBool,
+}
+
+impl Default for SpinLock {
+    fn default() -> SpinLock {
+        SpinLock::new()
+    }
+}
+
+impl SpinLock {
+    #[inline]
+    pub fn new() -> SpinLock {
+        SpinLock {
+            locked: AtomicBool::new(false),
+        }
+    }
+}
+
+unsafe impl RawMutex for SpinLock {
+    #[inline]
+    fn lock(&self) {
+        while !self.try_lock() {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.locked.compare_exchange_weak(false, true,
+            Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+/// A writer mutex built on `std::sync::Mutex` and `Condvar`.
+#[cfg(feature = "std")]
+pub struct StdMutex {
+    locked: Mutex<bool>,
+    cond: Condvar,
+}
+
+#[cfg(feature = "std")]
+impl Default for StdMutex {
+    fn default() -> StdMutex {
+        StdMutex::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl StdMutex {
+    #[inline]
+    pub fn new() -> StdMutex {
+        StdMutex {
+            locked: Mutex::new(false),
+            cond: Condvar::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl RawMutex for StdMutex {
+    #[inline]
+    fn lock(&self) {
+        let mut locked = self.locked.lock().unwrap();
+        while *locked {
+            locked = self.cond.wait(locked).unwrap();
+        }
+        *locked = true;
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        let mut locked = self.locked.lock().unwrap();
+        if *locked {
+            false
+        } else {
+            *locked = true;
+            true
+        }
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        *self.locked.lock().unwrap() = false;
+        self.cond.notify_one();
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        *self.locked.lock().unwrap()
+    }
+}
+
+/// Keeps a mutex on a cache line of its own.
+///
+/// Readers read the sequence number, which sits next to the writer mutex.
+/// When writers contend, every change to the mutex also costs readers a
+/// cache miss.  Padding trades 128 bytes for keeping them apart.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+pub struct Padded<M> {
+    inner: M,
+}
+
+impl<M: RawMutex> Padded<M> {
+    #[inline]
+    pub fn new(inner: M) -> Padded<M> {
+        Padded { inner }
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Padded<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn stats(&self) -> Option<SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+/// Wraps a writer mutex to give the `Seqloq` a name, for diagnostics (see
+/// `Seqloq::name`).
+#[derive(Debug)]
+pub struct Named<M> {
+    inner: M,
+    name: &'static str,
+}
+
+impl<M: RawMutex> Named<M> {
+    #[inline]
+    pub fn new(name: &'static str, inner: M) -> Named<M> {
+        Named { inner, name }
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Named<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn stats(&self) -> Option<SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        Some(self.name)
+    }
+}
+
+/// Any `lock_api` raw mutex (`parking_lot`, `spin`, ...) can exclude writers.
+#[cfg(feature = "lock-api")]
+unsafe impl<R> RawMutex for R
+    where R: lock_api::RawMutex + Send + Sync,
+{
+    #[inline]
+    fn lock(&self) {
+        lock_api::RawMutex::lock(self);
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        lock_api::RawMutex::try_lock(self)
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { lock_api::RawMutex::unlock(self) };
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        lock_api::RawMutex::is_locked(self)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use std::sync::atomic::AtomicUsize;
+
+#[cfg(all(test, feature = "std"))]
+struct CountingMutex {
+    inner: StdMutex,
+    locks: AtomicUsize,
+}
+
+#[cfg(all(test, feature = "std"))]
+unsafe impl RawMutex for CountingMutex {
+    fn lock(&self) {
+        self.inner.lock();
+        self.locks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock()
+    }
+
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock() };
+    }
+}
+
+#[test]
+fn word_lock() {
+    let m = WordLock::new();
+    m.lock();
+    assert!(!m.try_lock());
+    assert!(m.is_locked());
+    unsafe { m.unlock(); }
+    assert!(!m.is_locked());
+    assert!(m.try_lock());
+    unsafe { m.unlock(); }
+}
+
+#[test]
+fn word_lock_contended() {
+    use std::cell::UnsafeCell;
+    use std::thread;
+
+    struct Counter(WordLock, UnsafeCell<u64>);
+    unsafe impl Sync for Counter { }
+
+    let c = &Counter(WordLock::new(), UnsafeCell::new(0));
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(move || {
+                for _ in 0..10_000 {
+                    c.0.lock();
+                    unsafe {
+                        *c.1.get() += 1;
+                        c.0.unlock();
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(unsafe { *c.1.get() }, 40_000);
+}
+
+#[test]
+fn spin_lock() {
+    let m = SpinLock::new();
+    m.lock();
+    assert!(!m.try_lock());
+    unsafe { m.unlock(); }
+    m.lock();
+    unsafe { m.unlock(); }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn std_mutex() {
+    let m = StdMutex::new();
+    m.lock();
+    assert!(!m.try_lock());
+    unsafe { m.unlock(); }
+    assert!(m.try_lock());
+    unsafe { m.unlock(); }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn custom_mutex() {
+    use crate::Seqloq;
+
+    let x = Seqloq::with_mutex(3u32, CountingMutex {
+        inner: StdMutex::new(),
+        locks: AtomicUsize::new(0),
+    });
+
+    *x.lock() = 4;
+    assert_eq!(x.read(), 4);
+    assert_eq!(x.begin().commit(5), Ok(()));
+    assert_eq!(x.read(), 5);
+    assert_eq!(x.mutex.locks.load(Ordering::SeqCst), 2);
+    assert!(x.mutex.try_lock());
+}
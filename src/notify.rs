@@ -0,0 +1,275 @@
+//! Waking threads that wait for a write.
+//!
+//! Waiters sleep on a 32-bit word, with the futex-like primitive also used
+//! by `WordLock`.  The low bit says whether anyone is waiting, so that a
+//! writer with no waiters pays only for one load.
+//!
+//! With the `event-listener` feature, threads and tasks alike wait on an
+//! `event_listener::Event` instead.
+//!
+//! Subscribers, which get a message for every write, are kept in a global
+//! table.  A second bit in the word says whether there are any.  Without
+//! `std` there are no subscribers, and waiting means spinning.
+
+#[cfg(feature = "std")]
+use std::sync::Mutex as StdMutex;
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+
+use crate::SeqCount;
+use crate::sync::{AtomicU32, Ordering};
+
+#[cfg(not(feature = "event-listener"))]
+use crate::sync::{wait, wake_all};
+
+#[cfg(feature = "std")]
+use std::sync::atomic::AtomicU64;
+#[cfg(feature = "async")]
+use std::task::Waker;
+
+#[cfg(feature = "event-listener")]
+use event_listener::{Event, EventListener, Listener};
+
+const WAITERS: u32 = 1;
+#[cfg(feature = "std")]
+const SUBSCRIBED: u32 = 2;
+
+/// What `word` advances by on every wakeup.
+const WAKEUP: u32 = 4;
+
+pub(crate) struct Notify {
+    /// A wakeup counter, plus the `WAITERS` and `SUBSCRIBED` bits.
+    word: AtomicU32,
+}
+
+impl Notify {
+    #[inline]
+    pub(crate) fn new() -> Notify {
+        Notify {
+            word: AtomicU32::new(0),
+        }
+    }
+
+    /// Wake all waiters, and tell subscribers about the new version of
+    /// `seqnum`.  Call this after publishing a write.
+    #[inline]
+    pub(crate) fn notify(&self, seqnum: &SeqCount) {
+        let w = self.word.load(Ordering::SeqCst);
+        #[cfg(feature = "std")]
+        if w & SUBSCRIBED != 0 {
+            self.send(seqnum.version());
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = seqnum;
+        if w & WAITERS != 0 {
+            self.wake();
+        }
+    }
+
+    /// Wake all waiters, but send subscribers nothing.  Call this after
+    /// releasing the lock without writing, for the sake of tasks waiting
+    /// for the lock itself.
+    #[inline]
+    pub(crate) fn notify_release(&self) {
+        if self.word.load(Ordering::SeqCst) & WAITERS != 0 {
+            self.wake();
+        }
+    }
+
+    #[cold]
+    fn wake(&self) {
+        self.word.fetch_and(!WAITERS, Ordering::SeqCst);
+        self.word.fetch_add(WAKEUP, Ordering::SeqCst);
+
+        #[cfg(not(feature = "event-listener"))]
+        wake_all(&self.word);
+
+        #[cfg(feature = "async")]
+        self.wake_tasks();
+
+        #[cfg(feature = "event-listener")]
+        self.event().notify(usize::MAX);
+    }
+
+    /// Block until `done` returns `true`.  It's re-checked after every
+    /// notification, and possibly at other times.
+    pub(crate) fn wait_until<F>(&self, mut done: F)
+        where F: FnMut() -> bool,
+    {
+        while !done() {
+            // Announce ourselves before the final check.  A writer that
+            // publishes after that check is sure to see the bit, and will
+            // change the word before we can sleep on it.
+            #[cfg(not(feature = "event-listener"))]
+            {
+                let w = self.word.fetch_or(WAITERS, Ordering::SeqCst) | WAITERS;
+                if done() {
+                    return;
+                }
+                wait(&self.word, w);
+            }
+
+            #[cfg(feature = "event-listener")]
+            {
+                let l = self.listen();
+                if done() {
+                    return;
+                }
+                l.wait();
+            }
+        }
+    }
+
+    /// Which slot of a global table belongs to this `Notify`.
+    ///
+    /// Tables hashed by address keep per-lock waiting state out of the
+    /// `Seqloq` itself.  Collisions only cause spurious wakeups.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn slot(&self) -> usize {
+        // Notifies are at least 4-byte aligned; mix in the higher bits.
+        let a = self.addr() >> 2;
+        (a ^ (a >> 6) ^ (a >> 12)) % SLOTS
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn addr(&self) -> usize {
+        self as *const Notify as usize
+    }
+}
+
+#[cfg(feature = "std")]
+const SLOTS: usize = 64;
+
+#[cfg(feature = "std")]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A fresh id for `park` or `subscribe`.
+#[cfg(feature = "std")]
+pub(crate) fn new_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+struct Subscriber {
+    addr: usize,
+    id: u64,
+    tx: Sender<u64>,
+}
+
+#[cfg(feature = "std")]
+static SUBSCRIBERS: [StdMutex<Vec<Subscriber>>; SLOTS]
+    = [const { StdMutex::new(Vec::new()) }; SLOTS];
+
+#[cfg(feature = "std")]
+impl Notify {
+    #[inline]
+    fn subscribers(&self) -> std::sync::MutexGuard<'static, Vec<Subscriber>> {
+        SUBSCRIBERS[self.slot()].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Send the version of every future write to `tx`, until `unsubscribe`.
+    pub(crate) fn subscribe(&self, id: u64, tx: Sender<u64>) {
+        let mut v = self.subscribers();
+        v.push(Subscriber { addr: self.addr(), id, tx });
+        self.word.fetch_or(SUBSCRIBED, Ordering::SeqCst);
+    }
+
+    pub(crate) fn unsubscribe(&self, id: u64) {
+        let mut v = self.subscribers();
+        v.retain(|s| s.id != id);
+        if !v.iter().any(|s| s.addr == self.addr()) {
+            self.word.fetch_and(!SUBSCRIBED, Ordering::SeqCst);
+        }
+    }
+
+    #[cold]
+    fn send(&self, version: u64) {
+        for s in self.subscribers().iter().filter(|s| s.addr == self.addr()) {
+            let _ = s.tx.send(version);
+        }
+    }
+}
+
+#[cfg(feature = "event-listener")]
+static EVENTS: [Event; SLOTS] = [const { Event::new() }; SLOTS];
+
+#[cfg(feature = "event-listener")]
+impl Notify {
+    #[inline]
+    fn event(&self) -> &'static Event {
+        &EVENTS[self.slot()]
+    }
+
+    /// Start listening for the next notification.
+    ///
+    /// As with `wait_until`, re-check the condition after this returns.
+    pub(crate) fn listen(&self) -> EventListener {
+        let l = self.event().listen();
+        self.word.fetch_or(WAITERS, Ordering::SeqCst);
+        l
+    }
+}
+
+// Registered wakers.  `Changed` uses these when there's no
+// `event-listener`.
+
+#[cfg(feature = "async")]
+struct Parked {
+    addr: usize,
+    id: u64,
+    waker: Waker,
+}
+
+#[cfg(feature = "async")]
+static PARKED: [StdMutex<Vec<Parked>>; SLOTS] = [const { StdMutex::new(Vec::new()) }; SLOTS];
+
+#[cfg(feature = "async")]
+impl Notify {
+    #[inline]
+    fn bucket(&self) -> &'static StdMutex<Vec<Parked>> {
+        &PARKED[self.slot()]
+    }
+
+    /// Arrange for `waker` to be woken by the next notification, replacing
+    /// any waker previously parked under `id`.
+    ///
+    /// As with `wait_until`, re-check the condition after this returns.
+    pub(crate) fn park(&self, id: u64, waker: &Waker) {
+        {
+            let mut v = self.bucket().lock().unwrap_or_else(|e| e.into_inner());
+            match v.iter_mut().find(|p| p.id == id) {
+                Some(p) => p.waker.clone_from(waker),
+                None => v.push(Parked { addr: self.addr(), id, waker: waker.clone() }),
+            }
+        }
+        self.word.fetch_or(WAITERS, Ordering::SeqCst);
+    }
+
+    /// Forget the waker parked under `id`, if any.
+    pub(crate) fn unpark(&self, id: u64) {
+        let mut v = self.bucket().lock().unwrap_or_else(|e| e.into_inner());
+        v.retain(|p| p.id != id);
+    }
+
+    fn wake_tasks(&self) {
+        let addr = self.addr();
+        let woken: Vec<Waker> = {
+            let mut v = self.bucket().lock().unwrap_or_else(|e| e.into_inner());
+            let mut woken = vec![];
+            v.retain(|p| {
+                if p.addr == addr {
+                    woken.push(p.waker.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            woken
+        };
+        for w in woken {
+            w.wake();
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! A seqlock which may be empty.
+
+use std::mem;
+
+use crate::{Seqloq, RawMutex, WordLock};
+
+/// A `Seqloq<Option<T>>` with `Option`-like methods.
+///
+/// Readers see either a complete value or `None`, never a half-cleared one.
+pub struct SeqloqOption<T, M = WordLock> {
+    inner: Seqloq<Option<T>, M>,
+}
+
+impl<T> SeqloqOption<T>
+    where T: Send + Copy,
+{
+    #[inline]
+    pub fn new(t: Option<T>) -> SeqloqOption<T> {
+        SeqloqOption::with_mutex(t, WordLock::new())
+    }
+
+    /// An empty `SeqloqOption`.
+    #[inline]
+    pub fn none() -> SeqloqOption<T> {
+        SeqloqOption::new(None)
+    }
+}
+
+impl<T, M> SeqloqOption<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Use `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(t: Option<T>, mutex: M) -> SeqloqOption<T, M> {
+        SeqloqOption {
+            inner: Seqloq::with_mutex(t, mutex),
+        }
+    }
+
+    /// Read the current value.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.inner.read()
+    }
+
+    /// Is there a value right now?
+    #[inline]
+    pub fn is_some(&self) -> bool {
+        self.inner.peek(|p| unsafe { (*p).is_some() })
+    }
+
+    /// Store a value, replacing any previous one.
+    #[inline]
+    pub fn set(&self, t: T) {
+        self.replace(Some(t));
+    }
+
+    /// Remove the value.
+    #[inline]
+    pub fn clear(&self) {
+        self.replace(None);
+    }
+
+    /// Remove the value and return it.
+    ///
+    /// If two threads race to `take`, only one of them gets the value.
+    #[inline]
+    pub fn take(&self) -> Option<T> {
+        self.replace(None)
+    }
+
+    /// Store `t` and return the previous value, in one write section.
+    #[inline]
+    pub fn replace(&self, t: Option<T>) -> Option<T> {
+        self.inner.update(|v| mem::replace(v, t))
+    }
+
+    /// Store `t` only if empty.  Returns `Err(t)` if there was already a
+    /// value.
+    #[inline]
+    pub fn insert(&self, t: T) -> Result<(), T> {
+        let mut g = self.inner.lock_staged();
+        if g.is_some() {
+            return Err(t);
+        }
+        *g = Some(t);
+        g.commit();
+        Ok(())
+    }
+}
+
+impl<T> Default for SeqloqOption<T>
+    where T: Send + Copy,
+{
+    fn default() -> SeqloqOption<T> {
+        SeqloqOption::none()
+    }
+}
+
+#[test]
+fn option() {
+    let x = SeqloqOption::none();
+    assert_eq!(x.get(), None);
+    assert!(!x.is_some());
+
+    x.set([1u8, 2]);
+    assert!(x.is_some());
+    assert_eq!(x.insert([3, 4]), Err([3, 4]));
+    assert_eq!(x.take(), Some([1, 2]));
+    assert_eq!(x.take(), None);
+
+    assert_eq!(x.insert([5, 6]), Ok(()));
+    x.clear();
+    assert_eq!(x.get(), None);
+}
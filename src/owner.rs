@@ -0,0 +1,53 @@
+//! Catching a thread that locks the same writer mutex twice.
+//!
+//! The inner mutex would just deadlock.  In debug builds each thread keeps a
+//! list of the writer mutexes it holds, and locking one of them again
+//! panics instead.  Release builds skip all this, as do builds without
+//! `std`, which has no thread-locals, and the model checkers, which run
+//! many logical threads on one real one.
+
+#[cfg(all(feature = "std", debug_assertions, not(loom), not(shuttle)))]
+mod imp {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[inline]
+    pub fn check(addr: usize) {
+        if HELD.with(|h| h.borrow().contains(&addr)) {
+            panic!("seqloq: this thread already holds the writer lock \
+                    (re-entrant lock() would deadlock)");
+        }
+    }
+
+    #[inline]
+    pub fn acquired(addr: usize) {
+        HELD.with(|h| h.borrow_mut().push(addr));
+    }
+
+    #[inline]
+    pub fn released(addr: usize) {
+        HELD.with(|h| {
+            let mut h = h.borrow_mut();
+            if let Some(i) = h.iter().rposition(|&a| a == addr) {
+                h.swap_remove(i);
+            }
+        });
+    }
+}
+
+#[cfg(not(all(feature = "std", debug_assertions, not(loom), not(shuttle))))]
+mod imp {
+    #[inline(always)]
+    pub fn check(_addr: usize) { }
+
+    #[inline(always)]
+    pub fn acquired(_addr: usize) { }
+
+    #[inline(always)]
+    pub fn released(_addr: usize) { }
+}
+
+pub use self::imp::{check, acquired, released};
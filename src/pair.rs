@@ -0,0 +1,87 @@
+//! Seqlocks over several values at once.
+//!
+//! Readers of a `SeqloqPair` always get both values from the same write,
+//! which is what the common "position plus timestamp" pattern needs.  The
+//! values are stored together as a tuple, so there's no need to bundle them
+//! into a purpose-built struct.
+
+use crate::{Seqloq, RawMutex, WordLock};
+
+macro_rules! tuple_seqloq {
+    ($(#[$attr:meta])* $name:ident; $($t:ident $v:ident $idx:tt),*) => {
+        $(#[$attr])*
+        pub struct $name<$($t,)* M = WordLock> {
+            inner: Seqloq<($($t,)*), M>,
+        }
+
+        impl<$($t,)*> $name<$($t,)*>
+            where $($t: Send + Copy,)*
+        {
+            #[inline]
+            pub fn new($($v: $t),*) -> $name<$($t,)*> {
+                $name::with_mutex($($v,)* WordLock::new())
+            }
+        }
+
+        impl<$($t,)* M> $name<$($t,)* M>
+            where $($t: Send + Copy,)*
+                  M: RawMutex,
+        {
+            /// Use `mutex` to exclude concurrent writers.
+            #[inline]
+            pub fn with_mutex($($v: $t,)* mutex: M) -> $name<$($t,)* M> {
+                $name {
+                    inner: Seqloq::with_mutex(($($v,)*), mutex),
+                }
+            }
+
+            /// Read all of the values, from the same generation.
+            #[inline]
+            pub fn get(&self) -> ($($t,)*) {
+                self.inner.read()
+            }
+
+            /// Replace all of the values in one write section.
+            #[inline]
+            pub fn set(&self, $($v: $t),*) {
+                self.inner.write(($($v,)*));
+            }
+
+            /// Change any of the values in one write section.
+            #[inline]
+            pub fn update<F>(&self, f: F)
+                where F: FnOnce($(&mut $t),*),
+            {
+                self.inner.update(|g| f($(&mut g.$idx),*))
+            }
+        }
+    }
+}
+
+tuple_seqloq! {
+    /// Two values which readers see in matching generations.
+    SeqloqPair; A a 0, B b 1
+}
+
+tuple_seqloq! {
+    /// Three values which readers see in matching generations.
+    SeqloqTriple; A a 0, B b 1, C c 2
+}
+
+tuple_seqloq! {
+    /// Four values which readers see in matching generations.
+    SeqloqQuad; A a 0, B b 1, C c 2, D d 3
+}
+
+#[test]
+fn pair() {
+    let x = SeqloqPair::new(1.5f64, 10u64);
+    assert_eq!(x.get(), (1.5, 10));
+    x.set(2.5, 20);
+    x.update(|pos, t| { *pos += 1.0; *t += 1; });
+    assert_eq!(x.get(), (3.5, 21));
+
+    let y = SeqloqQuad::new(1u8, 2u16, 3u32, 4u64);
+    y.update(|_, _, c, _| *c = 0);
+    assert_eq!(y.get(), (1, 2, 0, 4));
+}
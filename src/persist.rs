@@ -0,0 +1,267 @@
+//! Snapshots that survive a restart.
+//!
+//! `Seqloq::persist_to` starts a thread which writes each newly published
+//! value to a file, and `Seqloq::restore` reads back the newest complete one
+//! when the process starts again:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use seqloq::Seqloq;
+//!
+//! let state = Arc::new(Seqloq::restore("state.bin").unwrap_or_else(|_| Seqloq::new([0u64; 8])));
+//! let _persister = state.persist_to("state.bin").unwrap();
+//! state.write([1; 8]);
+//! ```
+//!
+//! The file has two slots, used alternately.  Each holds a sequence number,
+//! the payload size, a fingerprint of the payload type, a checksum, and the
+//! payload, all little-endian.  A crash in the middle of writing one slot
+//! leaves the other intact, and the checksum tells which is which.  Writes
+//! published in quick succession are coalesced, as the thread only ever
+//! writes the latest value.
+//!
+//! The fingerprint is a hash of the type's name, size and alignment, so
+//! `restore` turns down a file written for some other type.  It's no
+//! defense against a crafted file, which is why `restore` only builds types
+//! for which any bytes will do.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::any::type_name;
+use std::mem::{align_of, size_of};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{ptr, slice};
+
+use crate::{Seqloq, RawMutex, NoPadding, AnyBitPattern};
+
+const HEADER: usize = 32;
+
+/// How often the thread checks whether it's been asked to stop.
+const POLL: Duration = Duration::from_millis(50);
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325u64;
+    for &b in bytes {
+        h = (h ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+/// Identifies `T` well enough to catch a file written for another type.
+fn fingerprint<T>() -> u64 {
+    let mut bytes = type_name::<T>().as_bytes().to_vec();
+    bytes.extend_from_slice(&(size_of::<T>() as u64).to_le_bytes());
+    bytes.extend_from_slice(&(align_of::<T>() as u64).to_le_bytes());
+    fnv1a(&bytes)
+}
+
+fn bytes_of<T: NoPadding>(t: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(t as *const T as *const u8, size_of::<T>()) }
+}
+
+/// The file backing a persisted `Seqloq`.
+struct Slots {
+    file: File,
+    seq: u64,
+}
+
+impl Slots {
+    fn slot_len<T>() -> u64 {
+        (HEADER + size_of::<T>()) as u64
+    }
+
+    fn write<T: NoPadding>(&mut self, t: &T) -> io::Result<()> {
+        self.seq += 1;
+        let payload = bytes_of(t);
+        let mut buf = Vec::with_capacity(HEADER + payload.len());
+        buf.extend_from_slice(&self.seq.to_le_bytes());
+        buf.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&fingerprint::<T>().to_le_bytes());
+        buf.extend_from_slice(&[0; 8]);
+        buf.extend_from_slice(payload);
+        let sum = fnv1a(&buf);
+        buf[24..32].copy_from_slice(&sum.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start((self.seq % 2) * Slots::slot_len::<T>()))?;
+        self.file.write_all(&buf)?;
+        self.file.sync_data()
+    }
+
+    /// The newest intact slot sized for a `T`, as a sequence number,
+    /// fingerprint and payload.
+    fn read<T>(file: &mut File) -> io::Result<Option<(u64, u64, Vec<u8>)>> {
+        let mut best: Option<(u64, u64, Vec<u8>)> = None;
+        for i in 0..2 {
+            let mut buf = vec![0; HEADER + size_of::<T>()];
+            file.seek(SeekFrom::Start(i * Slots::slot_len::<T>()))?;
+            match file.read_exact(&mut buf) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => continue,
+                r => r?,
+            }
+            let word = |j: usize| u64::from_le_bytes(buf[j * 8..j * 8 + 8].try_into().unwrap());
+            let (seq, len, print, sum) = (word(0), word(1), word(2), word(3));
+            buf[24..32].fill(0);
+            if len != size_of::<T>() as u64 || sum != fnv1a(&buf) {
+                continue;
+            }
+            if best.as_ref().is_none_or(|&(s, _, _)| seq > s) {
+                best = Some((seq, print, buf.split_off(HEADER)));
+            }
+        }
+        Ok(best)
+    }
+}
+
+/// Writes new values to a file in the background.  Returned by
+/// `Seqloq::persist_to`.
+///
+/// Dropping it stops the thread, after it writes the latest value.
+pub struct Persister {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Persister {
+    /// Stop, and report any error the thread ran into.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(r)) => r,
+            Some(Err(_)) => Err(io::Error::other("seqloq persister thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Persister {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Sync + NoPadding + 'static,
+          M: RawMutex + 'static,
+{
+    /// Write the current value to `path`, and each newly published value
+    /// after it, from a background thread.
+    pub fn persist_to<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> io::Result<Persister> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true)
+            .truncate(false).open(path)?;
+        // Carry on from the newest slot even if it's of another type, so
+        // that the new values supersede it.
+        let seq = Slots::read::<T>(&mut file)?.map_or(0, |(s, _, _)| s);
+        let mut slots = Slots { file, seq };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let seqloq = Arc::clone(self);
+        let stopping = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let sub = seqloq.subscribe();
+            let mut last = None;
+            loop {
+                // Checked before reading, so that the final read comes after
+                // any write made before `stop`.
+                let done = stopping.load(Ordering::SeqCst);
+                let (t, v) = seqloq.read_versioned();
+                if last != Some(v) {
+                    slots.write(&t)?;
+                    last = Some(v);
+                }
+                if done {
+                    return Ok(());
+                }
+                match sub.recv_timeout(POLL) {
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => { }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+        });
+        Ok(Persister {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl<T> Seqloq<T>
+    where T: Send + AnyBitPattern,
+{
+    /// Load the newest intact snapshot from a file written by `persist_to`.
+    ///
+    /// Fails if the newest snapshot was written for a different type.
+    pub fn restore<P: AsRef<Path>>(path: P) -> io::Result<Seqloq<T>> {
+        let mut file = File::open(path)?;
+        match Slots::read::<T>(&mut file)? {
+            Some((_, print, payload)) if print == fingerprint::<T>() => {
+                let t = unsafe { ptr::read_unaligned(payload.as_ptr() as *const T) };
+                Ok(Seqloq::new(t))
+            }
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                "seqloq snapshot is of a different type")),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData,
+                "no intact seqloq snapshot in file")),
+        }
+    }
+}
+
+#[test]
+fn persist() {
+    let path = std::env::temp_dir().join(format!("seqloq-persist-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let x = Arc::new(Seqloq::new([1u32, 2, 3]));
+    let p = x.persist_to(&path).unwrap();
+    x.write([4, 5, 6]);
+    p.finish().unwrap();
+    assert_eq!(Seqloq::<[u32; 3]>::restore(&path).unwrap().read(), [4, 5, 6]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn torn_slot() {
+    let path = std::env::temp_dir().join(format!("seqloq-torn-{}", std::process::id()));
+    let file = OpenOptions::new().read(true).write(true).create(true)
+        .truncate(true).open(&path).unwrap();
+    let mut slots = Slots { file, seq: 0 };
+    slots.write(&[1u32, 2, 3]).unwrap();
+    slots.write(&[4u32, 5, 6]).unwrap();
+    assert_eq!(Seqloq::<[u32; 3]>::restore(&path).unwrap().read(), [4, 5, 6]);
+
+    // Tear the newer slot, in slot 0; the older one survives.
+    slots.file.seek(SeekFrom::Start(HEADER as u64)).unwrap();
+    slots.file.write_all(&[0xff; 4]).unwrap();
+    assert_eq!(Seqloq::<[u32; 3]>::restore(&path).unwrap().read(), [1, 2, 3]);
+
+    slots.file.seek(SeekFrom::Start(Slots::slot_len::<[u32; 3]>() + HEADER as u64)).unwrap();
+    slots.file.write_all(&[0xff; 4]).unwrap();
+    assert!(Seqloq::<[u32; 3]>::restore(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn wrong_type() {
+    let path = std::env::temp_dir().join(format!("seqloq-type-{}", std::process::id()));
+    let file = OpenOptions::new().read(true).write(true).create(true)
+        .truncate(true).open(&path).unwrap();
+    let mut slots = Slots { file, seq: 0 };
+    slots.write(&[1u32, 2, 3]).unwrap();
+    let err = Seqloq::<[i32; 3]>::restore(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(Seqloq::<[u32; 3]>::restore(&path).is_ok());
+
+    // A newer value of the new type wins.
+    let x = Arc::new(Seqloq::new([4i32, 5, 6]));
+    x.persist_to(&path).unwrap().finish().unwrap();
+    assert_eq!(Seqloq::<[i32; 3]>::restore(&path).unwrap().read(), [4, 5, 6]);
+    std::fs::remove_file(&path).unwrap();
+}
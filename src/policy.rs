@@ -0,0 +1,244 @@
+//! Fairness policies.
+//!
+//! A plain `Seqloq` gives writers priority: a write never waits for readers,
+//! and a reader retries for as long as writes keep landing on it.  For
+//! read-dominated services that can be the wrong way round.  The wrappers
+//! here change that, and are chosen at construction like any other mutex:
+//!
+//! ```
+//! use std::time::Duration;
+//! use seqloq::{Seqloq, WordLock, ReaderPriority, RateLimited};
+//!
+//! let a = Seqloq::with_mutex(0u64, ReaderPriority::new(WordLock::new()));
+//! let b = Seqloq::with_mutex(0u64,
+//!     RateLimited::new(WordLock::new(), Duration::from_millis(1)));
+//! # *a.lock() = 1;
+//! # *b.lock() = 1;
+//! # assert_eq!((a.read(), b.read()), (1, 1));
+//! ```
+
+use std::cell::UnsafeCell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::RawMutex;
+use crate::sync::{AtomicU32, Ordering, yield_now};
+
+/// Holds writers back while any reader is starving.
+///
+/// A reader which has retried `threshold` times counts as starving until it
+/// succeeds.  Meanwhile a writer may take the mutex, but waits before
+/// starting its write section.  Writers can therefore starve instead, if
+/// readers keep arriving and retrying.
+///
+/// A `peek` callback that panics while its reader is starving leaves
+/// writers blocked for good.
+pub struct ReaderPriority<M> {
+    inner: M,
+    threshold: u32,
+    starving: AtomicU32,
+}
+
+impl<M: RawMutex> ReaderPriority<M> {
+    /// Readers count as starving after three retries.
+    #[inline]
+    pub fn new(inner: M) -> ReaderPriority<M> {
+        ReaderPriority::with_threshold(inner, 3)
+    }
+
+    /// Readers count as starving after `threshold` retries.
+    #[inline]
+    pub fn with_threshold(inner: M, threshold: u32) -> ReaderPriority<M> {
+        ReaderPriority {
+            inner,
+            threshold: threshold.max(1),
+            starving: AtomicU32::new(0),
+        }
+    }
+
+    #[inline]
+    fn any_starving(&self) -> bool {
+        self.starving.load(Ordering::SeqCst) != 0
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for ReaderPriority<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+        while self.any_starving() {
+            yield_now();
+        }
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        if !self.inner.try_lock() {
+            return false;
+        }
+        if self.any_starving() {
+            unsafe { self.inner.unlock() };
+            return false;
+        }
+        true
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        if retries == self.threshold {
+            self.starving.fetch_add(1, Ordering::SeqCst);
+        }
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        if retries >= self.threshold {
+            self.starving.fetch_sub(1, Ordering::SeqCst);
+        }
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<crate::SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+/// Limits how often writers can invalidate reads.
+///
+/// Each write section starts at least `interval` after the previous one
+/// ended.  Writers wait, holding the mutex, until then.
+pub struct RateLimited<M> {
+    inner: M,
+    interval: Duration,
+    /// When the last write ended.  Protected by `inner`.
+    last: UnsafeCell<Option<Instant>>,
+}
+
+unsafe impl<M: Send> Send for RateLimited<M> { }
+unsafe impl<M: Sync> Sync for RateLimited<M> { }
+
+impl<M: RawMutex> RateLimited<M> {
+    #[inline]
+    pub fn new(inner: M, interval: Duration) -> RateLimited<M> {
+        RateLimited {
+            inner,
+            interval,
+            last: UnsafeCell::new(None),
+        }
+    }
+
+    /// How much longer the holder must wait.  The caller must hold `inner`.
+    #[inline]
+    fn remaining(&self) -> Duration {
+        match unsafe { *self.last.get() } {
+            Some(t) => self.interval.saturating_sub(t.elapsed()),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for RateLimited<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+        let wait = self.remaining();
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        if !self.inner.try_lock() {
+            return false;
+        }
+        if !self.remaining().is_zero() {
+            unsafe { self.inner.unlock() };
+            return false;
+        }
+        true
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe {
+            *self.last.get() = Some(Instant::now());
+            self.inner.unlock();
+        }
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<crate::SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+#[test]
+fn reader_priority() {
+    let m = ReaderPriority::with_threshold(crate::SpinLock::new(), 2);
+    m.reader_retried(1);
+    m.reader_retried(2);
+    assert!(!m.try_lock());
+    m.reader_done(2);
+    assert!(m.try_lock());
+    unsafe { m.unlock() };
+}
+
+#[test]
+fn rate_limited() {
+    let m = RateLimited::new(crate::SpinLock::new(), Duration::from_millis(20));
+    let start = Instant::now();
+    m.lock();
+    unsafe { m.unlock() };
+    assert!(!m.try_lock());
+    m.lock();
+    unsafe { m.unlock() };
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
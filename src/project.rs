@@ -0,0 +1,259 @@
+//! Read handles for part of the data.
+//!
+//! `project!` narrows a `Seqloq` down to one field of its data, and
+//! `SeqloqRef::index` down to one array element.  The resulting `SeqloqRef`
+//! copies out only that part, but still validates against the whole
+//! `Seqloq`'s sequence number.  Hand one to a subsystem that has no
+//! business seeing the rest.
+//!
+//! ```
+//! use seqloq::{Seqloq, project};
+//!
+//! #[derive(Clone, Copy)]
+//! struct Body { position: [f32; 3], velocity: [f32; 3], mass: f32 }
+//!
+//! let body = Seqloq::new(Body { position: [0.0; 3], velocity: [1.0; 3], mass: 2.0 });
+//! let velocity = project!(body, Body, velocity);
+//! let vy = velocity.index(1);
+//! assert_eq!(vy.read(), 1.0);
+//! ```
+//!
+//! The field path is found with `offset_of!`, so it can't depend on the
+//! value: a path into an enum variant, through a reference, or into a union
+//! doesn't compile.  `Seqloq::project` takes any closure instead, and is
+//! unsafe for that reason.
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+use crate::{Seqloq, SeqCount, RawMutex, WordLock, copy, read_section};
+
+/// A read-only handle to part of a `Seqloq`'s data.
+pub struct SeqloqRef<'a, U, M = WordLock> {
+    mutex: &'a M,
+    seqnum: &'a SeqCount,
+    ptr: *const U,
+}
+
+unsafe impl<U: Send, M: Sync> Send for SeqloqRef<'_, U, M> { }
+unsafe impl<U: Send, M: Sync> Sync for SeqloqRef<'_, U, M> { }
+
+/// Narrow a `Seqloq` or `SeqloqRef` whose data is of type `$t` down to the
+/// field at `$field`, such as `position` or `inner.0`.
+///
+/// Paths which could depend on the value are rejected:
+///
+/// ```compile_fail
+/// use seqloq::{Seqloq, project};
+///
+/// let x = Seqloq::new(Ok::<bool, u8>(true));
+/// project!(x, Result<bool, u8>, Ok.0);
+/// ```
+///
+/// ```compile_fail
+/// use seqloq::{Seqloq, project};
+///
+/// #[derive(Clone, Copy)]
+/// struct Flag { on: bool }
+/// #[derive(Clone, Copy)]
+/// struct Indirect { flag: &'static Flag }
+///
+/// static FLAG: Flag = Flag { on: true };
+/// let x = Seqloq::new(Indirect { flag: &FLAG });
+/// project!(x, Indirect, flag.on);
+/// ```
+///
+/// ```compile_fail
+/// use seqloq::{Seqloq, project};
+///
+/// #[derive(Clone, Copy)]
+/// union Either { byte: u8, flag: bool }
+///
+/// let x = Seqloq::new(Either { byte: 7 });
+/// project!(x, Either, flag);
+/// ```
+#[macro_export]
+macro_rules! project {
+    ($x:expr, $t:ty, $($field:tt).+) => {{
+        let x = &$x;
+        let pick = $crate::project::pick::<$t, _>(|t| &t.$($field).+);
+        let at = ::core::mem::offset_of!($t, $($field).+);
+        // Safe: `offset_of!` only takes a plain path of fields, and `pick`
+        // checks that it ends at a `U`.
+        #[allow(unused_unsafe)]
+        unsafe { x.project_at(at, pick) }
+    }};
+}
+
+/// The type of the field that `project!` picks out.  Not meant to be used
+/// directly.
+#[doc(hidden)]
+pub struct Pick<T, U>(PhantomData<fn(&T) -> &U>);
+
+#[doc(hidden)]
+pub fn pick<T, U>(_: impl FnOnce(&T) -> &U) -> Pick<T, U> {
+    Pick(PhantomData)
+}
+
+/// Where `f` finds its result within `t`, in bytes.
+fn offset<T, U, F>(t: &T, f: F) -> usize
+    where F: FnOnce(&T) -> &U,
+{
+    let base = t as *const T as usize;
+    let p = f(t) as *const U as usize;
+    assert!(p >= base && p + size_of::<U>() <= base + size_of::<T>(),
+        "seqloq: a projection must return a reference into its argument");
+    p - base
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// A handle which reads only the part of the data that `f` picks out.
+    /// Prefer `project!`, which needs no `unsafe`.
+    ///
+    /// Panics if `f` returns a reference to something outside its argument.
+    ///
+    /// # Safety
+    ///
+    /// `f` is called once, on a copy of the data, to find where that part
+    /// lives.  It must pick out the same bytes, holding a valid `U`, for
+    /// every value the data will ever have: a field or a fixed array
+    /// element is fine, but the payload of one enum variant is not.
+    pub unsafe fn project<U, F>(&self, f: F) -> SeqloqRef<'_, U, M>
+        where U: Send + Copy,
+              F: FnOnce(&T) -> &U,
+    {
+        let at = offset(&self.read(), f);
+        unsafe { self.project_at(at, Pick(PhantomData)) }
+    }
+
+    /// The part of the data `at` bytes in, for `project!`.
+    #[doc(hidden)]
+    pub unsafe fn project_at<U>(&self, at: usize, _: Pick<T, U>) -> SeqloqRef<'_, U, M> {
+        debug_assert!(at + size_of::<U>() <= size_of::<T>());
+        SeqloqRef {
+            mutex: &self.mutex,
+            seqnum: &self.seqnum,
+            ptr: unsafe { (self.data.get() as *const u8).add(at) as *const U },
+        }
+    }
+}
+
+impl<'a, U, M> SeqloqRef<'a, U, M>
+    where U: Send + Copy,
+          M: RawMutex,
+{
+    /// Read this part of the data.
+    #[inline]
+    pub fn read(&self) -> U {
+        self.read_versioned().0
+    }
+
+    /// Read this part of the data, along with the version of the whole
+    /// `Seqloq` it was read at.
+    #[inline]
+    pub fn read_versioned(&self) -> (U, u64) {
+        read_section(self.mutex, self.seqnum, || unsafe { copy::load(self.ptr) })
+    }
+
+    /// The version of the whole `Seqloq`.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    /// Narrow this handle further.  Prefer `project!` or `index`.
+    ///
+    /// # Safety
+    ///
+    /// As for `Seqloq::project`.
+    pub unsafe fn project<V, F>(&self, f: F) -> SeqloqRef<'a, V, M>
+        where V: Send + Copy,
+              F: FnOnce(&U) -> &V,
+    {
+        let at = offset(&self.read(), f);
+        unsafe { self.project_at(at, Pick(PhantomData)) }
+    }
+
+    /// The part of this part `at` bytes in, for `project!`.
+    #[doc(hidden)]
+    pub unsafe fn project_at<V>(&self, at: usize, _: Pick<U, V>) -> SeqloqRef<'a, V, M> {
+        debug_assert!(at + size_of::<V>() <= size_of::<U>());
+        SeqloqRef {
+            mutex: self.mutex,
+            seqnum: self.seqnum,
+            ptr: unsafe { (self.ptr as *const u8).add(at) as *const V },
+        }
+    }
+}
+
+impl<'a, V, M, const N: usize> SeqloqRef<'a, [V; N], M>
+    where V: Send + Copy,
+          M: RawMutex,
+{
+    /// Narrow this handle to element `i` of the array.
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn index(&self, i: usize) -> SeqloqRef<'a, V, M> {
+        assert!(i < N, "seqloq: index {} out of bounds for length {}", i, N);
+        SeqloqRef {
+            mutex: self.mutex,
+            seqnum: self.seqnum,
+            ptr: unsafe { (self.ptr as *const V).add(i) },
+        }
+    }
+}
+
+impl<U, M> Clone for SeqloqRef<'_, U, M> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<U, M> Copy for SeqloqRef<'_, U, M> { }
+
+#[test]
+fn project() {
+    let x = Seqloq::new(([1u8, 2, 3], 4u64));
+    let a = project!(x, ([u8; 3], u64), 0);
+    let b = project!(x, ([u8; 3], u64), 1);
+    let a2 = a.index(2);
+    x.write(([5, 6, 7], 8));
+    assert_eq!((a.read(), b.read(), a2.read()), ([5, 6, 7], 8, 7));
+    assert_eq!(a2.read_versioned(), (7, 2));
+    assert_eq!(b.version(), 2);
+}
+
+#[test]
+fn project_nested() {
+    #[derive(Clone, Copy)]
+    struct Inner { a: u16, b: (u8, u32) }
+    #[derive(Clone, Copy)]
+    struct Outer { x: u64, inner: Inner }
+
+    let s = Seqloq::new(Outer { x: 1, inner: Inner { a: 2, b: (3, 4) } });
+    let x = project!(s, Outer, x);
+    let b1 = project!(s, Outer, inner.b.1);
+    let inner = project!(s, Outer, inner);
+    let a = project!(inner, Inner, a);
+    s.write(Outer { x: 5, inner: Inner { a: 6, b: (7, 8) } });
+    assert_eq!((x.read(), b1.read(), a.read()), (5, 8, 6));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn index_outside() {
+    let x = Seqloq::new(([1u8, 2, 3], 4u64));
+    project!(x, ([u8; 3], u64), 0).index(3);
+}
+
+#[test]
+#[should_panic(expected = "reference into its argument")]
+fn project_outside() {
+    static OTHER: u32 = 0;
+    let x = Seqloq::new(1u32);
+    unsafe { x.project(|_| &OTHER); }
+}
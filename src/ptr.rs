@@ -0,0 +1,124 @@
+//! Seqlocks over memory the lock doesn't own.
+//!
+//! `SeqloqPtr` runs the same protocol as `Seqloq`, but on data somewhere
+//! else: a `static mut`, an arena, a memory-mapped region.  The caller is
+//! responsible for keeping that memory alive, and for making sure nobody
+//! touches it except through the `SeqloqPtr`.
+
+use crate::{SeqloqGuard, SeqloqStagedGuard, SeqCount, RawMutex, WordLock, Parts};
+
+/// A seqlock protecting externally owned data.
+pub struct SeqloqPtr<T, M = WordLock> {
+    mutex: M,
+    seqnum: SeqCount,
+    data: *mut T,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqPtr<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqPtr<T, M> { }
+
+impl<T> SeqloqPtr<T>
+    where T: Send + Copy,
+{
+    /// Protect the data at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null, properly aligned, and valid for reads and
+    /// writes of `T` for as long as the `SeqloqPtr` (or any guard borrowed
+    /// from it) exists.  The data must hold a valid `T` to begin with.  While
+    /// the `SeqloqPtr` exists, all access to the data must go through it.
+    #[inline]
+    pub unsafe fn from_raw(ptr: *mut T) -> SeqloqPtr<T> {
+        unsafe { SeqloqPtr::from_raw_with_mutex(ptr, WordLock::new()) }
+    }
+}
+
+impl<T, M> SeqloqPtr<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Protect the data at `ptr`, using `mutex` to exclude concurrent
+    /// writers.
+    ///
+    /// # Safety
+    ///
+    /// As for `from_raw`.
+    #[inline]
+    pub unsafe fn from_raw_with_mutex(ptr: *mut T, mutex: M) -> SeqloqPtr<T, M> {
+        SeqloqPtr {
+            mutex,
+            seqnum: SeqCount::new(),
+            data: ptr,
+        }
+    }
+
+    /// The protected pointer.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut T {
+        self.data
+    }
+
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, T, M> {
+        Parts {
+            mutex: &self.mutex,
+            notify: None,
+            seqnum: &self.seqnum,
+            data: self.data,
+        }
+    }
+
+    /// As `Seqloq::peek`.
+    #[inline]
+    pub fn peek<F, R>(&self, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts().peek_versioned(f).0
+    }
+
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.parts().read()
+    }
+
+    /// As `Seqloq::lock`.
+    #[inline]
+    pub fn lock(&self) -> SeqloqGuard<'_, T, M> {
+        self.parts().lock()
+    }
+
+    /// As `Seqloq::lock_staged`.
+    #[inline]
+    pub fn lock_staged(&self) -> SeqloqStagedGuard<'_, T, M> {
+        self.parts().lock_staged()
+    }
+
+    /// As `Seqloq::write`.
+    #[inline]
+    pub fn write(&self, t: T) {
+        self.parts().write(t)
+    }
+
+    /// As `Seqloq::update`.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts().update(f)
+    }
+}
+
+#[test]
+fn external() {
+    let mut storage = Box::new([1u32, 2, 3]);
+    {
+        let x = unsafe { SeqloqPtr::from_raw(&mut *storage) };
+        assert_eq!(x.read(), [1, 2, 3]);
+        x.lock()[0] = 4;
+        assert_eq!(x.read(), [4, 2, 3]);
+        assert_eq!(x.as_ptr(), &mut *storage as *mut [u32; 3]);
+    }
+    assert_eq!(*storage, [4, 2, 3]);
+}
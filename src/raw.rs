@@ -0,0 +1,148 @@
+//! Integration with the `lock_api` crate.
+//!
+//! `lock_api` hands out plain references to readers, so its shared locks
+//! can't be optimistic.  `RawSeqloq` is the closest analogue: the writer
+//! side uses the seqlock protocol (an odd sequence number means a writer is
+//! active, and writers have absolute priority over new readers), while
+//! readers are counted so that a writer can wait for them to drain.
+//!
+//! Only available with the `lock-api` feature.
+
+use std::thread;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lock_api::{RawRwLock, GuardSend};
+
+/// Raw reader-writer lock with writer priority.
+pub struct RawSeqloq {
+    seqnum: AtomicUsize,
+    readers: AtomicUsize,
+}
+
+/// A `lock_api::RwLock` backed by `RawSeqloq`.
+pub type SeqRwLock<T> = lock_api::RwLock<RawSeqloq, T>;
+pub type SeqRwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawSeqloq, T>;
+pub type SeqRwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawSeqloq, T>;
+
+impl RawSeqloq {
+    /// The current sequence number.
+    ///
+    /// It's odd while a writer holds or is waiting for the lock, and it
+    /// changes on every write.
+    #[inline]
+    pub fn seqnum(&self) -> usize {
+        self.seqnum.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn try_begin_write(&self) -> bool {
+        let old = self.seqnum.load(Ordering::SeqCst);
+        (old & 1) == 0 &&
+            self.seqnum.compare_exchange(old, old+1, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+    }
+
+    #[inline]
+    fn try_enter_shared(&self) -> bool {
+        if (self.seqnum.load(Ordering::SeqCst) & 1) != 0 {
+            return false;
+        }
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        if (self.seqnum.load(Ordering::SeqCst) & 1) != 0 {
+            // A writer got in first; let it proceed.
+            self.readers.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+}
+
+unsafe impl RawRwLock for RawSeqloq {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: RawSeqloq = RawSeqloq {
+        seqnum: AtomicUsize::new(0),
+        readers: AtomicUsize::new(0),
+    };
+
+    type GuardMarker = GuardSend;
+
+    #[inline]
+    fn lock_shared(&self) {
+        while !self.try_enter_shared() {
+            // FIXME: smarter spinlocking
+            thread::yield_now();
+        }
+    }
+
+    #[inline]
+    fn try_lock_shared(&self) -> bool {
+        self.try_enter_shared()
+    }
+
+    #[inline]
+    unsafe fn unlock_shared(&self) {
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[inline]
+    fn lock_exclusive(&self) {
+        while !self.try_begin_write() {
+            thread::yield_now();
+        }
+        // New readers are now held off; wait for the old ones to leave.
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+    }
+
+    #[inline]
+    fn try_lock_exclusive(&self) -> bool {
+        if !self.try_begin_write() {
+            return false;
+        }
+        if self.readers.load(Ordering::SeqCst) != 0 {
+            // Back out.  Nothing was written, but the sequence number must
+            // still move forward.
+            self.seqnum.fetch_add(1, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+
+    #[inline]
+    unsafe fn unlock_exclusive(&self) {
+        self.seqnum.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        (self.seqnum.load(Ordering::SeqCst) & 1) != 0
+            || self.readers.load(Ordering::SeqCst) != 0
+    }
+
+    #[inline]
+    fn is_locked_exclusive(&self) -> bool {
+        (self.seqnum.load(Ordering::SeqCst) & 1) != 0
+    }
+}
+
+#[test]
+fn smoke_test() {
+    let x: SeqRwLock<u32> = SeqRwLock::new(3);
+
+    {
+        let r1 = x.read();
+        let r2 = x.read();
+        assert_eq!(*r1 + *r2, 6);
+        assert!(x.try_write().is_none());
+    }
+
+    {
+        let mut g = x.write();
+        *g = 4;
+        assert!(x.try_read().is_none());
+    }
+
+    assert_eq!(*x.read(), 4);
+    // The failed try_write also counts as a write.
+    assert_eq!(unsafe { x.raw() }.seqnum(), 4);
+}
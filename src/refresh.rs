@@ -0,0 +1,205 @@
+//! Data refreshed on a timer.
+//!
+//! `Refresher` owns a `Seqloq` and a thread which calls a fetch function
+//! every so often and publishes the result.  Caches of DNS lookups, feature
+//! flags, exchange rates and the like all look like this.
+//!
+//! ```
+//! use std::sync::atomic::{AtomicU32, Ordering};
+//! use std::time::Duration;
+//! use seqloq::refresh::Refresher;
+//!
+//! static FETCHES: AtomicU32 = AtomicU32::new(0);
+//!
+//! let rates = Refresher::spawn(Duration::from_secs(60), || {
+//!     FETCHES.fetch_add(1, Ordering::SeqCst)
+//! });
+//! assert_eq!(rates.read(), 0);
+//! let v = rates.version();
+//! rates.refresh_now();
+//! assert_eq!(rates.wait_for_change(v).0, 1);
+//! ```
+
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::Seqloq;
+
+#[derive(Default)]
+struct Control {
+    paused: bool,
+    trigger: bool,
+    stop: bool,
+}
+
+struct Shared<T> {
+    value: Seqloq<T>,
+    control: Mutex<Control>,
+    cond: Condvar,
+}
+
+/// A `Seqloq` republished periodically by a background thread.
+///
+/// Dereferences to the `Seqloq`, for reading, waiting and subscribing.
+/// Writing to it directly works too, until the next refresh.  Dropping the
+/// `Refresher` stops the thread, waiting for any fetch in progress.
+pub struct Refresher<T> {
+    shared: Arc<Shared<T>>,
+    interval: Duration,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + Copy + 'static> Refresher<T> {
+    /// Call `fetch` for an initial value, then again from a new thread
+    /// every `interval`, publishing each result.
+    ///
+    /// The interval is measured from the end of one fetch to the start of
+    /// the next.
+    pub fn spawn<F>(interval: Duration, mut fetch: F) -> Refresher<T>
+        where F: FnMut() -> T + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            value: Seqloq::new(fetch()),
+            control: Mutex::new(Control::default()),
+            cond: Condvar::new(),
+        });
+        let sh = Arc::clone(&shared);
+        let thread = thread::spawn(move || {
+            while sh.next(interval) {
+                sh.value.write(fetch());
+            }
+        });
+        Refresher {
+            shared,
+            interval,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    /// Wait until it's time for the next fetch.  `false` means stop.
+    fn next(&self, interval: Duration) -> bool {
+        let deadline = Instant::now() + interval;
+        let mut c = self.control.lock().unwrap();
+        loop {
+            if c.stop {
+                return false;
+            }
+            if c.trigger {
+                c.trigger = false;
+                return true;
+            }
+            let now = Instant::now();
+            if c.paused {
+                c = self.cond.wait(c).unwrap();
+            } else if now >= deadline {
+                return true;
+            } else {
+                c = self.cond.wait_timeout(c, deadline - now).unwrap().0;
+            }
+        }
+    }
+
+    fn control<F: FnOnce(&mut Control)>(&self, f: F) {
+        f(&mut self.control.lock().unwrap());
+        self.cond.notify_all();
+    }
+}
+
+impl<T> Refresher<T> {
+    /// Stop refreshing on the timer.  `refresh_now` still works.
+    pub fn pause(&self) {
+        self.shared.control(|c| c.paused = true);
+    }
+
+    /// Start refreshing on the timer again.
+    ///
+    /// The next refresh is still due a full interval after the last one
+    /// ended, as if there had been no pause.  If the pause lasted longer
+    /// than that, the value is stale, and it's refreshed straight away.
+    pub fn resume(&self) {
+        self.shared.control(|c| c.paused = false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.control.lock().unwrap().paused
+    }
+
+    /// Fetch again as soon as possible, without waiting for the timer.
+    ///
+    /// This returns straight away.  To wait for the new value, pass the
+    /// version from before the call to `wait_for_change`.
+    pub fn refresh_now(&self) {
+        self.shared.control(|c| c.trigger = true);
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl<T> Deref for Refresher<T> {
+    type Target = Seqloq<T>;
+
+    #[inline]
+    fn deref(&self) -> &Seqloq<T> {
+        &self.shared.value
+    }
+}
+
+impl<T> Drop for Refresher<T> {
+    fn drop(&mut self) {
+        self.shared.control(|c| c.stop = true);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+#[test]
+fn refresher() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let n = Arc::new(AtomicU32::new(0));
+    let m = Arc::clone(&n);
+    let r = Refresher::spawn(Duration::from_millis(1), move || m.fetch_add(1, Ordering::SeqCst));
+    r.wait_until(|&x| x >= 3);
+
+    r.pause();
+    assert!(r.is_paused());
+    thread::sleep(Duration::from_millis(10));
+    let (x, v) = r.read_versioned();
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(r.read(), x);
+
+    r.refresh_now();
+    assert_eq!(r.wait_for_change(v).0, x + 1);
+    r.resume();
+    r.wait_until(|&y| y > x + 1);
+    drop(r);
+    assert_eq!(Arc::strong_count(&n), 1);
+}
+
+#[test]
+fn resume_when_due() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let interval = Duration::from_secs(1);
+    let n = Arc::new(AtomicU32::new(0));
+    let m = Arc::clone(&n);
+    let r = Refresher::spawn(interval, move || m.fetch_add(1, Ordering::SeqCst));
+    r.pause();
+    thread::sleep(interval + Duration::from_millis(200));
+    let v = r.version();
+    assert_eq!(r.read(), 0);
+
+    // Overdue, so it doesn't wait another interval.
+    let start = Instant::now();
+    r.resume();
+    assert_eq!(r.wait_for_change(v).0, 1);
+    assert!(start.elapsed() < interval, "{:?}", start.elapsed());
+}
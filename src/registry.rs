@@ -0,0 +1,142 @@
+//! A process-wide list of named `Seqloq`s, for inspection at runtime.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use seqloq::{Seqloq, Stats, WordLock, registry};
+//!
+//! let routes = Arc::new(Seqloq::with_mutex(0u32, Stats::new(WordLock::new())));
+//! routes.register("routes");
+//! routes.write(1);
+//!
+//! let e = registry::entries().into_iter().find(|e| e.name == "routes").unwrap();
+//! assert_eq!(e.version, 2);
+//! assert_eq!(e.stats.unwrap().writes, 1);
+//! ```
+//!
+//! The registry holds weak references: dropping the last `Arc` takes an
+//! entry off the list.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::{Seqloq, RawMutex, SeqloqStats};
+
+/// What the registry needs from a `Seqloq`, whatever its type.
+trait Inspect: Send + Sync {
+    fn version(&self) -> u64;
+    fn is_locked(&self) -> bool;
+    fn stats(&self) -> Option<SeqloqStats>;
+}
+
+impl<T: Send, M: RawMutex> Inspect for Seqloq<T, M> {
+    fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    fn is_locked(&self) -> bool {
+        self.mutex.is_locked()
+    }
+
+    fn stats(&self) -> Option<SeqloqStats> {
+        self.mutex.stats()
+    }
+}
+
+static REGISTRY: Mutex<Vec<(&'static str, Weak<dyn Inspect>)>> = Mutex::new(Vec::new());
+
+/// The state of a registered `Seqloq` at one moment.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub name: &'static str,
+    /// The version, which is odd while a write is in progress.
+    pub version: u64,
+    /// Whether the writer mutex is held.
+    pub locked: bool,
+    /// Statistics, if the writer mutex keeps them.
+    pub stats: Option<SeqloqStats>,
+}
+
+impl Entry {
+    /// Is a write in progress?
+    #[inline]
+    pub fn writing(&self) -> bool {
+        self.version & 1 == 1
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: version {}", self.name, self.version)?;
+        if self.writing() {
+            f.write_str(" (writing)")?;
+        } else if self.locked {
+            f.write_str(" (locked)")?;
+        }
+        if let Some(s) = self.stats {
+            write!(f, ", {} reads, {} retries, {} writes, max hold {:?}",
+                s.reads, s.retries, s.writes, s.max_write_hold)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + 'static,
+          M: RawMutex + 'static,
+{
+    /// Add this `Seqloq` to the registry under `name`.
+    ///
+    /// Names needn't be unique.
+    pub fn register(self: &Arc<Self>, name: &'static str) {
+        let weak: Weak<dyn Inspect> = Arc::downgrade(self) as Weak<Seqloq<T, M>>;
+        let mut reg = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        reg.retain(|(_, w)| w.strong_count() > 0);
+        reg.push((name, weak));
+    }
+}
+
+/// Every live registered `Seqloq`, in order of registration.
+pub fn entries() -> Vec<Entry> {
+    let mut reg = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    reg.retain(|(_, w)| w.strong_count() > 0);
+    reg.iter()
+        .filter_map(|(name, w)| {
+            let s = w.upgrade()?;
+            Some(Entry {
+                name,
+                version: s.version(),
+                locked: s.is_locked(),
+                stats: s.stats(),
+            })
+        })
+        .collect()
+}
+
+/// One line per registered `Seqloq`.
+pub fn dump() -> String {
+    entries().iter().map(|e| format!("{}\n", e)).collect()
+}
+
+#[test]
+fn registry() {
+    let a = Arc::new(Seqloq::new(0u32));
+    a.register("registry::a");
+    let b = Arc::new(Seqloq::new(0u32));
+    b.register("registry::b");
+    b.write(1);
+    let _g = b.lock_staged();
+
+    let names = |es: &[Entry]| es.iter().map(|e| e.name)
+        .filter(|n| n.starts_with("registry::")).collect::<Vec<_>>();
+    let es = entries();
+    assert_eq!(names(&es), ["registry::a", "registry::b"]);
+    let eb = es.iter().find(|e| e.name == "registry::b").unwrap();
+    assert_eq!(eb.version, 2);
+    assert!(!eb.writing());
+    assert!(eb.stats.is_none());
+    assert!(eb.locked);
+    assert!(dump().contains("registry::b: version 2 (locked)\n"));
+
+    drop((a, _g));
+    assert_eq!(names(&entries()), ["registry::b"]);
+}
@@ -0,0 +1,175 @@
+//! Per-thread replicas: read locally, write everywhere.
+//!
+//! Every `Seqloq` reader loads the same sequence counter, so at very high
+//! read rates the cache line holding it bounces between cores whenever it's
+//! written.  `SeqloqReplicated` gives each reader its own copy of the data,
+//! on its own cache line.  Reads only touch that replica.  A write updates
+//! the master copy and then every replica in turn, so writes get slower
+//! with each reader.
+//!
+//! Each replica is updated atomically, but not all at once: right after a
+//! write, one reader may see the new value while another still sees the old.
+//! `version` tells readers which write they're looking at.
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+
+use crate::{RawMutex, RawGuard, SeqCount, WordLock, Parts, store};
+
+/// One reader's copy, padded to keep it off its neighbours' cache lines.
+#[repr(align(128))]
+struct Replica<T> {
+    seqnum: SeqCount,
+    /// The data, and the number of writes it reflects.
+    data: UnsafeCell<(u64, T)>,
+}
+
+unsafe impl<T: Send> Send for Replica<T> { }
+unsafe impl<T: Send> Sync for Replica<T> { }
+
+/// Data replicated to each reader, for reads that never share a cache line.
+pub struct SeqloqReplicated<T, M = WordLock> {
+    mutex: M,
+    /// Everything below is protected by `mutex`.
+    version: UnsafeCell<u64>,
+    master: UnsafeCell<T>,
+    replicas: UnsafeCell<Vec<Arc<Replica<T>>>>,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqReplicated<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqReplicated<T, M> { }
+
+/// A reader's handle to its own replica.
+///
+/// Create one per thread with `SeqloqReplicated::reader`.
+pub struct ReplicaReader<'a, T, M = WordLock> {
+    mutex: &'a M,
+    replica: Arc<Replica<T>>,
+}
+
+impl<T> SeqloqReplicated<T>
+    where T: Send + Copy,
+{
+    #[inline]
+    pub fn new(t: T) -> SeqloqReplicated<T> {
+        SeqloqReplicated::with_mutex(t, WordLock::new())
+    }
+}
+
+impl<T, M> SeqloqReplicated<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Use `mutex` to exclude concurrent writers.
+    #[inline]
+    pub fn with_mutex(t: T, mutex: M) -> SeqloqReplicated<T, M> {
+        SeqloqReplicated {
+            mutex,
+            version: UnsafeCell::new(0),
+            master: UnsafeCell::new(t),
+            replicas: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    /// Create a new replica, holding the current value.
+    pub fn reader(&self) -> ReplicaReader<'_, T, M> {
+        let _guard = RawGuard::lock(&self.mutex);
+        let replica = unsafe {
+            Arc::new(Replica {
+                seqnum: SeqCount::new(),
+                data: UnsafeCell::new((*self.version.get(), *self.master.get())),
+            })
+        };
+        unsafe {
+            (*self.replicas.get()).push(replica.clone());
+        }
+        ReplicaReader {
+            mutex: &self.mutex,
+            replica,
+        }
+    }
+
+    /// The number of live readers.
+    pub fn readers(&self) -> usize {
+        let _guard = RawGuard::lock(&self.mutex);
+        self.prune();
+        unsafe { (*self.replicas.get()).len() }
+    }
+
+    /// Replace the data, in the master copy and every replica.
+    pub fn write(&self, t: T) {
+        let _guard = RawGuard::lock(&self.mutex);
+        self.prune();
+        unsafe {
+            let version = self.version.get();
+            *version += 1;
+            *self.master.get() = t;
+            for r in &*self.replicas.get() {
+                store(&r.seqnum, r.data.get(), (*version, t));
+            }
+        }
+    }
+
+    /// The value most recently written.
+    ///
+    /// This takes the writer mutex.  Readers should use their replica.
+    pub fn get(&self) -> T {
+        let _guard = RawGuard::lock(&self.mutex);
+        unsafe { *self.master.get() }
+    }
+
+    /// Forget the replicas of readers that have gone away.  The caller must
+    /// hold the writer mutex.
+    #[inline]
+    fn prune(&self) {
+        unsafe {
+            (*self.replicas.get()).retain(|r| Arc::strong_count(r) > 1);
+        }
+    }
+}
+
+impl<T, M> ReplicaReader<'_, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    #[inline(always)]
+    fn parts(&self) -> Parts<'_, (u64, T), M> {
+        Parts {
+            mutex: self.mutex,
+            notify: None,
+            seqnum: &self.replica.seqnum,
+            data: self.replica.data.get(),
+        }
+    }
+
+    /// Read this reader's replica.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.parts().read().1
+    }
+
+    /// Read this reader's replica, along with the number of writes it
+    /// reflects.
+    #[inline]
+    pub fn read_versioned(&self) -> (T, u64) {
+        let (v, t) = self.parts().read();
+        (t, v)
+    }
+}
+
+#[test]
+fn replicated() {
+    let x = SeqloqReplicated::new(1u32);
+    let a = x.reader();
+    x.write(2);
+    let b = x.reader();
+    assert_eq!(a.read(), 2);
+    assert_eq!(b.read_versioned(), (2, 1));
+    assert_eq!(x.readers(), 2);
+
+    drop(a);
+    x.write(3);
+    assert_eq!(x.readers(), 1);
+    assert_eq!(b.read_versioned(), (3, 2));
+    assert_eq!(x.get(), 3);
+}
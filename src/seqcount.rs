@@ -0,0 +1,152 @@
+//! The bare sequence-counter protocol.
+//!
+//! `SeqCount` is the equivalent of the Linux kernel's `seqcount_t`: a
+//! sequence counter with no data and no writer exclusion of its own.  Use it
+//! to protect data whose storage you control, with writers excluded by a lock
+//! you already have.  `Seqloq` is built on it.
+//!
+//! ```
+//! use std::sync::Mutex;
+//! use std::cell::UnsafeCell;
+//! use seqloq::SeqCount;
+//!
+//! struct Position {
+//!     writer: Mutex<()>,
+//!     seq: SeqCount,
+//!     x: UnsafeCell<i32>,
+//!     y: UnsafeCell<i32>,
+//! }
+//!
+//! impl Position {
+//!     fn set(&self, x: i32, y: i32) {
+//!         let _g = self.writer.lock().unwrap();
+//!         self.seq.write_begin();
+//!         unsafe {
+//!             *self.x.get() = x;
+//!             *self.y.get() = y;
+//!         }
+//!         self.seq.write_end();
+//!     }
+//!
+//!     fn get(&self) -> (i32, i32) {
+//!         loop {
+//!             let start = self.seq.read_begin();
+//!             let v = unsafe { (*self.x.get(), *self.y.get()) };
+//!             if !self.seq.read_retry(start) {
+//!                 return v;
+//!             }
+//!         }
+//!     }
+//! }
+//! # let p = Position { writer: Mutex::new(()), seq: SeqCount::new(),
+//! #     x: UnsafeCell::new(0), y: UnsafeCell::new(0) };
+//! # p.set(1, 2);
+//! # assert_eq!(p.get(), (1, 2));
+//! ```
+
+use crate::hook;
+use crate::sync::{AtomicSeq, Ordering, fence, yield_now, widen};
+
+/// A sequence counter.
+///
+/// Odd values mean a write is in progress.  Every write advances the counter
+/// by two, so a reader which sees the same even value before and after
+/// reading knows it saw no writes.
+#[repr(transparent)]
+pub struct SeqCount {
+    pub(crate) seq: AtomicSeq,
+}
+
+impl Default for SeqCount {
+    fn default() -> SeqCount {
+        SeqCount::new()
+    }
+}
+
+impl SeqCount {
+    #[inline]
+    pub fn new() -> SeqCount {
+        SeqCount {
+            seq: AtomicSeq::new(0),
+        }
+    }
+
+    /// The current value of the counter.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        widen(self.seq.load(Ordering::SeqCst))
+    }
+
+    /// Start a read section, waiting for any active writer to finish.
+    ///
+    /// Pass the result to `read_retry` once you're done reading.
+    #[inline]
+    pub fn read_begin(&self) -> u64 {
+        loop {
+            // Seeing an even count stored by `write_end` makes that whole
+            // write happen before this read.
+            let v = self.version();
+            if (v & 1) == 0 {
+                return v;
+            }
+            // A writer is active.  Let it run, in case it's waiting for
+            // our CPU.
+            // FIXME: smarter spinlocking
+            yield_now();
+        }
+    }
+
+    /// End a read section.  Returns `true` if a write overlapped it, in
+    /// which case whatever was read must be discarded.
+    #[inline]
+    pub fn read_retry(&self, start: u64) -> bool {
+        hook::at(hook::Point::BeforeValidate);
+        // Pairs with the fence in `write_begin`: if any data read here came
+        // from a later write, the count has moved.  Only reads which are
+        // then thrown away depend on it.
+        fence(Ordering::Acquire);
+        self.version() != start
+    }
+
+    /// Start a write section.
+    ///
+    /// Writers must be excluded from each other by some other means, such
+    /// as a mutex that's held for the whole write section.
+    #[inline(always)]
+    pub fn write_begin(&self) {
+        self.increment();
+        // Keep the data writes from floating above the increment.
+        fence(Ordering::Release);
+        hook::at(hook::Point::WriteBegun);
+    }
+
+    /// End a write section.
+    #[inline(always)]
+    pub fn write_end(&self) {
+        self.increment();
+    }
+
+    // Writers are excluded from each other, so this needn't be an atomic
+    // read-modify-write.
+    #[inline(always)]
+    fn increment(&self) {
+        let v = self.seq.load(Ordering::SeqCst);
+        self.seq.store(v.wrapping_add(1), Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn protocol() {
+    let s = SeqCount::new();
+
+    let start = s.read_begin();
+    assert!(!s.read_retry(start));
+
+    s.write_begin();
+    assert_eq!(s.version() & 1, 1);
+    assert!(s.read_retry(start));
+    s.write_end();
+
+    assert!(s.read_retry(start));
+    assert_eq!(s.read_begin(), 2);
+}
@@ -0,0 +1,142 @@
+//! Seqlocks in memory shared between processes.
+//!
+//! Seqlocks are the classic way to publish telemetry through shared memory:
+//! the writer never waits for readers, and readers, which can't be trusted
+//! to release anything, never write.  Map a region in every process, then
+//! set it up in one of them with `from_mmap` and `attach` to it in the
+//! others.  The region holds a `SeqloqC`, whose writers exclude each other
+//! with a process-shared `SharedLock`.
+//!
+//! A process can die while writing, leaving the lock held and readers
+//! waiting.  Any other process can clear that up with `SeqloqC::recover`.
+//!
+//! `T` is copied between address spaces byte for byte, so it must not hold
+//! pointers or anything else which only means something in one process.
+
+use std::error::Error;
+use std::fmt;
+use std::mem::{align_of, size_of};
+use std::ptr;
+
+use crate::SeqloqC;
+
+/// Returned when a region can't hold a `SeqloqC<T>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BadRegion {
+    /// The region is smaller than `size_of::<SeqloqC<T>>()`.
+    TooSmall,
+    /// The region isn't aligned to `align_of::<SeqloqC<T>>()`.
+    Misaligned,
+}
+
+impl fmt::Display for BadRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            BadRegion::TooSmall => "region is too small for the seqloq",
+            BadRegion::Misaligned => "region is misaligned for the seqloq",
+        })
+    }
+}
+
+impl Error for BadRegion { }
+
+fn check<T>(region: *mut u8, len: usize) -> Result<*mut SeqloqC<T>, BadRegion> {
+    if len < size_of::<SeqloqC<T>>() {
+        return Err(BadRegion::TooSmall);
+    }
+    if !(region as usize).is_multiple_of(align_of::<SeqloqC<T>>()) {
+        return Err(BadRegion::Misaligned);
+    }
+    Ok(region as *mut SeqloqC<T>)
+}
+
+/// Set up a `SeqloqC` holding `t` at the start of `region`.
+///
+/// # Safety
+///
+/// `region` must point to `len` bytes of writable memory which stays
+/// mapped for `'a`, and which nothing else uses while this runs.
+/// Afterwards it must only be accessed as a `SeqloqC<T>`.
+pub unsafe fn from_mmap<'a, T>(region: *mut u8, len: usize, t: T)
+    -> Result<&'a SeqloqC<T>, BadRegion>
+    where T: Send + Copy,
+{
+    let p = check::<T>(region, len)?;
+    unsafe {
+        ptr::write(p, SeqloqC::new(t));
+        Ok(&*p)
+    }
+}
+
+/// Use a `SeqloqC` that `from_mmap`, maybe in another process, set up at
+/// the start of `region`.
+///
+/// # Safety
+///
+/// `region` must point to `len` bytes of writable memory which stays
+/// mapped for `'a`, and which holds a `SeqloqC<T>` set up by `from_mmap`
+/// (or by anything following the protocol in the `layout` module).
+pub unsafe fn attach<'a, T>(region: *mut u8, len: usize)
+    -> Result<&'a SeqloqC<T>, BadRegion>
+    where T: Send + Copy,
+{
+    let p = check::<T>(region, len)?;
+    unsafe { Ok(&*p) }
+}
+
+#[test]
+fn bad_region() {
+    let mut buf = [0u64; 8];
+    let p = buf.as_mut_ptr() as *mut u8;
+    unsafe {
+        assert_eq!(from_mmap(p, 8, 0u64).err(), Some(BadRegion::TooSmall));
+        assert_eq!(attach::<u64>(p.add(4), 60).err(), Some(BadRegion::Misaligned));
+        assert_eq!(from_mmap(p, 64, 7u64).unwrap().read(), 7);
+        assert_eq!(attach::<u64>(p, 64).unwrap().read(), 7);
+    }
+}
+
+/// Map the same memory twice, at different addresses, as two processes
+/// would.  The writers contend through the process-shared futex.
+#[cfg(target_os = "linux")]
+#[test]
+fn two_mappings() {
+    use std::thread;
+
+    const LEN: usize = 4096;
+    unsafe {
+        let fd = libc::memfd_create(c"seqloq-test".as_ptr(), 0);
+        assert!(fd >= 0);
+        assert_eq!(libc::ftruncate(fd, LEN as libc::off_t), 0);
+        let map = || {
+            let p = libc::mmap(ptr::null_mut(), LEN, libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED, fd, 0);
+            assert_ne!(p, libc::MAP_FAILED);
+            p as *mut u8
+        };
+        let (a, b) = (map(), map());
+        assert_ne!(a, b);
+
+        let x = from_mmap(a, LEN, [0u64; 4]).unwrap();
+        let y = attach::<[u64; 4]>(b, LEN).unwrap();
+        thread::scope(|s| {
+            for z in [x, y] {
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        z.update(|v| *v = [v[0] + 1; 4]);
+                    }
+                });
+            }
+            for _ in 0..1000 {
+                let v = y.read();
+                assert!(v.iter().all(|&e| e == v[0]));
+            }
+        });
+        assert_eq!(x.read(), [2000; 4]);
+        assert_eq!(y.version(), 4000);
+
+        libc::munmap(a as *mut _, LEN);
+        libc::munmap(b as *mut _, LEN);
+        libc::close(fd);
+    }
+}
@@ -0,0 +1,165 @@
+//! Arrays with a sequence counter per element, or per stripe of elements.
+//!
+//! A `Seqloq<[T; N]>` has one counter, so a write to any element makes
+//! every reader retry, and writers to different elements exclude each
+//! other.  `SeqloqSlice` gives each stripe its own counter and mutex.
+//! Element `i` belongs to stripe `i % stripes`, so neighbouring elements
+//! don't contend.
+//!
+//! Each element is read consistently, but there is no consistency across
+//! elements: a reader walking the slice may see one element before a write
+//! and the next after it.
+
+use std::cell::UnsafeCell;
+
+use crate::{SeqloqGuard, SeqloqStagedGuard, SeqCount, RawMutex, WordLock, Parts};
+
+struct Stripe<M> {
+    mutex: M,
+    seqnum: SeqCount,
+}
+
+/// A fixed-length array whose elements are seqlocked independently.
+pub struct SeqloqSlice<T, M = WordLock> {
+    stripes: Box<[Stripe<M>]>,
+    data: Box<[UnsafeCell<T>]>,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqSlice<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqSlice<T, M> { }
+
+impl<T> SeqloqSlice<T>
+    where T: Send + Copy,
+{
+    /// Protect `items`, with a counter for each element.
+    #[inline]
+    pub fn new(items: Vec<T>) -> SeqloqSlice<T> {
+        let stripes = items.len();
+        SeqloqSlice::with_stripes(items, stripes)
+    }
+}
+
+impl<T, M> SeqloqSlice<T, M>
+    where T: Send + Copy,
+          M: RawMutex + Default,
+{
+    /// Protect `items`, sharing `stripes` counters between them.
+    ///
+    /// Fewer stripes save memory, at the cost of more contention.
+    pub fn with_stripes(items: Vec<T>, stripes: usize) -> SeqloqSlice<T, M> {
+        let stripes = stripes.clamp(1, items.len().max(1));
+        SeqloqSlice {
+            stripes: (0..stripes).map(|_| Stripe {
+                mutex: M::default(),
+                seqnum: SeqCount::new(),
+            }).collect(),
+            data: items.into_iter().map(UnsafeCell::new).collect(),
+        }
+    }
+}
+
+impl<T, M> SeqloqSlice<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The number of sequence counters.
+    #[inline]
+    pub fn stripes(&self) -> usize {
+        self.stripes.len()
+    }
+
+    /// Panics if `i` is out of bounds.
+    #[inline(always)]
+    fn parts(&self, i: usize) -> Parts<'_, T, M> {
+        let stripe = &self.stripes[i % self.stripes.len()];
+        Parts {
+            mutex: &stripe.mutex,
+            notify: None,
+            seqnum: &stripe.seqnum,
+            data: self.data[i].get(),
+        }
+    }
+
+    /// Read element `i`.
+    #[inline]
+    pub fn read(&self, i: usize) -> T {
+        self.parts(i).read()
+    }
+
+    /// Read every element into `out`, one at a time.
+    pub fn read_into(&self, out: &mut Vec<T>) {
+        out.clear();
+        out.extend((0..self.len()).map(|i| self.read(i)));
+    }
+
+    /// As `Seqloq::peek`, for element `i`.
+    #[inline]
+    pub fn peek<F, R>(&self, i: usize, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.parts(i).peek_versioned(f).0
+    }
+
+    /// Replace element `i`.
+    #[inline]
+    pub fn write(&self, i: usize, t: T) {
+        self.parts(i).write(t)
+    }
+
+    /// As `Seqloq::update`, for element `i`.
+    #[inline]
+    pub fn update<F, R>(&self, i: usize, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        self.parts(i).update(f)
+    }
+
+    /// Lock the stripe holding element `i`, for access to that element.
+    #[inline]
+    pub fn lock(&self, i: usize) -> SeqloqGuard<'_, T, M> {
+        self.parts(i).lock()
+    }
+
+    /// As `lock`, staging writes in a private copy.
+    #[inline]
+    pub fn lock_staged(&self, i: usize) -> SeqloqStagedGuard<'_, T, M> {
+        self.parts(i).lock_staged()
+    }
+}
+
+#[test]
+fn slice() {
+    let x = SeqloqSlice::new(vec![0u64; 8]);
+    assert_eq!(x.stripes(), 8);
+    x.write(3, 5);
+    *x.lock(4) += 1;
+    assert_eq!(x.update(7, |v| { *v = 9; *v }), 9);
+
+    let mut out = Vec::new();
+    x.read_into(&mut out);
+    assert_eq!(out, [0, 0, 0, 5, 1, 0, 0, 9]);
+
+    let y: SeqloqSlice<u8> = SeqloqSlice::with_stripes(vec![1, 2, 3], 2);
+    assert_eq!(y.stripes(), 2);
+    y.write(2, 4);
+    assert_eq!(y.read(2), 4);
+}
+
+#[test]
+fn slice_concurrent_writers() {
+    let x = SeqloqSlice::new(vec![0u32; 2]);
+    let _g = x.lock(0);
+    // A different stripe isn't blocked by the guard.
+    x.write(1, 1);
+    assert_eq!(x.read(1), 1);
+}
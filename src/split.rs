@@ -0,0 +1,128 @@
+//! Single-writer seqlocks.
+//!
+//! When only one thread ever writes, the writer mutex is pure overhead.
+//! `Seqloq::split` hands out a unique `Writer`, which can write without
+//! locking because the type system guarantees there's no other writer, and
+//! a `Reader` which can be cloned for as many threads as needed.
+
+use std::sync::Arc;
+
+use crate::{Seqloq, RawMutex, store};
+
+/// The only handle that can write to a split `Seqloq`.
+pub struct Writer<T, M> {
+    inner: Arc<Seqloq<T, M>>,
+}
+
+/// A handle for reading a split `Seqloq`.
+pub struct Reader<T, M> {
+    inner: Arc<Seqloq<T, M>>,
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Split into a writer and a reader.  Writes through the `Writer` don't
+    /// touch the mutex at all.
+    pub fn split(self) -> (Writer<T, M>, Reader<T, M>) {
+        let inner = Arc::new(self);
+        (Writer { inner: inner.clone() }, Reader { inner })
+    }
+}
+
+impl<T, M> Writer<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// The current value.
+    ///
+    /// Nobody else can write, so this needs no retry loop.
+    #[inline]
+    pub fn get(&self) -> T {
+        unsafe { *self.inner.data.get() }
+    }
+
+    /// Replace the value.
+    #[inline]
+    pub fn write(&mut self, t: T) {
+        unsafe {
+            store(&self.inner.seqnum, self.inner.data.get(), t);
+        }
+        self.inner.notify.notify(&self.inner.seqnum);
+    }
+
+    /// Modify a copy of the value, then publish it.
+    #[inline]
+    pub fn update<F, R>(&mut self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        let mut t = self.get();
+        let res = f(&mut t);
+        self.write(t);
+        res
+    }
+
+    /// Another reader.
+    #[inline]
+    pub fn reader(&self) -> Reader<T, M> {
+        Reader { inner: self.inner.clone() }
+    }
+}
+
+impl<T, M> Reader<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.inner.read()
+    }
+
+    /// As `Seqloq::peek`.
+    #[inline]
+    pub fn peek<F, R>(&self, f: F) -> R
+        where F: FnMut(*const T) -> R,
+    {
+        self.inner.peek(f)
+    }
+
+    /// As `Seqloq::version`.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.inner.version()
+    }
+
+    /// As `Seqloq::wait_for_change`.
+    #[inline]
+    pub fn wait_for_change(&self, since: u64) -> (T, u64) {
+        self.inner.wait_for_change(since)
+    }
+
+    /// As `Seqloq::wait_until`.
+    #[inline]
+    pub fn wait_until<F>(&self, pred: F) -> T
+        where F: FnMut(&T) -> bool,
+    {
+        self.inner.wait_until(pred)
+    }
+}
+
+impl<T, M> Clone for Reader<T, M> {
+    #[inline]
+    fn clone(&self) -> Reader<T, M> {
+        Reader { inner: self.inner.clone() }
+    }
+}
+
+#[test]
+fn split() {
+    let (mut w, r) = Seqloq::new([1u8, 2]).split();
+    let r2 = r.clone();
+    w.write([3, 4]);
+    assert_eq!(w.update(|v| { v[0] = 5; v[1] }), 4);
+    assert_eq!(r.read(), [5, 4]);
+    assert_eq!(r2.version(), 4);
+    assert_eq!(w.reader().read(), w.get());
+}
@@ -0,0 +1,159 @@
+//! Write timestamps.
+//!
+//! Wrap the writer mutex in `Timestamped` to find out how stale the data
+//! is, without putting a timestamp in the data itself:
+//!
+//! ```
+//! use seqloq::{Seqloq, Timestamped, WordLock};
+//!
+//! let temp = Seqloq::with_mutex(20.5f32, Timestamped::new(WordLock::new()));
+//! temp.write(21.0);
+//! let (t, age) = temp.read_age();
+//! assert_eq!(t, 21.0);
+//! # let _ = age;
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Seqloq, RawMutex};
+
+/// A writer mutex which records when a writer last published through it.
+///
+/// The time is taken just after the write section ends, so an age can be
+/// overstated by as much as it takes to release the mutex.  Releases which
+/// wrote nothing, like a guard that was only read through or a staged
+/// write that was aborted, don't count.
+pub struct Timestamped<M> {
+    inner: M,
+    epoch: Instant,
+    /// Nanoseconds from `epoch` to the last publish.
+    last: AtomicU64,
+}
+
+impl<M: RawMutex> Timestamped<M> {
+    /// Until the first write, the time of creation counts as the last write.
+    #[inline]
+    pub fn new(inner: M) -> Timestamped<M> {
+        Timestamped {
+            inner,
+            epoch: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// When a writer last published through the mutex.
+    #[inline]
+    pub fn last_publish(&self) -> Instant {
+        self.epoch + Duration::from_nanos(self.last.load(Ordering::Acquire))
+    }
+}
+
+impl<M: RawMutex + Default> Default for Timestamped<M> {
+    fn default() -> Timestamped<M> {
+        Timestamped::new(M::default())
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Timestamped<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock()
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        self.last.store(now, Ordering::Release);
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<crate::SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+impl<T, M: RawMutex> Seqloq<T, Timestamped<M>> {
+    /// When the data was last written.
+    #[inline]
+    pub fn last_write_time(&self) -> Instant {
+        self.mutex.last_publish()
+    }
+}
+
+impl<T: Send + Copy, M: RawMutex> Seqloq<T, Timestamped<M>> {
+    /// Read the data, along with how long ago it was written.
+    pub fn read_age(&self) -> (T, Duration) {
+        loop {
+            let (t, version) = self.read_versioned();
+            let written = self.last_write_time();
+            // Otherwise the time could belong to a newer write than the
+            // data.
+            if self.version() == version {
+                return (t, written.elapsed());
+            }
+        }
+    }
+}
+
+#[test]
+fn timestamped() {
+    let x = Seqloq::with_mutex(0u32, Timestamped::new(crate::WordLock::new()));
+    let created = x.last_write_time();
+    std::thread::sleep(Duration::from_millis(2));
+    assert!(x.read_age().1 >= Duration::from_millis(2));
+
+    x.write(1);
+    assert!(x.last_write_time() >= created + Duration::from_millis(2));
+    let (t, age) = x.read_age();
+    assert_eq!(t, 1);
+    assert!(age < Duration::from_secs(1));
+}
+
+#[test]
+fn clean_release() {
+    let x = Seqloq::with_mutex(0u32, Timestamped::new(crate::WordLock::new()));
+    x.write(1);
+    let written = x.last_write_time();
+    std::thread::sleep(Duration::from_millis(2));
+
+    assert_eq!(*x.lock(), 1);
+    x.lock_staged().abort();
+    drop(x.try_lock());
+    assert_eq!(x.last_write_time(), written);
+    assert!(x.read_age().1 >= Duration::from_millis(2));
+
+    *x.lock() = 2;
+    assert!(x.last_write_time() >= written + Duration::from_millis(2));
+}
@@ -0,0 +1,244 @@
+//! Opt-in statistics.
+//!
+//! Wrap the writer mutex in `Stats` to count what readers go through, and
+//! how long writers wait for and hold the lock:
+//!
+//! ```
+//! use seqloq::{Seqloq, Stats, WordLock};
+//!
+//! let x = Seqloq::with_mutex(0u32, Stats::new(WordLock::new()));
+//! x.read();
+//! assert_eq!(x.stats().reads, 1);
+//! ```
+//!
+//! Counting reads means writing to shared memory on every read, which a
+//! plain `Seqloq` never does.  The counters are spread over several cache
+//! lines to soften the blow, but expect some slowdown.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Seqloq, RawMutex};
+
+/// Per-thread counters are spread over this many stripes.
+const STRIPES: usize = 16;
+
+#[derive(Default)]
+#[repr(align(128))]
+struct Stripe {
+    reads: AtomicU64,
+    retries: AtomicU64,
+    disturbed: AtomicU64,
+}
+
+/// A writer mutex which also keeps statistics.
+pub struct Stats<M> {
+    inner: M,
+    stripes: [Stripe; STRIPES],
+    epoch: Instant,
+    writes: AtomicU64,
+    /// Nanoseconds since `epoch`, plus one, at which the current holder
+    /// took the lock; zero when unlocked.
+    held_since: AtomicU64,
+    max_hold: AtomicU64,
+    total_hold: AtomicU64,
+    total_wait: AtomicU64,
+}
+
+/// A snapshot of the statistics kept by `Stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SeqloqStats {
+    /// Reads completed.
+    pub reads: u64,
+    /// Read attempts thrown away because a write overlapped them.
+    pub retries: u64,
+    /// Reads that needed at least one retry.
+    pub disturbed_reads: u64,
+    /// Times the writer mutex was taken.
+    pub writes: u64,
+    /// Total time spent waiting for the writer mutex.
+    pub write_wait: Duration,
+    /// Total time the writer mutex was held.
+    pub write_hold: Duration,
+    /// The longest single hold of the writer mutex.
+    pub max_write_hold: Duration,
+    /// How long the current holder, if any, has had the writer mutex.
+    pub current_write_hold: Option<Duration>,
+}
+
+impl<M: RawMutex> Stats<M> {
+    #[inline]
+    pub fn new(inner: M) -> Stats<M> {
+        Stats {
+            inner,
+            stripes: Default::default(),
+            epoch: Instant::now(),
+            writes: AtomicU64::new(0),
+            held_since: AtomicU64::new(0),
+            max_hold: AtomicU64::new(0),
+            total_hold: AtomicU64::new(0),
+            total_wait: AtomicU64::new(0),
+        }
+    }
+
+    /// The statistics so far.
+    pub fn snapshot(&self) -> SeqloqStats {
+        let mut s = SeqloqStats::default();
+        for st in &self.stripes {
+            s.reads += st.reads.load(Ordering::Relaxed);
+            s.retries += st.retries.load(Ordering::Relaxed);
+            s.disturbed_reads += st.disturbed.load(Ordering::Relaxed);
+        }
+        let nanos = |a: &AtomicU64| Duration::from_nanos(a.load(Ordering::Relaxed));
+        s.writes = self.writes.load(Ordering::Relaxed);
+        s.write_wait = nanos(&self.total_wait);
+        s.write_hold = nanos(&self.total_hold);
+        s.max_write_hold = nanos(&self.max_hold);
+        s.current_write_hold = match self.held_since.load(Ordering::Relaxed) {
+            0 => None,
+            t => Some(Duration::from_nanos(self.now().saturating_sub(t - 1))),
+        };
+        s
+    }
+
+    #[inline]
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+
+    /// Note that the lock was just taken.
+    #[inline]
+    fn acquired(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.held_since.store(self.now() + 1, Ordering::Relaxed);
+    }
+
+    /// This thread's stripe.
+    #[inline]
+    fn stripe(&self) -> &Stripe {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static INDEX: Cell<usize> = const { Cell::new(usize::MAX) };
+        }
+        let i = INDEX.with(|i| {
+            if i.get() == usize::MAX {
+                i.set(NEXT.fetch_add(1, Ordering::Relaxed) % STRIPES);
+            }
+            i.get()
+        });
+        &self.stripes[i]
+    }
+}
+
+impl<M: RawMutex + Default> Default for Stats<M> {
+    fn default() -> Stats<M> {
+        Stats::new(M::default())
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Stats<M> {
+    #[inline]
+    fn lock(&self) {
+        let start = self.now();
+        self.inner.lock();
+        self.total_wait.fetch_add(self.now() - start, Ordering::Relaxed);
+        self.acquired();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        let locked = self.inner.try_lock();
+        if locked {
+            self.acquired();
+        }
+        locked
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        let since = self.held_since.swap(0, Ordering::Relaxed);
+        let held = self.now().saturating_sub(since - 1);
+        self.total_hold.fetch_add(held, Ordering::Relaxed);
+        self.max_hold.fetch_max(held, Ordering::Relaxed);
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        let st = self.stripe();
+        st.reads.fetch_add(1, Ordering::Relaxed);
+        if retries > 0 {
+            st.retries.fetch_add(retries as u64, Ordering::Relaxed);
+            st.disturbed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<SeqloqStats> {
+        Some(self.snapshot())
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        self.inner.name()
+    }
+}
+
+impl<T, M: RawMutex> Seqloq<T, Stats<M>> {
+    /// Statistics on this `Seqloq`'s readers.
+    #[inline]
+    pub fn stats(&self) -> SeqloqStats {
+        self.mutex.snapshot()
+    }
+}
+
+#[test]
+fn stats() {
+    let x = Seqloq::with_mutex([0u32; 4], Stats::new(crate::WordLock::new()));
+    x.read();
+    x.peek(|_| ());
+    let mut first = true;
+    x.peek(|_| {
+        if first {
+            first = false;
+            // Force a retry.
+            x.write([1; 4]);
+        }
+    });
+    let s = x.stats();
+    assert_eq!((s.reads, s.retries, s.disturbed_reads), (3, 1, 1));
+}
+
+#[test]
+fn write_stats() {
+    let x = Seqloq::with_mutex(0u32, Stats::new(crate::WordLock::new()));
+    x.write(1);
+    {
+        let mut g = x.lock();
+        *g = 2;
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(x.stats().current_write_hold.unwrap() >= Duration::from_millis(2));
+    }
+    let s = x.stats();
+    assert_eq!(s.writes, 2);
+    assert_eq!(s.current_write_hold, None);
+    assert!(s.max_write_hold >= Duration::from_millis(2));
+    assert!(s.write_hold >= s.max_write_hold);
+}
@@ -0,0 +1,71 @@
+//! Channels of version numbers.
+//!
+//! A `Subscription` receives the new version number whenever a writer
+//! releases the lock.  Worker threads can block on it, or select over it
+//! alongside other channels, without touching the data until they need it.
+
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver};
+
+use crate::{Seqloq, notify};
+use crate::notify::Notify;
+
+/// Receives the version of each write to a `Seqloq`.
+///
+/// Dereferences to a `std::sync::mpsc::Receiver`.  Dropping it
+/// unsubscribes.
+pub struct Subscription<'a> {
+    notify: &'a Notify,
+    id: u64,
+    rx: Receiver<u64>,
+}
+
+impl<T, M> Seqloq<T, M> {
+    /// Subscribe to writes.
+    ///
+    /// One message is sent each time a writer releases the lock, carrying
+    /// the version at that point.  Several `publish` calls under one guard
+    /// produce a single message.
+    pub fn subscribe(&self) -> Subscription<'_> {
+        let (tx, rx) = mpsc::channel();
+        let id = notify::new_id();
+        self.notify.subscribe(id, tx);
+        Subscription {
+            notify: &self.notify,
+            id,
+            rx,
+        }
+    }
+}
+
+impl Deref for Subscription<'_> {
+    type Target = Receiver<u64>;
+
+    #[inline]
+    fn deref(&self) -> &Receiver<u64> {
+        &self.rx
+    }
+}
+
+impl Drop for Subscription<'_> {
+    fn drop(&mut self) {
+        self.notify.unsubscribe(self.id);
+    }
+}
+
+#[test]
+fn subscribe() {
+    let x = Seqloq::new(0u32);
+    let a = x.subscribe();
+    x.write(1);
+    {
+        let b = x.subscribe();
+        *x.lock() = 2;
+        assert_eq!(b.try_iter().collect::<Vec<_>>(), [4]);
+    }
+    x.write(3);
+    assert_eq!(a.try_iter().collect::<Vec<_>>(), [2, 4, 6]);
+    drop(a);
+    x.write(4);
+    assert_eq!(x.version(), 8);
+}
@@ -0,0 +1,142 @@
+//! Synchronization primitives used by the protocol.
+//!
+//! When building with `--cfg loom` or `--cfg shuttle` these come from the
+//! corresponding testing crate instead, so it can take control of thread
+//! scheduling.  See `tests/loom.rs` and `tests/shuttle.rs`.
+//!
+//! Without `std` there are only the atomics, and nothing to yield to or
+//! sleep on, so waiting means spinning.
+
+#[cfg(all(feature = "std", not(any(loom, shuttle))))]
+use std::sync as imp;
+#[cfg(all(not(feature = "std"), not(any(loom, shuttle))))]
+use core::sync as imp;
+#[cfg(all(feature = "std",
+    not(any(loom, shuttle, all(target_family = "wasm", not(target_feature = "atomics"))))))]
+use std::thread;
+
+#[cfg(loom)]
+use loom::{sync as imp, thread};
+
+#[cfg(all(shuttle, not(loom)))]
+use shuttle::{sync as imp, thread};
+
+#[cfg(feature = "std")]
+pub(crate) use self::imp::{Mutex, Condvar};
+#[cfg(feature = "std")]
+pub(crate) use self::imp::atomic::{AtomicUsize, AtomicPtr};
+pub(crate) use self::imp::atomic::{AtomicBool, AtomicU32, Ordering, fence};
+#[cfg(all(feature = "std", not(all(target_family = "wasm", not(target_feature = "atomics")))))]
+pub(crate) use self::thread::yield_now;
+
+#[cfg(all(not(feature = "std"), not(any(loom, shuttle))))]
+#[inline]
+pub(crate) fn yield_now() {
+    spin_loop();
+}
+
+/// Busy-wait hint.  The simulated schedulers need a real yield, or they'll
+/// never run the thread we're waiting for.
+#[cfg(not(any(loom, shuttle,
+    all(feature = "std", target_family = "wasm", not(target_feature = "atomics")))))]
+pub(crate) use core::hint::spin_loop;
+
+// On WebAssembly without the atomics proposal there is only ever one thread,
+// so waiting for another thread would mean waiting for ever.  Everything
+// that waits comes through here, and panics instead.
+#[cfg(all(feature = "std", target_family = "wasm", not(target_feature = "atomics")))]
+#[cold]
+#[track_caller]
+pub(crate) fn yield_now() {
+    panic!("seqloq: waiting for another thread, on a single-threaded target");
+}
+
+#[cfg(all(feature = "std", target_family = "wasm", not(target_feature = "atomics")))]
+pub(crate) use self::yield_now as spin_loop;
+
+#[cfg(any(loom, shuttle))]
+#[inline]
+pub(crate) fn spin_loop() {
+    yield_now();
+}
+
+// Sleeping on an atomic word, for contended writers.  Where there's no
+// futex-like primitive, or the scheduler is simulated, just yield.
+#[cfg(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android",
+    target_os = "freebsd", target_os = "macos", target_os = "windows")))]
+pub(crate) use atomic_wait::{wait, wake_one};
+
+// Waking every waiter is only needed by `Notify`, which uses
+// `event-listener` instead when that feature is on.
+#[cfg(all(feature = "std", not(any(loom, shuttle)), not(feature = "event-listener"),
+    any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos",
+    target_os = "windows")))]
+pub(crate) use atomic_wait::wake_all;
+
+#[cfg(not(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android",
+    target_os = "freebsd", target_os = "macos", target_os = "windows"))))]
+#[inline]
+pub(crate) fn wait(_: &AtomicU32, _: u32) {
+    yield_now();
+}
+
+#[cfg(not(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android",
+    target_os = "freebsd", target_os = "macos", target_os = "windows"))))]
+#[inline]
+pub(crate) fn wake_one(_: &AtomicU32) { }
+
+#[cfg(all(not(feature = "event-listener"),
+    not(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android",
+    target_os = "freebsd", target_os = "macos", target_os = "windows")))))]
+#[inline]
+pub(crate) fn wake_all(_: &AtomicU32) { }
+
+// Sleeping on a word that other processes may also be using.  Only Linux
+// futexes are used that way; elsewhere, yield.
+#[cfg(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))]
+#[inline]
+pub(crate) fn wait_shared(a: &AtomicU32, expected: u32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, a, libc::FUTEX_WAIT, expected,
+            std::ptr::null::<libc::timespec>());
+    }
+}
+
+#[cfg(all(feature = "std", not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))]
+#[inline]
+pub(crate) fn wake_one_shared(a: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, a, libc::FUTEX_WAKE, 1i32);
+    }
+}
+
+#[cfg(all(feature = "std",
+    not(all(not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))))]
+#[inline]
+pub(crate) fn wait_shared(_: &AtomicU32, _: u32) {
+    yield_now();
+}
+
+#[cfg(all(feature = "std",
+    not(all(not(any(loom, shuttle)), any(target_os = "linux", target_os = "android")))))]
+#[inline]
+pub(crate) fn wake_one_shared(_: &AtomicU32) { }
+
+// Sequence numbers are 64 bits wherever the target has 64-bit atomics, so
+// they never wrap in practice.  Elsewhere they're pointer-sized.
+#[cfg(target_has_atomic = "64")]
+pub(crate) use self::imp::atomic::AtomicU64 as AtomicSeq;
+#[cfg(target_has_atomic = "64")]
+pub(crate) type Seq = u64;
+
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) use self::imp::atomic::AtomicUsize as AtomicSeq;
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) type Seq = usize;
+
+/// Sequence numbers are always `u64` in the public API.
+#[allow(clippy::unnecessary_cast)]
+#[inline(always)]
+pub(crate) fn widen(s: Seq) -> u64 {
+    s as u64
+}
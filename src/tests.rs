@@ -1,42 +1,108 @@
 //! Tests and test infrastructure.
 //!
-//! Unless you're writing custom benchmarks, you don't need this.
+//! Unless you're writing custom benchmarks, or a synchronization wrapper
+//! of your own, you don't need this.  For a wrapper, implement
+//! `TestableMutex` and run `reader_writer_test` against it, with either
+//! payload the harness provides:
+//!
+//! ```
+//! use std::sync::Mutex;
+//! use std::time::Duration;
+//! use seqloq::tests::{Checksummed, Payload, TestableMutex, ThreadSpec, reader_writer_test};
+//!
+//! struct MyLock<P>(Mutex<P>);
+//!
+//! impl<P: Payload> TestableMutex for MyLock<P> {
+//!     type Payload = P;
+//!
+//!     fn create() -> MyLock<P> {
+//!         MyLock(Mutex::new(P::new()))
+//!     }
+//!
+//!     fn check(&self, delay: Duration) -> usize {
+//!         self.0.lock().unwrap().check(delay)
+//!     }
+//!
+//!     fn frob(&self, delay: Duration) {
+//!         self.0.lock().unwrap().frob(delay);
+//!     }
+//! }
+//!
+//! let spec = ThreadSpec { qty: 4, steps: 10, ..Default::default() };
+//! reader_writer_test::<MyLock<Checksummed>>(spec, spec, None).assert_ok();
+//! ```
 
-use std::old_io::timer;
-use std::time::Duration;
-use std::sync::{Arc, Mutex, RwLock};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{Barrier, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::cell::UnsafeCell;
-use std::thread::Thread;
-use std::default::Default;
-use std::any::Any;
-use time::precise_time_ns;
-use test::black_box;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::hint::{self, black_box};
+
+use crate::{Seqloq, copy};
+
+/// Data for the tests to protect, which readers can check for tearing.
+///
+/// `delay` slows both operations down, element by element, to widen the
+/// window in which a broken lock lets a reader see a write half done.
+pub trait Payload: Copy + Send + Sync + 'static {
+    fn new() -> Self;
 
-use Seqloq;
+    /// How many inconsistencies this snapshot has; zero unless it's torn.
+    fn check(&self, delay: Duration) -> usize;
+
+    /// Change the data to a new consistent value, one element at a time.
+    fn frob(&mut self, delay: Duration);
+}
 
-#[doc(hidden)]
+/// Something like a lock, which the tests and benchmarks here can run
+/// against.  `check` reads the payload under whatever protection the lock
+/// gives readers, and `frob` changes it with writers excluded.
 pub trait TestableMutex: Send + Sync {
+    type Payload: Payload;
+
     fn create() -> Self;
     fn check(&self, delay: Duration) -> usize;
     fn frob(&self, delay: Duration);
+
+    /// Like `check`, but also return how many times the read was retried,
+    /// if this is a kind of lock whose reads retry.
+    fn check_counted(&self, delay: Duration) -> (usize, Option<u32>) {
+        (self.check(delay), None)
+    }
 }
 
 const ARRAY_LEN: usize = 4;
 
-#[derive(Copy)]
-pub struct TestArray(pub [u64; ARRAY_LEN]);
+/// The data the tests protect: `N` words which are always equal, unless a
+/// read was torn.  Bigger payloads make reads and writes take longer, and
+/// so make a seqlock's readers retry more.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TestArray<const N: usize = ARRAY_LEN>(pub [u64; N]);
+
+impl<const N: usize> Default for TestArray<N> {
+    fn default() -> TestArray<N> {
+        TestArray::new()
+    }
+}
+
+impl<const N: usize> TestArray<N> {
+    pub fn new() -> TestArray<N> {
+        TestArray([0; N])
+    }
+}
 
-impl TestArray {
-    pub fn new() -> TestArray {
-        TestArray([0; ARRAY_LEN])
+impl<const N: usize> Payload for TestArray<N> {
+    fn new() -> TestArray<N> {
+        TestArray::new()
     }
 
-    pub fn check(&self, delay: Duration) -> usize {
+    fn check(&self, delay: Duration) -> usize {
         let v = self.0[0];
         let n = self.0.iter().skip(1)
             .filter(|e| {
-                timer::sleep(delay);
+                self::delay(delay);
                 **e != v
             }).count();
 
@@ -45,17 +111,19 @@ impl TestArray {
         n
     }
 
-    pub fn frob(&mut self, delay: Duration) {
+    fn frob(&mut self, delay: Duration) {
         for e in self.0.iter_mut() {
             *e += 1;
-            timer::sleep(delay);
+            self::delay(delay);
         }
     }
 }
 
-impl TestableMutex for Mutex<TestArray> {
-    fn create() -> Mutex<TestArray> {
-        Mutex::new(TestArray::new())
+impl<P: Payload> TestableMutex for Mutex<P> {
+    type Payload = P;
+
+    fn create() -> Mutex<P> {
+        Mutex::new(P::new())
     }
 
     fn check(&self, delay: Duration) -> usize {
@@ -67,9 +135,11 @@ impl TestableMutex for Mutex<TestArray> {
     }
 }
 
-impl TestableMutex for RwLock<TestArray> {
-    fn create() -> RwLock<TestArray> {
-        RwLock::new(TestArray::new())
+impl<P: Payload> TestableMutex for RwLock<P> {
+    type Payload = P;
+
+    fn create() -> RwLock<P> {
+        RwLock::new(P::new())
     }
 
     fn check(&self, delay: Duration) -> usize {
@@ -81,9 +151,11 @@ impl TestableMutex for RwLock<TestArray> {
     }
 }
 
-impl TestableMutex for Seqloq<TestArray> {
-    fn create() -> Seqloq<TestArray> {
-        Seqloq::new(TestArray::new())
+impl<P: Payload> TestableMutex for Seqloq<P> {
+    type Payload = P;
+
+    fn create() -> Seqloq<P> {
+        Seqloq::new(P::new())
     }
 
     fn check(&self, delay: Duration) -> usize {
@@ -94,13 +166,20 @@ impl TestableMutex for Seqloq<TestArray> {
     fn frob(&self, delay: Duration) {
         self.lock().frob(delay);
     }
+
+    fn check_counted(&self, delay: Duration) -> (usize, Option<u32>) {
+        let (x, _, retries) = self.parts().peek_counted(|x| unsafe { copy::load(x) });
+        (x.check(delay), Some(retries))
+    }
 }
 
 pub struct SeqloqPeek<T>(Seqloq<T>);
 
-impl TestableMutex for SeqloqPeek<TestArray> {
-    fn create() -> SeqloqPeek<TestArray> {
-        SeqloqPeek(Seqloq::new(TestArray::new()))
+impl<P: Payload> TestableMutex for SeqloqPeek<P> {
+    type Payload = P;
+
+    fn create() -> SeqloqPeek<P> {
+        SeqloqPeek(Seqloq::new(P::new()))
     }
 
     fn check(&self, delay: Duration) -> usize {
@@ -112,16 +191,167 @@ impl TestableMutex for SeqloqPeek<TestArray> {
     fn frob(&self, delay: Duration) {
         self.0.frob(delay);
     }
+
+    fn check_counted(&self, delay: Duration) -> (usize, Option<u32>) {
+        let (res, _, retries) = self.0.parts().peek_counted(|x| unsafe {
+            (*x).check(delay)
+        });
+        (res, Some(retries))
+    }
 }
 
+#[cfg(feature = "compare")]
+impl<P: Payload> TestableMutex for parking_lot::RwLock<P> {
+    type Payload = P;
+
+    fn create() -> parking_lot::RwLock<P> {
+        parking_lot::RwLock::new(P::new())
+    }
+
+    fn check(&self, delay: Duration) -> usize {
+        self.read().check(delay)
+    }
+
+    fn frob(&self, delay: Duration) {
+        self.write().frob(delay);
+    }
+}
+
+#[cfg(feature = "compare")]
+impl<P: Payload> TestableMutex for seqlock::SeqLock<P> {
+    type Payload = P;
+
+    fn create() -> seqlock::SeqLock<P> {
+        seqlock::SeqLock::new(P::new())
+    }
+
+    fn check(&self, delay: Duration) -> usize {
+        let x = self.read();
+        x.check(delay)
+    }
+
+    fn frob(&self, delay: Duration) {
+        self.lock_write().frob(delay);
+    }
+}
+
+/// `AtomicCell` has no write lock, so writers copy, frob and
+/// compare-and-swap, starting over if another writer got in first.
+#[cfg(feature = "compare")]
+impl<P: Payload + Eq> TestableMutex for crossbeam_utils::atomic::AtomicCell<P> {
+    type Payload = P;
+
+    fn create() -> crossbeam_utils::atomic::AtomicCell<P> {
+        crossbeam_utils::atomic::AtomicCell::new(P::new())
+    }
+
+    fn check(&self, delay: Duration) -> usize {
+        self.load().check(delay)
+    }
+
+    fn frob(&self, delay: Duration) {
+        let mut current = self.load();
+        loop {
+            let mut new = current;
+            new.frob(delay);
+            match self.compare_exchange(current, new) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// Writers build a new `Arc` and swap it in, retrying like `AtomicCell`.
+#[cfg(feature = "compare")]
+impl<P: Payload> TestableMutex for arc_swap::ArcSwap<P> {
+    type Payload = P;
+
+    fn create() -> arc_swap::ArcSwap<P> {
+        arc_swap::ArcSwap::from_pointee(P::new())
+    }
+
+    fn check(&self, delay: Duration) -> usize {
+        self.load().check(delay)
+    }
+
+    fn frob(&self, delay: Duration) {
+        self.rcu(|x| {
+            let mut x = **x;
+            x.frob(delay);
+            x
+        });
+    }
+}
+
+/// A payload which carries a checksum of its own contents, so that a torn
+/// read shows in the snapshot itself, without having to catch a writer in
+/// the act: words from two different writes almost never match either
+/// write's checksum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checksummed<const N: usize = ARRAY_LEN> {
+    pub words: [u64; N],
+    pub sum: u64,
+}
+
+impl<const N: usize> Checksummed<N> {
+    /// A payload of pseudo-random words, generated from `seed`.
+    pub fn new(seed: u64) -> Checksummed<N> {
+        let mut rng = Rng::new(seed);
+        let words = std::array::from_fn(|_| rng.next_u64());
+        Checksummed { words, sum: checksum(&words) }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.sum == checksum(&self.words)
+    }
+}
+
+impl<const N: usize> Payload for Checksummed<N> {
+    fn new() -> Checksummed<N> {
+        Checksummed::new(0)
+    }
+
+    fn check(&self, delay: Duration) -> usize {
+        let words: [u64; N] = std::array::from_fn(|i| {
+            self::delay(delay);
+            self.words[i]
+        });
+        black_box((checksum(&words) != self.sum) as usize)
+    }
+
+    /// The next value is generated from the old checksum.
+    fn frob(&mut self, delay: Duration) {
+        let next = Checksummed::<N>::new(self.sum);
+        for (w, &n) in self.words.iter_mut().zip(&next.words) {
+            *w = n;
+            self::delay(delay);
+        }
+        self.sum = next.sum;
+    }
+}
+
+/// FNV-1a over whole words, then a final mix so that every bit of the
+/// input affects every bit of the sum.
+fn checksum(words: &[u64]) -> u64 {
+    let mut h = 0xcbf2_9ce4_8422_2325_u64;
+    for &w in words {
+        h = (h ^ w).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    Rng::new(h).next_u64()
+}
+
+#[allow(dead_code)]
 struct BogusMutex<T>(UnsafeCell<T>);
 
 unsafe impl<T: 'static> Send for BogusMutex<T> { }
 unsafe impl<T: 'static> Sync for BogusMutex<T> { }
 
-impl TestableMutex for BogusMutex<TestArray> {
-    fn create() -> BogusMutex<TestArray> {
-        BogusMutex(UnsafeCell::new(TestArray::new()))
+impl<P: Payload> TestableMutex for BogusMutex<P> {
+    type Payload = P;
+
+    fn create() -> BogusMutex<P> {
+        BogusMutex(UnsafeCell::new(P::new()))
     }
 
     fn check(&self, delay: Duration) -> usize {
@@ -137,7 +367,179 @@ impl TestableMutex for BogusMutex<TestArray> {
     }
 }
 
-#[derive(Copy)]
+/// A ratio of reads to writes, for threads which do both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mix {
+    pub reads: u32,
+    pub writes: u32,
+}
+
+impl Mix {
+    /// Whether operation number `step` should be a write.  Writes are
+    /// spread evenly: 90:10 writes on every tenth step, not ten in a row.
+    pub fn is_write(&self, step: u64) -> bool {
+        let (w, total) = (self.writes as u64, (self.reads + self.writes) as u64);
+        total > 0 && (step + 1) * w / total > step * w / total
+    }
+}
+
+/// A range of CPUs, `first..first + count`, for pinning threads to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cpus {
+    pub first: usize,
+    pub count: usize,
+}
+
+impl Cpus {
+    /// The CPU for thread number `i`, round-robin.
+    pub fn nth(&self, i: usize) -> usize {
+        self.first + i % self.count.max(1)
+    }
+}
+
+/// Pin the calling thread to `cpu`.  Returns `false` if that's not
+/// possible, including on platforms other than Linux and Android.
+pub fn pin_to_cpu(cpu: usize) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return false;
+        }
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = cpu;
+        false
+    }
+}
+
+fn pin(cpu: usize) {
+    assert!(pin_to_cpu(cpu), "failed to pin a thread to CPU {}", cpu);
+}
+
+/// The CPUs on each online NUMA node, by node number.  Empty if the
+/// topology is unknown, as it is on platforms other than Linux and Android.
+pub fn numa_nodes() -> Vec<Vec<usize>> {
+    let mut nodes = vec![];
+    for node in 0.. {
+        if cfg!(not(any(target_os = "linux", target_os = "android"))) {
+            break;
+        }
+        let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+        match std::fs::read_to_string(path) {
+            Ok(list) => nodes.push(cpu_list(list.trim())),
+            // Node numbers can have gaps, but rarely before the last
+            // node, so stopping at the first missing one is good enough.
+            Err(_) => break,
+        }
+    }
+    nodes
+}
+
+/// Parse a kernel CPU list, like `0-3,8-11`.
+fn cpu_list(s: &str) -> Vec<usize> {
+    s.split(',').filter(|r| !r.is_empty()).flat_map(|r| {
+        let (a, b) = r.split_once('-').unwrap_or((r, r));
+        a.parse().unwrap_or(0)..=b.parse().unwrap_or(0)
+    }).collect()
+}
+
+/// The CPU for thread number `i`, spread round-robin across NUMA nodes:
+/// thread 0 on the first CPU of node 0, thread 1 on the first CPU of node 1,
+/// and so on, before moving on to each node's second CPU.
+fn spread(nodes: &[Vec<usize>], i: usize) -> Option<usize> {
+    let nonempty: Vec<&Vec<usize>> = nodes.iter().filter(|n| !n.is_empty()).collect();
+    let node = nonempty.get(i % nonempty.len().max(1))?;
+    Some(node[(i / nonempty.len()) % node.len()])
+}
+
+/// Delays at least this long are slept through; shorter ones spin.
+const SPIN_LIMIT: Duration = Duration::from_millis(1);
+
+static SPIN: AtomicBool = AtomicBool::new(true);
+
+/// Whether `delay` spins for short delays, which it does unless this is
+/// turned off.  Applies to the whole process.
+pub fn set_spin_delays(spin: bool) {
+    SPIN.store(spin, Ordering::Relaxed);
+}
+
+/// Wait for `d`.  `thread::sleep` rounds short sleeps up to a timer tick or
+/// a scheduler quantum, often tens of microseconds, so delays under a
+/// millisecond busy-wait on the clock instead, finishing early by the cost
+/// of reading it.
+pub fn delay(d: Duration) {
+    if d.is_zero() {
+        return;
+    }
+    if d >= SPIN_LIMIT || !SPIN.load(Ordering::Relaxed) {
+        thread::sleep(d);
+        return;
+    }
+    let start = Instant::now();
+    let d = d.saturating_sub(clock_cost());
+    while start.elapsed() < d {
+        hint::spin_loop();
+    }
+}
+
+/// How long `Instant::now` takes, measured once.
+fn clock_cost() -> Duration {
+    static COST: OnceLock<Duration> = OnceLock::new();
+    *COST.get_or_init(|| {
+        const CALLS: u32 = 1000;
+        let start = Instant::now();
+        for _ in 0..CALLS {
+            black_box(Instant::now());
+        }
+        start.elapsed() / CALLS
+    })
+}
+
+/// The seed for a `reader_writer_test`: `SEQLOQ_SEED` if that's set, and
+/// otherwise a new random one each time.
+pub fn seed() -> u64 {
+    match std::env::var("SEQLOQ_SEED") {
+        Ok(s) => s.trim().parse()
+            .unwrap_or_else(|_| panic!("SEQLOQ_SEED should be a number, not {:?}", s)),
+        Err(_) => RandomState::new().build_hasher().finish(),
+    }
+}
+
+/// SplitMix64, which is plenty for picking pauses, and makes the same
+/// choices everywhere for a given seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, near enough for small `n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+
+    fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            v.swap(i, self.below(i as u64 + 1) as usize);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct ThreadSpec {
     /// Number of threads to spawn.
     pub qty: u64,
@@ -147,6 +549,18 @@ pub struct ThreadSpec {
     pub delay: u64,
     /// Pause between operations, with the mutex unlocked, in microseconds
     pub pause: u64,
+    /// If set, each thread both reads and writes, in this ratio, whether it
+    /// was spawned as a reader or a writer.
+    pub mix: Option<Mix>,
+    /// If set, pin the threads to these CPUs.
+    pub cpus: Option<Cpus>,
+    /// Pin the threads across NUMA nodes, alternating between them,
+    /// instead of to `cpus`.  Does nothing if the topology is unknown.
+    pub spread: bool,
+    /// Randomize a `reader_writer_test`: spawn threads in a shuffled
+    /// order, and make each pause a random length from zero to twice
+    /// `pause`.  The choices come from the test's seed.
+    pub jitter: bool,
 }
 
 impl Default for ThreadSpec {
@@ -156,13 +570,43 @@ impl Default for ThreadSpec {
             steps: 100,
             delay: 2,
             pause: 2000,
+            mix: None,
+            cpus: None,
+            spread: false,
+            jitter: false,
         }
     }
 }
 
 impl ThreadSpec {
     fn pause(&self) {
-        timer::sleep(Duration::microseconds(self.pause as i64));
+        delay(Duration::from_micros(self.pause));
+    }
+
+    fn pause_with(&self, rng: &mut Rng) {
+        let us = if self.jitter { rng.below(2 * self.pause + 1) } else { self.pause };
+        delay(Duration::from_micros(us));
+    }
+
+    /// Whether operation number `step` of a thread spawned as a reader or
+    /// writer should be a write.
+    fn is_write(&self, spawned_as_writer: bool, step: u64) -> bool {
+        match self.mix {
+            Some(mix) => mix.is_write(step),
+            None => spawned_as_writer,
+        }
+    }
+
+    /// Pin thread number `i` as the spec asks.
+    fn pin(&self, nodes: &[Vec<usize>], i: usize) {
+        let cpu = match (self.spread, self.cpus) {
+            (true, _) => spread(nodes, i),
+            (false, Some(cpus)) => Some(cpus.nth(i)),
+            (false, None) => None,
+        };
+        if let Some(cpu) = cpu {
+            pin(cpu);
+        }
     }
 }
 
@@ -172,104 +616,347 @@ struct SharedData<M> {
     failed_checks: AtomicUsize,
 }
 
-#[derive(Copy, Debug)]
+impl<M: TestableMutex> SharedData<M> {
+    /// If `cpu` is set, the data is allocated and first written by a thread
+    /// pinned there, so that Linux puts its pages on that CPU's NUMA node.
+    fn new(cpu: Option<usize>) -> Box<SharedData<M>> {
+        let create = move || {
+            if let Some(cpu) = cpu {
+                pin(cpu);
+            }
+            Box::new(SharedData {
+                mutex: <M as TestableMutex>::create(),
+                shutdown: AtomicBool::new(false),
+                failed_checks: AtomicUsize::new(0),
+            })
+        };
+        match cpu {
+            Some(_) => thread::scope(|scope| scope.spawn(create).join().unwrap()),
+            None => create(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BenchMode { Reader, Writer }
 
+/// Which thread of a `reader_writer_test` took a sample.  Readers and
+/// writers are numbered from zero, separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Thread { Measuring, Reader(u64), Writer(u64) }
+
+/// One operation's latency, attributed to its thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadSample {
+    pub thread: Thread,
+    /// Whether the operation was a read or a write.
+    pub mode: BenchMode,
+    pub ns: u64,
+}
+
 pub struct BenchRequest<'a> {
     pub mode: BenchMode,
+    /// Operations to run first, without recording them, while caches,
+    /// the allocator and the CPU's clock speed settle.
+    pub warmup: u64,
     pub num_samples: u64,
     pub samples: &'a mut Vec<u64>,
+    /// If set, pin the measuring thread, which is the caller's, to this
+    /// CPU.  It stays pinned afterwards.
+    pub cpu: Option<usize>,
+    /// If set, allocate the protected data on this CPU's NUMA node.
+    pub data_cpu: Option<usize>,
+    /// If set, the background threads time their operations too, each
+    /// into its own buffer.  Those are merged here afterwards, thread by
+    /// thread, after the measuring thread's samples.
+    pub thread_samples: Option<&'a mut Vec<ThreadSample>>,
+    /// If set, and the implementation counts them, the number of times
+    /// each sampled read was retried, in the same order as `samples`.
+    pub retries: Option<&'a mut Vec<u32>>,
+    /// If set, called on the measuring thread with `true` just before the
+    /// first sample and `false` just after the last, to measure the
+    /// sampled section some other way as well.
+    pub around: Option<&'a mut dyn FnMut(bool)>,
+}
+
+/// What one thread of a `reader_writer_test` did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadStats {
+    pub thread: Thread,
+    pub reads: u64,
+    pub writes: u64,
+    /// Reads which saw a torn write.
+    pub failed_checks: u64,
+}
+
+/// A thread of a `reader_writer_test` which panicked.
+#[derive(Clone, Debug)]
+pub struct Panic {
+    pub thread: Thread,
+    pub message: String,
+}
+
+/// The results of a `reader_writer_test`.  Torn reads and panics are
+/// reported here, not asserted on, so that the caller can decide what
+/// they mean.
+#[derive(Clone, Debug)]
+pub struct TestOutcome {
+    /// Reads which saw a torn write, in all threads.
+    pub failed_checks: usize,
+    /// The measuring thread, if there was a `BenchRequest`, and then the
+    /// readers and writers in the order they were spawned.
+    pub per_thread_stats: Vec<ThreadStats>,
+    pub panics: Vec<Panic>,
+    /// The seed for the test's random choices.  Set `SEQLOQ_SEED` to this
+    /// to make them again.
+    pub seed: u64,
+}
+
+impl TestOutcome {
+    /// No torn reads and no panics.
+    pub fn is_ok(&self) -> bool {
+        self.failed_checks == 0 && self.panics.is_empty()
+    }
+
+    /// Panic unless `is_ok`, saying how to re-run with the same seed.
+    pub fn assert_ok(&self) {
+        for p in &self.panics {
+            eprintln!("{:?} thread panicked: {}", p.thread, p.message);
+        }
+        assert!(self.is_ok(), "{} torn reads and {} panics; re-run with SEQLOQ_SEED={}",
+            self.failed_checks, self.panics.len(), self.seed);
+    }
+}
+
+fn panic_message(e: Box<dyn std::any::Any + Send>) -> String {
+    match e.downcast::<String>() {
+        Ok(s) => *s,
+        Err(e) => e.downcast_ref::<&str>().map_or("(not a string)".into(), |s| s.to_string()),
+    }
 }
 
 pub fn reader_writer_test<M: TestableMutex>(
     readers: ThreadSpec,
     writers: ThreadSpec,
-    bench: Option<BenchRequest>,
-    should_fail: bool)
+    mut bench: Option<BenchRequest<'_>>) -> TestOutcome
 {
-    // should be safe to put this on the stack, but screw it
-    let shared = Arc::new(SharedData {
-        mutex: <M as TestableMutex>::create(),
-        shutdown: AtomicBool::new(false),
-        failed_checks: AtomicUsize::new(0),
-    });
-    let mut guards = vec![];
-
-    macro_rules! go {
-        ($spec:ident, $is_writer:expr) => {
-            for _ in 0..$spec.qty {
-                let shared = shared.clone();
-                guards.push(Thread::scoped(move || {
-                    for _ in 0..$spec.steps {
-                        let delay = Duration::microseconds($spec.delay as i64);
-                        if $is_writer {
-                            shared.mutex.frob(delay);
-                        } else {
-                            if 0 != shared.mutex.check(delay) {
-                                shared.failed_checks.fetch_add(1, Ordering::SeqCst);
-                            }
-                        }
+    let shared = &*SharedData::<M>::new(bench.as_ref().and_then(|b| b.data_cpu));
+    let nodes = &numa_nodes();
+    let mut thread_samples = bench.as_mut().and_then(|b| b.thread_samples.take());
+    let timed = thread_samples.is_some();
+    let mut per_thread_stats = vec![];
+    let mut panics = vec![];
+    let seed = seed();
+    let mut rng = Rng::new(seed);
+
+    thread::scope(|scope| {
+        let mut guards = vec![];
+
+        let mut order: Vec<Thread> = (0..readers.qty).map(Thread::Reader)
+            .chain((0..writers.qty).map(Thread::Writer))
+            .collect();
+        if readers.jitter || writers.jitter {
+            rng.shuffle(&mut order);
+        }
 
-                        $spec.pause();
-                        if shared.shutdown.load(Ordering::SeqCst) {
-                            break;
+        for thread in order {
+            let (spec, i, is_writer) = match thread {
+                Thread::Reader(i) => (readers, i, false),
+                Thread::Writer(i) => (writers, i, true),
+                Thread::Measuring => unreachable!(),
+            };
+            let mut rng = Rng::new(rng.next_u64());
+            guards.push((thread, scope.spawn(move || {
+                spec.pin(nodes, i as usize);
+                let mut stats = ThreadStats { thread, reads: 0, writes: 0, failed_checks: 0 };
+                let mut samples = vec![];
+                for step in 0..spec.steps {
+                    let delay = Duration::from_micros(spec.delay);
+                    let t0 = timed.then(Instant::now);
+                    let mode = if spec.is_write(is_writer, step) {
+                        shared.mutex.frob(delay);
+                        stats.writes += 1;
+                        BenchMode::Writer
+                    } else {
+                        if 0 != shared.mutex.check(delay) {
+                            shared.failed_checks.fetch_add(1, Ordering::SeqCst);
+                            stats.failed_checks += 1;
                         }
+                        stats.reads += 1;
+                        BenchMode::Reader
+                    };
+                    if let Some(t0) = t0 {
+                        let ns = t0.elapsed().as_nanos() as u64;
+                        samples.push(ThreadSample { thread, mode, ns });
                     }
+
+                    spec.pause_with(&mut rng);
+                    if shared.shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
+                (stats, samples)
+            })));
+        }
+
+        if let Some(mut bench) = bench {
+            if let Some(cpu) = bench.cpu {
+                pin(cpu);
+            }
+            let mut stats = ThreadStats { thread: Thread::Measuring, reads: 0, writes: 0, failed_checks: 0 };
+            for i in 0..bench.warmup + bench.num_samples {
+                if i == bench.warmup && let Some(f) = &mut bench.around {
+                    f(true);
+                }
+                let t0;
+                let t1;
+                match bench.mode {
+                    BenchMode::Reader => {
+                        t0 = Instant::now();
+                        let (res, retries) = shared.mutex.check_counted(Duration::ZERO);
+                        t1 = Instant::now();
+                        if res != 0 {
+                            shared.failed_checks.fetch_add(1, Ordering::SeqCst);
+                            stats.failed_checks += 1;
+                        }
+                        stats.reads += 1;
+                        if let (Some(r), Some(out)) = (retries, &mut bench.retries)
+                            && i >= bench.warmup
+                        {
+                            out.push(r);
+                        }
+                        readers.pause();
+                    },
+
+                    BenchMode::Writer => {
+                        t0 = Instant::now();
+                        shared.mutex.frob(Duration::ZERO);
+                        t1 = Instant::now();
+                        stats.writes += 1;
+                        writers.pause();
+                    },
+                }
+                if i >= bench.warmup {
+                    bench.samples.push((t1 - t0).as_nanos() as u64);
+                }
+            }
+            if bench.num_samples > 0 && let Some(f) = &mut bench.around {
+                f(false);
+            }
+            per_thread_stats.push(stats);
+
+            shared.shutdown.store(true, Ordering::SeqCst);
+            if let Some(all) = &mut thread_samples {
+                all.extend(bench.samples.iter().map(|&ns| {
+                    ThreadSample { thread: Thread::Measuring, mode: bench.mode, ns }
                 }));
             }
         }
-    }
 
-    go!(readers, false);
-    go!(writers, true);
-
-    if let Some(bench) = bench {
-        for _ in 0..bench.num_samples {
-            let t0;
-            let t1;
-            match bench.mode {
-                BenchMode::Reader => {
-                    t0 = precise_time_ns();
-                    let res = shared.mutex.check(Duration::zero());
-                    t1 = precise_time_ns();
-                    assert_eq!(res, 0);
-                    readers.pause();
-                },
-
-                BenchMode::Writer => {
-                    t0 = precise_time_ns();
-                    shared.mutex.frob(Duration::zero());
-                    t1 = precise_time_ns();
-                    writers.pause();
-                },
+        for (thread, r) in guards {
+            match r.join() {
+                Ok((stats, samples)) => {
+                    per_thread_stats.push(stats);
+                    if let Some(all) = &mut thread_samples {
+                        all.extend(samples);
+                    }
+                }
+                Err(e) => panics.push(Panic { thread, message: panic_message(e) }),
             }
-            bench.samples.push(t1 - t0);
         }
+    });
 
-        shared.shutdown.store(true, Ordering::SeqCst);
+    TestOutcome {
+        failed_checks: shared.failed_checks.load(Ordering::SeqCst),
+        per_thread_stats,
+        panics,
+        seed,
     }
+}
 
-    for r in guards.into_iter() {
-        if let Err(e) = r.join() {
-            // can't print Box<Any + Send> :(
-            panic!("thread panicked with {:?}", e.get_type_id());
-        }
+/// Operations completed by all threads in a `throughput_test`.  Torn
+/// reads are counted, not asserted on, as in `TestOutcome`.
+#[derive(Clone, Copy, Debug)]
+pub struct Throughput {
+    /// Reads which saw a torn write, in all threads.
+    pub failed_checks: usize,
+    pub reads: u64,
+    pub writes: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn reads_per_sec(&self) -> f64 {
+        self.reads as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn writes_per_sec(&self) -> f64 {
+        self.writes as f64 / self.elapsed.as_secs_f64()
     }
+}
+
+/// Run readers and writers for `window`, counting the operations they
+/// complete.  The threads' `steps` are ignored: they run until the window
+/// closes.
+pub fn throughput_test<M: TestableMutex>(
+    readers: ThreadSpec,
+    writers: ThreadSpec,
+    window: Duration) -> Throughput
+{
+    let shared = &*SharedData::<M>::new(None);
+    let nodes = &numa_nodes();
+    let (reads, writes) = (&AtomicU64::new(0), &AtomicU64::new(0));
+    let start = &Barrier::new((readers.qty + writers.qty + 1) as usize);
+
+    let elapsed = thread::scope(|scope| {
+        for (spec, is_writer) in [(readers, false), (writers, true)] {
+            for i in 0..spec.qty {
+                scope.spawn(move || {
+                    spec.pin(nodes, i as usize);
+                    let delay = Duration::from_micros(spec.delay);
+                    let (mut r, mut w) = (0, 0);
+                    start.wait();
+                    while !shared.shutdown.load(Ordering::Relaxed) {
+                        if spec.is_write(is_writer, r + w) {
+                            shared.mutex.frob(delay);
+                            w += 1;
+                        } else {
+                            if 0 != shared.mutex.check(delay) {
+                                shared.failed_checks.fetch_add(1, Ordering::SeqCst);
+                            }
+                            r += 1;
+                        }
+                        if spec.pause > 0 {
+                            spec.pause();
+                        }
+                    }
+                    reads.fetch_add(r, Ordering::SeqCst);
+                    writes.fetch_add(w, Ordering::SeqCst);
+                });
+            }
+        }
 
-    let failures = shared.failed_checks.load(Ordering::SeqCst);
-    if should_fail {
-        assert!(failures > 0);
-    } else {
-        assert_eq!(failures, 0);
+        start.wait();
+        let t0 = Instant::now();
+        thread::sleep(window);
+        shared.shutdown.store(true, Ordering::SeqCst);
+        t0.elapsed()
+    });
+
+    Throughput {
+        failed_checks: shared.failed_checks.load(Ordering::SeqCst),
+        reads: reads.load(Ordering::SeqCst),
+        writes: writes.load(Ordering::SeqCst),
+        elapsed,
     }
 }
 
 macro_rules! mk_test {
-    ($name:ident, $mutex:ident) => {
+    ($name:ident, $($mutex:ident)::+) => {
         #[test]
         fn $name() {
-            let spec = Default::default();
-            reader_writer_test::<$mutex<TestArray>>(spec, spec, None, false);
+            let spec = ThreadSpec { jitter: true, ..Default::default() };
+            reader_writer_test::<$($mutex)::+<TestArray>>(spec, spec, None).assert_ok();
         }
     }
 }
@@ -279,8 +966,237 @@ mk_test!(test_rwlock, RwLock);
 mk_test!(test_seqloq, Seqloq);
 mk_test!(test_seqloq_peek, SeqloqPeek);
 
+#[cfg(feature = "compare")]
+mk_test!(test_parking_lot_rwlock, parking_lot::RwLock);
+#[cfg(feature = "compare")]
+mk_test!(test_seqlock, seqlock::SeqLock);
+#[cfg(feature = "compare")]
+mk_test!(test_atomic_cell, crossbeam_utils::atomic::AtomicCell);
+#[cfg(feature = "compare")]
+mk_test!(test_arc_swap, arc_swap::ArcSwap);
+
 #[test]
 fn test_bogus_mutex() {
-    let spec = Default::default();
-    reader_writer_test::<BogusMutex<TestArray>>(spec, spec, None, true);
+    let spec = ThreadSpec { jitter: true, ..Default::default() };
+    let outcome = reader_writer_test::<BogusMutex<TestArray>>(spec, spec, None);
+    assert!(outcome.failed_checks > 0);
+    assert!(outcome.panics.is_empty());
+}
+
+#[test]
+fn test_throughput() {
+    let spec = ThreadSpec { qty: 2, delay: 0, pause: 0, ..Default::default() };
+    let t = throughput_test::<Seqloq<TestArray>>(spec, spec, Duration::from_millis(20));
+    assert_eq!(t.failed_checks, 0);
+    assert!(t.reads > 0 && t.writes > 0);
+    assert!(t.elapsed >= Duration::from_millis(20));
+}
+
+#[test]
+fn test_bogus_throughput() {
+    let spec = ThreadSpec { qty: 2, delay: 0, pause: 0, ..Default::default() };
+    let t = throughput_test::<BogusMutex<TestArray>>(spec, spec, Duration::from_millis(50));
+    assert!(t.failed_checks > 0);
+}
+
+#[test]
+fn test_mix() {
+    let mix = Mix { reads: 9, writes: 1 };
+    let writes: Vec<u64> = (0..30).filter(|&i| mix.is_write(i)).collect();
+    assert_eq!(writes, [9, 19, 29]);
+    assert!(!(0..10).any(|i| Mix { reads: 1, writes: 0 }.is_write(i)));
+
+    let spec = ThreadSpec { qty: 4, mix: Some(Mix { reads: 3, writes: 1 }), ..Default::default() };
+    let none = ThreadSpec { qty: 0, ..Default::default() };
+    reader_writer_test::<Seqloq<TestArray>>(spec, none, None).assert_ok();
+    let t = throughput_test::<Seqloq<TestArray>>(spec, none, Duration::from_millis(20));
+    assert!(t.reads > 0 && t.writes > 0);
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn test_pinning() {
+    let cpus = Some(Cpus { first: 0, count: 1 });
+    let spec = ThreadSpec { qty: 2, delay: 0, pause: 0, cpus, ..Default::default() };
+    let t = throughput_test::<Seqloq<TestArray>>(spec, spec, Duration::from_millis(10));
+    assert!(t.reads > 0);
+    assert!(!pin_to_cpu(usize::MAX));
+}
+
+#[test]
+fn test_numa() {
+    assert_eq!(cpu_list("0-3,8,10-11"), [0, 1, 2, 3, 8, 10, 11]);
+    let nodes = vec![vec![0, 1], vec![], vec![4, 5, 6]];
+    let cpus: Vec<_> = (0..6).map(|i| spread(&nodes, i).unwrap()).collect();
+    assert_eq!(cpus, [0, 4, 1, 5, 0, 6]);
+    assert_eq!(spread(&[], 0), None);
+
+    let nodes = numa_nodes();
+    let spec = ThreadSpec { qty: 4, spread: true, ..Default::default() };
+    let mut samples = vec![];
+    let bench = BenchRequest {
+        mode: BenchMode::Reader,
+        warmup: 0,
+        num_samples: 10,
+        samples: &mut samples,
+        cpu: None,
+        data_cpu: nodes.first().and_then(|n| n.first().copied()),
+        thread_samples: None,
+        retries: None,
+        around: None,
+    };
+    reader_writer_test::<Seqloq<TestArray>>(spec, spec, Some(bench)).assert_ok();
+    assert_eq!(samples.len(), 10);
+}
+
+#[test]
+fn test_thread_samples() {
+    let readers = ThreadSpec { qty: 3, steps: 5, pause: 0, ..Default::default() };
+    let writers = ThreadSpec { qty: 2, steps: 4, pause: 0, ..Default::default() };
+    let (mut samples, mut all, mut calls) = (vec![], vec![], vec![]);
+    let bench = BenchRequest {
+        mode: BenchMode::Writer,
+        warmup: 0,
+        num_samples: 10,
+        samples: &mut samples,
+        cpu: None,
+        data_cpu: None,
+        thread_samples: Some(&mut all),
+        retries: None,
+        around: Some(&mut |start| calls.push(start)),
+    };
+    let outcome = reader_writer_test::<Seqloq<TestArray>>(readers, writers, Some(bench));
+    outcome.assert_ok();
+    assert_eq!(calls, [true, false]);
+
+    let stats = &outcome.per_thread_stats;
+    assert_eq!(stats.len(), 6);
+    assert_eq!(stats[0], ThreadStats { thread: Thread::Measuring, reads: 0, writes: 10, failed_checks: 0 });
+    for s in &stats[1..] {
+        let n = all.iter().filter(|x| x.thread == s.thread).count() as u64;
+        assert_eq!(s.reads + s.writes, n);
+    }
+
+    let count = |t: Thread| all.iter().filter(|s| s.thread == t).count();
+    assert_eq!(count(Thread::Measuring), 10);
+    assert!(all[..10].iter().all(|s| s.mode == BenchMode::Writer));
+    // The background threads may stop early once the measuring thread is
+    // done, but always take at least one sample.
+    for i in 0..3 {
+        assert!((1..=5).contains(&count(Thread::Reader(i))));
+    }
+    for i in 0..2 {
+        assert!((1..=4).contains(&count(Thread::Writer(i))));
+    }
+    for s in &all[10..] {
+        let writer = matches!(s.thread, Thread::Writer(_));
+        assert_eq!(s.mode == BenchMode::Writer, writer);
+    }
+}
+
+#[test]
+fn test_retries() {
+    let readers = ThreadSpec { qty: 0, ..Default::default() };
+    let writers = ThreadSpec { qty: 2, pause: 0, ..Default::default() };
+    for peek in [false, true] {
+        let (mut samples, mut retries) = (vec![], vec![]);
+        let bench = BenchRequest {
+            mode: BenchMode::Reader,
+            warmup: 5,
+            num_samples: 50,
+            samples: &mut samples,
+            cpu: None,
+            data_cpu: None,
+            thread_samples: None,
+            retries: Some(&mut retries),
+            around: None,
+        };
+        if peek {
+            reader_writer_test::<SeqloqPeek<TestArray>>(readers, writers, Some(bench)).assert_ok();
+        } else {
+            reader_writer_test::<Seqloq<TestArray>>(readers, writers, Some(bench)).assert_ok();
+        }
+        assert_eq!(retries.len(), 50);
+    }
+
+    let mut retries = vec![];
+    let bench = BenchRequest {
+        mode: BenchMode::Reader,
+        warmup: 0,
+        num_samples: 10,
+        samples: &mut vec![],
+        cpu: None,
+        data_cpu: None,
+        thread_samples: None,
+        retries: Some(&mut retries),
+        around: None,
+    };
+    reader_writer_test::<Mutex<TestArray>>(readers, writers, Some(bench)).assert_ok();
+    assert!(retries.is_empty());
+}
+
+#[test]
+fn test_payload_size() {
+    let spec = ThreadSpec { qty: 4, ..Default::default() };
+    reader_writer_test::<Seqloq<TestArray<1>>>(spec, spec, None).assert_ok();
+    reader_writer_test::<SeqloqPeek<TestArray<64>>>(spec, spec, None).assert_ok();
+    let many = Default::default();
+    assert!(reader_writer_test::<BogusMutex<TestArray<64>>>(many, many, None).failed_checks > 0);
+    let t = throughput_test::<RwLock<TestArray<256>>>(spec, spec, Duration::from_millis(10));
+    assert!(t.reads > 0);
+}
+
+#[test]
+fn test_delay() {
+    for us in [0, 1, 5, 50, 1500] {
+        let d = Duration::from_micros(us);
+        let t0 = Instant::now();
+        delay(d);
+        assert!(t0.elapsed() + clock_cost() >= d);
+    }
+    assert!(clock_cost() < Duration::from_micros(10));
+}
+
+#[test]
+fn test_seed() {
+    let run = |seed| {
+        let mut rng = Rng::new(seed);
+        let mut v: Vec<u64> = (0..20).collect();
+        rng.shuffle(&mut v);
+        (v, rng.next_u64())
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+    let (mut v, _) = run(7);
+    v.sort();
+    assert!(v.iter().copied().eq(0..20));
+
+    let mut rng = Rng::new(1);
+    let picks: Vec<u64> = (0..1000).map(|_| rng.below(7)).collect();
+    assert!((0..7).all(|n| picks.contains(&n)) && picks.iter().all(|&n| n < 7));
+    assert_eq!(rng.below(0), 0);
+}
+
+#[test]
+fn test_checksummed() {
+    let (a, b) = (Checksummed::<8>::new(1), Checksummed::<8>::new(2));
+    assert!(a.is_valid() && b.is_valid());
+    assert_ne!(a, b);
+    for i in 0..8 {
+        let mut torn = a;
+        torn.words[i..].copy_from_slice(&b.words[i..]);
+        assert!(!torn.is_valid());
+        torn.sum = b.sum;
+        assert_eq!(torn.is_valid(), i == 0);
+    }
+}
+
+#[test]
+fn test_checksummed_payload() {
+    let spec = ThreadSpec { qty: 4, ..Default::default() };
+    reader_writer_test::<Seqloq<Checksummed>>(spec, spec, None).assert_ok();
+    reader_writer_test::<SeqloqPeek<Checksummed<16>>>(spec, spec, None).assert_ok();
+    reader_writer_test::<RwLock<Checksummed>>(spec, spec, None).assert_ok();
+    let many = Default::default();
+    assert!(reader_writer_test::<BogusMutex<Checksummed>>(many, many, None).failed_checks > 0);
 }
@@ -0,0 +1,118 @@
+//! A cached, coarse clock.
+//!
+//! Reading the system clock is cheap but not free, and some programs read
+//! it on every request.  `CoarseClock` reads it once per tick, from a
+//! background thread, and readers get the time of the last tick:
+//!
+//! ```
+//! use std::time::Duration;
+//! use seqloq::time::CoarseClock;
+//!
+//! let clock = CoarseClock::new(Duration::from_millis(1));
+//! let t0 = clock.now();
+//! std::thread::sleep(Duration::from_millis(20));
+//! assert!(clock.now() > t0);
+//! ```
+//!
+//! The time is at most one tick (plus scheduling delay) behind.  Handles are
+//! cheap to clone, and the thread exits within a tick once they're all
+//! dropped.
+
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::Seqloq;
+
+/// The clocks, as of the last tick.
+#[derive(Clone, Copy, Debug)]
+pub struct Now {
+    pub instant: Instant,
+    pub system: SystemTime,
+}
+
+impl Now {
+    fn get() -> Now {
+        Now {
+            instant: Instant::now(),
+            system: SystemTime::now(),
+        }
+    }
+}
+
+/// A handle to a clock updated by a background thread.
+#[derive(Clone)]
+pub struct CoarseClock {
+    now: Arc<Seqloq<Now>>,
+    interval: Duration,
+}
+
+impl CoarseClock {
+    /// Start a thread which updates the clock every `interval`.
+    pub fn new(interval: Duration) -> CoarseClock {
+        let now = Arc::new(Seqloq::new(Now::get()));
+        let weak: Weak<Seqloq<Now>> = Arc::downgrade(&now);
+        thread::Builder::new()
+            .name("seqloq-clock".into())
+            .spawn(move || {
+                loop {
+                    thread::sleep(interval);
+                    match weak.upgrade() {
+                        Some(now) => now.write(Now::get()),
+                        None => return,
+                    }
+                }
+            })
+            .expect("failed to spawn clock thread");
+        CoarseClock { now, interval }
+    }
+
+    /// The monotonic time as of the last tick.
+    #[inline]
+    pub fn now(&self) -> Instant {
+        self.now.peek(|p| unsafe { (*p).instant })
+    }
+
+    /// The wall-clock time as of the last tick.
+    #[inline]
+    pub fn system_now(&self) -> SystemTime {
+        self.now.peek(|p| unsafe { (*p).system })
+    }
+
+    /// Both clocks, from the same tick.
+    #[inline]
+    pub fn both(&self) -> Now {
+        self.now.read()
+    }
+
+    /// Time elapsed since `earlier`, by this clock.  Zero if `earlier` is
+    /// after the last tick.
+    #[inline]
+    pub fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    /// How often the clock ticks.
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+#[test]
+fn ticks() {
+    let clock = CoarseClock::new(Duration::from_millis(1));
+    let first = clock.both();
+    let copy = clock.clone();
+    thread::sleep(Duration::from_millis(50));
+    let later = copy.both();
+    assert!(later.instant > first.instant);
+    assert!(later.system >= first.system);
+    assert!(clock.elapsed(first.instant) > Duration::ZERO);
+    assert_eq!(clock.elapsed(later.instant + Duration::from_secs(1)), Duration::ZERO);
+
+    let weak = Arc::downgrade(&clock.now);
+    drop((clock, copy));
+    thread::sleep(Duration::from_millis(50));
+    assert!(weak.upgrade().is_none());
+}
@@ -0,0 +1,314 @@
+//! `tracing` integration.
+//!
+//! Wrap the writer mutex in `Traced` to give a `Seqloq` a name and have it
+//! report through `tracing`:
+//!
+//! ```
+//! use seqloq::{Seqloq, Traced, WordLock};
+//!
+//! let routes = Seqloq::with_mutex(0u32, Traced::new("routes", WordLock::new()));
+//! routes.write(1);
+//! ```
+//!
+//! Each write section is a `seqloq.write` span at trace level, closed by an
+//! event giving its duration.  A reader that keeps retrying produces a debug
+//! event once it passes the storm threshold.
+//!
+//! A long write holds up every reader.  `Traced::warn_slow_writes` sets a
+//! limit past which a write section is reported as a warning, with a
+//! backtrace of the end of the write in debug builds.
+
+use std::cell::UnsafeCell;
+use std::time::{Duration, Instant};
+
+use tracing::Span;
+
+use crate::RawMutex;
+
+/// A writer mutex which reports through `tracing`.
+pub struct Traced<M> {
+    inner: M,
+    name: &'static str,
+    storm: u32,
+    slow: Option<Duration>,
+    /// The open write span and when it started.  Protected by `inner`.
+    write: UnsafeCell<Option<(Span, Instant)>>,
+}
+
+unsafe impl<M: Send> Send for Traced<M> { }
+unsafe impl<M: Sync> Sync for Traced<M> { }
+
+impl<M: RawMutex> Traced<M> {
+    /// Readers count as a retry storm after 16 retries.
+    #[inline]
+    pub fn new(name: &'static str, inner: M) -> Traced<M> {
+        Traced::with_storm_threshold(name, inner, 16)
+    }
+
+    /// Readers count as a retry storm after `threshold` retries.
+    #[inline]
+    pub fn with_storm_threshold(name: &'static str, inner: M, threshold: u32) -> Traced<M> {
+        Traced {
+            inner,
+            name,
+            storm: threshold.max(1),
+            slow: None,
+            write: UnsafeCell::new(None),
+        }
+    }
+
+    /// Warn about write sections lasting longer than `threshold`.
+    #[inline]
+    pub fn warn_slow_writes(self, threshold: Duration) -> Traced<M> {
+        Traced {
+            slow: Some(threshold),
+            ..self
+        }
+    }
+
+    /// The name attached to spans and events.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Open the write span.  The caller must hold `inner`.
+    #[inline]
+    fn acquired(&self) {
+        let span = tracing::trace_span!("seqloq.write", name = self.name);
+        span.with_subscriber(|(id, dispatch)| dispatch.enter(id));
+        unsafe { *self.write.get() = Some((span, Instant::now())) };
+    }
+}
+
+unsafe impl<M: RawMutex> RawMutex for Traced<M> {
+    #[inline]
+    fn lock(&self) {
+        self.inner.lock();
+        self.acquired();
+    }
+
+    #[inline]
+    fn try_lock(&self) -> bool {
+        let locked = self.inner.try_lock();
+        if locked {
+            self.acquired();
+        }
+        locked
+    }
+
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        if let Some((span, start)) = unsafe { (*self.write.get()).take() } {
+            let elapsed = start.elapsed();
+            tracing::trace!(name = self.name, duration_us = elapsed.as_micros() as u64,
+                "seqloq write finished");
+            if self.slow.is_some_and(|t| elapsed > t) {
+                slow_write(self.name, elapsed);
+            }
+            span.with_subscriber(|(id, dispatch)| dispatch.exit(id));
+        }
+        unsafe { self.inner.unlock() };
+    }
+
+    #[inline]
+    fn reader_retried(&self, retries: u32) {
+        if retries == self.storm {
+            tracing::debug!(name = self.name, retries, "seqloq reader retry storm");
+        }
+        self.inner.reader_retried(retries);
+    }
+
+    #[inline]
+    fn reader_done(&self, retries: u32) {
+        if retries >= self.storm {
+            tracing::debug!(name = self.name, retries, "seqloq reader recovered");
+        }
+        self.inner.reader_done(retries);
+    }
+
+    #[inline]
+    fn writer_published(&self) {
+        self.inner.writer_published();
+    }
+
+    #[inline]
+    fn stats(&self) -> Option<crate::SeqloqStats> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn name(&self) -> Option<&'static str> {
+        Some(self.name)
+    }
+}
+
+#[cold]
+fn slow_write(name: &'static str, elapsed: Duration) {
+    let duration_us = elapsed.as_micros() as u64;
+    if cfg!(debug_assertions) {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        tracing::warn!(name, duration_us, %backtrace, "slow seqloq write");
+    } else {
+        tracing::warn!(name, duration_us, "slow seqloq write");
+    }
+}
+
+/// A span, an entry to or exit from one, or an event, as seen by
+/// `Recorder`.
+#[cfg(test)]
+struct Seen {
+    kind: &'static str,
+    /// The span's name, or the event's message.
+    name: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+#[cfg(test)]
+impl Seen {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(f, _)| *f == name).map(|(_, v)| &v[..])
+    }
+}
+
+#[cfg(test)]
+impl tracing::field::Visit for Seen {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.fields.push((field.name(), value.to_owned()));
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.name = format!("{:?}", value);
+        } else {
+            self.fields.push((field.name(), format!("{:?}", value)));
+        }
+    }
+}
+
+/// A subscriber which records everything, for tests to check.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<Seen>>>);
+
+#[cfg(test)]
+impl Recorder {
+    fn push(&self, kind: &'static str, name: String) {
+        self.0.lock().unwrap().push(Seen { kind, name, fields: vec![] });
+    }
+
+    /// Enter or exit the span with this id, by name.
+    fn span(&self, kind: &'static str, id: &tracing::span::Id) {
+        let name = self.0.lock().unwrap()[id.into_u64() as usize - 1].name.clone();
+        self.push(kind, name);
+    }
+
+    fn count(&self, kind: &str, name: &str) -> usize {
+        self.0.lock().unwrap().iter().filter(|s| s.kind == kind && s.name == name).count()
+    }
+}
+
+#[cfg(test)]
+impl tracing::Subscriber for Recorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut seen = Seen { kind: "span", name: span.metadata().name().into(), fields: vec![] };
+        span.record(&mut seen);
+        let mut all = self.0.lock().unwrap();
+        all.push(seen);
+        // Spans are only ever looked up by these ids.
+        tracing::span::Id::from_u64(all.len() as u64)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) { }
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) { }
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut seen = Seen { kind: "event", name: String::new(), fields: vec![] };
+        event.record(&mut seen);
+        self.0.lock().unwrap().push(seen);
+    }
+
+    fn enter(&self, id: &tracing::span::Id) {
+        self.span("enter", id);
+    }
+
+    fn exit(&self, id: &tracing::span::Id) {
+        self.span("exit", id);
+    }
+}
+
+#[test]
+fn traced() {
+    let rec = Recorder::default();
+    let x = crate::Seqloq::with_mutex(0u32,
+        Traced::with_storm_threshold("test", crate::WordLock::new(), 2));
+    assert_eq!(x.mutex.name(), "test");
+    tracing::subscriber::with_default(rec.clone(), || {
+        x.write(1);
+        *x.lock() += 1;
+        assert!(x.mutex.try_lock());
+        unsafe { x.mutex.unlock() };
+        x.mutex.reader_retried(1);
+        x.mutex.reader_retried(2);
+        x.mutex.reader_done(2);
+    });
+    assert_eq!(x.read(), 2);
+
+    // One span per write section, whether or not it wrote anything.
+    assert_eq!(rec.count("span", "seqloq.write"), 3);
+    assert_eq!(rec.count("enter", "seqloq.write"), 3);
+    assert_eq!(rec.count("exit", "seqloq.write"), 3);
+    assert_eq!(rec.count("event", "seqloq write finished"), 3);
+    assert_eq!(rec.count("event", "seqloq reader retry storm"), 1);
+    assert_eq!(rec.count("event", "seqloq reader recovered"), 1);
+    for s in rec.0.lock().unwrap().iter().filter(|s| s.kind != "enter" && s.kind != "exit") {
+        assert_eq!(s.field("name"), Some("test"), "{}", s.name);
+    }
+    assert!(rec.0.lock().unwrap().iter()
+        .filter(|s| s.name == "seqloq write finished")
+        .all(|s| s.field("duration_us").is_some()));
+}
+
+#[test]
+fn slow_writes() {
+    let rec = Recorder::default();
+    let m = Traced::new("test", crate::WordLock::new())
+        .warn_slow_writes(Duration::from_millis(1));
+    let x = crate::Seqloq::with_mutex(0u32, m);
+    tracing::subscriber::with_default(rec.clone(), || {
+        let mut g = x.lock();
+        std::thread::sleep(Duration::from_millis(2));
+        *g = 1;
+        drop(g);
+        // Quick writes stay quiet.
+        x.write(2);
+        x.write(3);
+    });
+    assert_eq!(x.read(), 3);
+
+    let all = rec.0.lock().unwrap();
+    let us = |s: &Seen| s.field("duration_us").unwrap().parse::<u64>().unwrap();
+    let finished: Vec<u64> = all.iter().filter(|s| s.name == "seqloq write finished")
+        .map(us).collect();
+    let slow: Vec<&Seen> = all.iter().filter(|s| s.name == "slow seqloq write").collect();
+    assert_eq!(finished.len(), 3);
+    assert!(finished[0] >= 2000, "{:?}", finished);
+    // A warning for each write over the threshold, and no others.  The
+    // quick ones are normally well under, but a busy machine may say not.
+    assert_eq!(slow.len(), finished.iter().filter(|&&d| d >= 1000).count());
+    for s in slow {
+        assert_eq!(s.field("name"), Some("test"));
+        assert!(us(s) >= 1000, "{}", us(s));
+        assert_eq!(s.field("backtrace").is_some(), cfg!(debug_assertions));
+    }
+}
@@ -0,0 +1,131 @@
+//! Wait-free single-producer, single-consumer triple buffer.
+//!
+//! A `Seqloq` reader retries while a write is in progress.  A triple buffer
+//! avoids that by keeping three copies of the data: one the writer owns, one
+//! the reader owns, and a spare "back" buffer.  The writer fills its buffer
+//! and swaps it with the back buffer; the reader, on finding the back buffer
+//! newer than its own, swaps again.  Neither side ever waits or retries.
+//!
+//! The price is three copies of `T`, and only one reader.  Readers see the
+//! latest complete value, but may skip intermediate ones.
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+
+use crate::sync::{AtomicU32, Ordering};
+
+/// Set in `back` when the back buffer holds a value the reader hasn't seen.
+const FRESH: u32 = 4;
+const INDEX: u32 = 3;
+
+struct Shared<T> {
+    bufs: [UnsafeCell<T>; 3],
+    back: AtomicU32,
+}
+
+// Each buffer is owned by exactly one side at a time, and ownership moves
+// through `back` with acquire/release swaps.
+unsafe impl<T: Send> Sync for Shared<T> { }
+
+/// The writing half of a triple buffer.
+pub struct TripleWriter<T> {
+    shared: Arc<Shared<T>>,
+    idx: u32,
+}
+
+/// The reading half of a triple buffer.
+pub struct TripleReader<T> {
+    shared: Arc<Shared<T>>,
+    idx: u32,
+}
+
+/// Create a triple buffer holding `t`.
+pub fn triple_buffer<T: Clone + Send>(t: T) -> (TripleWriter<T>, TripleReader<T>) {
+    let shared = Arc::new(Shared {
+        bufs: [UnsafeCell::new(t.clone()), UnsafeCell::new(t.clone()), UnsafeCell::new(t)],
+        back: AtomicU32::new(1),
+    });
+    (TripleWriter { shared: shared.clone(), idx: 0 },
+     TripleReader { shared, idx: 2 })
+}
+
+impl<T: Send> TripleWriter<T> {
+    /// The writer's private buffer.
+    ///
+    /// It holds whatever was in the back buffer when it was last swapped, so
+    /// it isn't necessarily the last value written.  Changes are invisible
+    /// until `publish`.
+    #[inline]
+    pub fn input(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.bufs[self.idx as usize].get() }
+    }
+
+    /// Make the private buffer visible to the reader.
+    #[inline]
+    pub fn publish(&mut self) {
+        let old = self.shared.back.swap(self.idx | FRESH, Ordering::AcqRel);
+        self.idx = old & INDEX;
+    }
+
+    /// Replace the value.
+    #[inline]
+    pub fn write(&mut self, t: T) {
+        *self.input() = t;
+        self.publish();
+    }
+}
+
+impl<T: Send> TripleReader<T> {
+    /// Is there a value newer than the one `read` last returned?
+    #[inline]
+    pub fn updated(&self) -> bool {
+        self.shared.back.load(Ordering::Relaxed) & FRESH != 0
+    }
+
+    /// The latest published value.
+    #[inline]
+    pub fn read(&mut self) -> &T {
+        if self.updated() {
+            let old = self.shared.back.swap(self.idx, Ordering::AcqRel);
+            self.idx = old & INDEX;
+        }
+        unsafe { &*self.shared.bufs[self.idx as usize].get() }
+    }
+}
+
+#[test]
+fn triple() {
+    let (mut w, mut r) = triple_buffer(String::from("a"));
+    assert_eq!(r.read(), "a");
+    assert!(!r.updated());
+
+    w.write("b".into());
+    w.write("c".into());
+    assert!(r.updated());
+    assert_eq!(r.read(), "c");
+    assert_eq!(r.read(), "c");
+
+    w.input().push('d');
+    w.publish();
+    assert_eq!(r.read(), "bd");
+}
+
+#[test]
+fn triple_threads() {
+    let (mut w, mut r) = triple_buffer([0u64; 16]);
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            for i in 1..=10_000 {
+                w.write([i; 16]);
+            }
+        });
+        let mut last = 0;
+        while last < 10_000 {
+            let v = *r.read();
+            assert!(v.iter().all(|&x| x == v[0]));
+            assert!(v[0] >= last);
+            last = v[0];
+            std::thread::yield_now();
+        }
+    });
+}
@@ -0,0 +1,341 @@
+//! Optimistic transactions.
+//!
+//! A transaction takes a snapshot without locking, lets you compute a new
+//! value at leisure, and then commits it only if nobody else has written in
+//! the meantime.
+//!
+//! `SeqloqTx` covers a single `Seqloq`.  `Transaction` covers any number of
+//! them, possibly with different payload types.
+
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::error::Error;
+
+use crate::{Seqloq, RawMutex, WordLock, RawGuard, copy};
+
+/// Returned when a transaction's snapshot was invalidated by another write.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Conflict;
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("seqloq was written since the snapshot was taken")
+    }
+}
+
+impl Error for Conflict { }
+
+/// A snapshot of a `Seqloq`, which can be replaced by a new value if it's
+/// still current.
+pub struct SeqloqTx<'a, T: 'a, M: RawMutex + 'a = WordLock> {
+    seqloq: &'a Seqloq<T, M>,
+    seqnum: u64,
+    value: T,
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Start an optimistic transaction.
+    ///
+    /// This reads the data without locking, like `read`.
+    #[inline]
+    pub fn begin(&self) -> SeqloqTx<'_, T, M> {
+        let (value, seqnum) = self.read_versioned();
+        SeqloqTx {
+            seqloq: self,
+            seqnum,
+            value,
+        }
+    }
+}
+
+impl<T, M> SeqloqTx<'_, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// The value as of the start of the transaction.
+    #[inline]
+    pub fn value(&self) -> T {
+        self.value
+    }
+
+    /// Is the snapshot still current?
+    ///
+    /// A `true` result can be stale by the time you act on it; only `commit`
+    /// checks atomically.
+    #[inline]
+    pub fn is_current(&self) -> bool {
+        self.seqloq.seqnum.version() == self.seqnum
+    }
+
+    /// Store `new` if there have been no writes since the snapshot.
+    ///
+    /// This takes the write lock, but only for the duration of the copy.
+    #[inline]
+    pub fn commit(self, new: T) -> Result<(), Conflict> {
+        let _guard = self.seqloq.guard();
+        if !self.is_current() {
+            return Err(Conflict);
+        }
+
+        unsafe {
+            self.seqloq.store(new);
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a `Seqloq` and its type.
+///
+/// The address alone isn't enough: a `Seqloq` may sit at the very start of
+/// some other value, and in principle that could be another `Seqloq`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    addr: usize,
+    ty: TypeId,
+}
+
+impl Key {
+    fn of<T: 'static, M: 'static>(seqloq: &Seqloq<T, M>) -> Key {
+        Key {
+            addr: seqloq as *const Seqloq<T, M> as usize,
+            ty: TypeId::of::<Seqloq<T, M>>(),
+        }
+    }
+}
+
+/// What a transaction knows about one `Seqloq`.
+struct State<T> {
+    snapshot: Option<(T, u64)>,
+    staged: Option<T>,
+}
+
+/// Type-erased view of one `Seqloq` participating in a `Transaction`.
+trait Entry<'a> {
+    fn key(&self) -> Key;
+    /// The `State<T>`, for `Transaction` to downcast.
+    fn state(&mut self) -> &mut dyn Any;
+    fn acquire(&mut self);
+    fn is_current(&self) -> bool;
+    fn begin_write(&mut self);
+    fn end_write(&mut self);
+    fn release(&mut self);
+}
+
+struct TxEntry<'a, T: 'a, M: RawMutex + 'a> {
+    seqloq: &'a Seqloq<T, M>,
+    state: State<T>,
+    guard: Option<RawGuard<'a, M>>,
+}
+
+impl<'a, T, M> Entry<'a> for TxEntry<'a, T, M>
+    where T: Send + Copy + 'static,
+          M: RawMutex + 'static,
+{
+    fn key(&self) -> Key {
+        Key::of(self.seqloq)
+    }
+
+    fn state(&mut self) -> &mut dyn Any {
+        &mut self.state
+    }
+
+    fn acquire(&mut self) {
+        self.guard = Some(self.seqloq.guard());
+    }
+
+    fn is_current(&self) -> bool {
+        match self.state.snapshot {
+            Some((_, seqnum)) => self.seqloq.seqnum.version() == seqnum,
+            None => true,
+        }
+    }
+
+    fn begin_write(&mut self) {
+        if let Some(v) = self.state.staged {
+            self.seqloq.begin_write();
+            unsafe {
+                copy::store(self.seqloq.data.get(), v);
+            }
+        }
+    }
+
+    fn end_write(&mut self) {
+        if self.state.staged.is_some() {
+            self.seqloq.end_write();
+        }
+    }
+
+    fn release(&mut self) {
+        self.guard = None;
+    }
+}
+
+/// An optimistic transaction over several `Seqloq`s.
+///
+/// Reads are recorded along with the sequence number they observed, and
+/// writes are staged privately.  `commit` locks every participating `Seqloq`
+/// in address order, checks that nothing read has since been written, and
+/// only then publishes the staged writes.  On conflict nothing is written.
+///
+/// Reads of different `Seqloq`s happen at different times, so the values
+/// seen during the transaction may be mutually inconsistent.  Such a
+/// transaction will always fail to commit, but don't let it crash you.
+pub struct Transaction<'a> {
+    entries: Vec<Box<dyn Entry<'a> + 'a>>,
+}
+
+impl<'a> Default for Transaction<'a> {
+    fn default() -> Transaction<'a> {
+        Transaction::new()
+    }
+}
+
+impl<'a> Transaction<'a> {
+    #[inline]
+    pub fn new() -> Transaction<'a> {
+        Transaction {
+            entries: vec![],
+        }
+    }
+
+    fn entry<T, M>(&mut self, seqloq: &'a Seqloq<T, M>) -> &mut State<T>
+        where T: Send + Copy + 'static,
+              M: RawMutex + 'static,
+    {
+        let key = Key::of(seqloq);
+        let idx = match self.entries.iter().position(|e| e.key() == key) {
+            Some(i) => i,
+            None => {
+                self.entries.push(Box::new(TxEntry {
+                    seqloq,
+                    state: State {
+                        snapshot: None,
+                        staged: None,
+                    },
+                    guard: None,
+                }));
+                self.entries.len() - 1
+            }
+        };
+        self.entries[idx].state().downcast_mut().expect("seqloq transaction entry of wrong type")
+    }
+
+    /// Read a `Seqloq` as part of this transaction.
+    ///
+    /// Returns the value staged by this transaction, if any.  Otherwise the
+    /// first read takes a snapshot, and later reads return the same value.
+    pub fn read<T, M>(&mut self, seqloq: &'a Seqloq<T, M>) -> T
+        where T: Send + Copy + 'static,
+              M: RawMutex + 'static,
+    {
+        let entry = self.entry(seqloq);
+        if let Some(v) = entry.staged {
+            return v;
+        }
+        match entry.snapshot {
+            Some((v, _)) => v,
+            None => {
+                let snap = seqloq.read_versioned();
+                entry.snapshot = Some(snap);
+                snap.0
+            }
+        }
+    }
+
+    /// Stage a write, to be published by `commit`.
+    ///
+    /// Writing a `Seqloq` that wasn't read is a blind write; it can't
+    /// conflict.
+    pub fn write<T, M>(&mut self, seqloq: &'a Seqloq<T, M>, value: T)
+        where T: Send + Copy + 'static,
+              M: RawMutex + 'static,
+    {
+        self.entry(seqloq).staged = Some(value);
+    }
+
+    /// Publish all staged writes, if none of the values read are stale.
+    pub fn commit(mut self) -> Result<(), Conflict> {
+        // Lock in a canonical order so concurrent transactions can't
+        // deadlock.
+        self.entries.sort_by_key(|e| e.key());
+        for e in self.entries.iter_mut() {
+            e.acquire();
+        }
+
+        let res = if self.entries.iter().all(|e| e.is_current()) {
+            for e in self.entries.iter_mut() {
+                e.begin_write();
+            }
+            for e in self.entries.iter_mut() {
+                e.end_write();
+            }
+            Ok(())
+        } else {
+            Err(Conflict)
+        };
+
+        for e in self.entries.iter_mut().rev() {
+            e.release();
+        }
+        res
+    }
+}
+
+#[test]
+fn commit() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+
+    let tx = x.begin();
+    assert_eq!(tx.value(), 3);
+    assert!(tx.is_current());
+    assert_eq!(tx.commit(4), Ok(()));
+    assert_eq!(x.read(), 4);
+}
+
+#[test]
+fn conflict() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+
+    let tx = x.begin();
+    *x.lock() = 5;
+    assert!(!tx.is_current());
+    assert_eq!(tx.commit(4), Err(Conflict));
+    assert_eq!(x.read(), 5);
+}
+
+#[test]
+fn group_commit() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+    let y: Seqloq<char> = Seqloq::new('a');
+
+    let mut tx = Transaction::new();
+    let n = tx.read(&x);
+    tx.write(&x, n + 1);
+    tx.write(&y, 'b');
+    assert_eq!(tx.read(&x), 4);
+    assert_eq!(x.read(), 3);
+    assert_eq!(tx.commit(), Ok(()));
+
+    assert_eq!(x.read(), 4);
+    assert_eq!(y.read(), 'b');
+}
+
+#[test]
+fn group_conflict() {
+    let x: Seqloq<u32> = Seqloq::new(3);
+    let y: Seqloq<char> = Seqloq::new('a');
+
+    let mut tx = Transaction::new();
+    tx.write(&y, 'b');
+    let n = tx.read(&x);
+    tx.write(&x, n + 1);
+
+    *x.lock() = 7;
+    assert_eq!(tx.commit(), Err(Conflict));
+
+    assert_eq!(x.read(), 7);
+    assert_eq!(y.read(), 'a');
+}
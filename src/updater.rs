@@ -0,0 +1,128 @@
+//! Updates sent over a channel.
+//!
+//! `Seqloq::updater` returns a sender of updates, which never blocks on the
+//! writer mutex, and a `Drain` which applies whatever has been sent so far
+//! under a single lock.  Producers on hot paths send; one thread drains.
+//!
+//! ```
+//! use seqloq::Seqloq;
+//!
+//! let totals = Seqloq::new([0u64; 2]);
+//! let (tx, drain) = totals.updater();
+//! std::thread::scope(|s| {
+//!     for i in 0..2 {
+//!         let tx = tx.clone();
+//!         s.spawn(move || {
+//!             for _ in 0..100 {
+//!                 tx.send(move |t| t[i] += 1).unwrap();
+//!             }
+//!         });
+//!     }
+//!     drop(tx);
+//!     drain.run();
+//! });
+//! assert_eq!(totals.read(), [100, 100]);
+//! ```
+
+use std::sync::mpsc::{self, Receiver, RecvError, Sender, SendError};
+
+use crate::{Seqloq, RawMutex};
+
+type Update<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Sends updates to a `Seqloq`.  Cloneable, for any number of producers.
+pub struct Updater<T> {
+    tx: Sender<Update<T>>,
+}
+
+/// Applies the updates sent by `Updater`s.
+pub struct Drain<'a, T, M> {
+    seqloq: &'a Seqloq<T, M>,
+    rx: Receiver<Update<T>>,
+}
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// A channel of updates to this `Seqloq`.
+    pub fn updater(&self) -> (Updater<T>, Drain<'_, T, M>) {
+        let (tx, rx) = mpsc::channel();
+        (Updater { tx }, Drain { seqloq: self, rx })
+    }
+}
+
+impl<T> Updater<T> {
+    /// Send an update, to be applied on the next drain.
+    ///
+    /// This never blocks.  It fails only if the `Drain` is gone.
+    pub fn send<F>(&self, f: F) -> Result<(), SendError<()>>
+        where F: FnOnce(&mut T) + Send + 'static,
+    {
+        self.tx.send(Box::new(f)).map_err(|_| SendError(()))
+    }
+}
+
+impl<T> Clone for Updater<T> {
+    #[inline]
+    fn clone(&self) -> Updater<T> {
+        Updater { tx: self.tx.clone() }
+    }
+}
+
+impl<T, M> Drain<'_, T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Apply every update sent so far, in one write, and return how many
+    /// there were.  If there were none, nothing is written.
+    pub fn drain(&self) -> usize {
+        match self.rx.try_recv() {
+            Ok(f) => self.apply(f),
+            Err(_) => 0,
+        }
+    }
+
+    /// Wait for an update, then apply it and any others sent so far, in one
+    /// write.  Fails once every `Updater` is gone and nothing is pending.
+    pub fn recv(&self) -> Result<usize, RecvError> {
+        self.rx.recv().map(|f| self.apply(f))
+    }
+
+    /// Keep applying updates until every `Updater` is gone.
+    pub fn run(&self) {
+        while self.recv().is_ok() { }
+    }
+
+    fn apply(&self, first: Update<T>) -> usize {
+        let mut guard = self.seqloq.lock();
+        first(&mut guard);
+        let mut n = 1;
+        for f in self.rx.try_iter() {
+            f(&mut guard);
+            n += 1;
+        }
+        n
+    }
+}
+
+#[test]
+fn updater() {
+    let x = Seqloq::new(1u32);
+    let (tx, drain) = x.updater();
+    assert_eq!(drain.drain(), 0);
+    assert_eq!(x.version(), 0);
+
+    let tx2 = tx.clone();
+    tx.send(|n| *n += 1).unwrap();
+    tx2.send(|n| *n *= 10).unwrap();
+    assert_eq!(x.read(), 1);
+    assert_eq!(drain.recv(), Ok(2));
+    assert_eq!((x.read(), x.version()), (20, 2));
+
+    drop((tx, tx2));
+    assert_eq!(drain.recv(), Err(RecvError));
+    let (tx, drain) = x.updater();
+    drop(drain);
+    assert!(tx.send(|_| { }).is_err());
+}
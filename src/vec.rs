@@ -0,0 +1,208 @@
+//! Variable-length payloads.
+//!
+//! `Seqloq` needs a `Copy` payload of fixed size.  `SeqloqVec` holds up to a
+//! fixed number of elements, chosen at construction, while the number
+//! actually in use can change with every write.  That covers routing tables,
+//! lookup arrays and the like, without padding everything out to the
+//! maximum size.
+
+use std::cell::UnsafeCell;
+use std::cmp;
+use std::ptr;
+
+use crate::{RawMutex, RawGuard, SeqCount, WordLock, copy, read_section};
+
+/// A bounded, variable-length array protected by the seqlock protocol.
+pub struct SeqloqVec<T, M = WordLock> {
+    mutex: M,
+    seqnum: SeqCount,
+    len: UnsafeCell<usize>,
+    buf: Box<[UnsafeCell<T>]>,
+}
+
+unsafe impl<T: Send, M: Send> Send for SeqloqVec<T, M> { }
+unsafe impl<T: Send, M: Sync> Sync for SeqloqVec<T, M> { }
+
+impl<T> SeqloqVec<T>
+    where T: Send + Copy + Default,
+{
+    /// Create an empty `SeqloqVec` with room for `capacity` elements.
+    #[inline]
+    pub fn new(capacity: usize) -> SeqloqVec<T> {
+        SeqloqVec::with_mutex(&[], capacity, WordLock::new())
+    }
+
+    /// Create a `SeqloqVec` with room for `capacity` elements, initially
+    /// holding a copy of `items`.
+    ///
+    /// Panics if `items` is longer than `capacity`.
+    #[inline]
+    pub fn from_slice(items: &[T], capacity: usize) -> SeqloqVec<T> {
+        SeqloqVec::with_mutex(items, capacity, WordLock::new())
+    }
+}
+
+impl<T, M> SeqloqVec<T, M>
+    where T: Send + Copy + Default,
+          M: RawMutex,
+{
+    /// As `from_slice`, using `mutex` to exclude concurrent writers.
+    pub fn with_mutex(items: &[T], capacity: usize, mutex: M) -> SeqloqVec<T, M> {
+        assert!(items.len() <= capacity, "SeqloqVec capacity exceeded");
+
+        // Unused slots are initialized too, so that a reader who sees a
+        // torn length never copies garbage.
+        let buf = (0..capacity).map(|i| {
+            UnsafeCell::new(items.get(i).cloned().unwrap_or_default())
+        }).collect();
+
+        SeqloqVec {
+            mutex,
+            seqnum: SeqCount::new(),
+            len: UnsafeCell::new(items.len()),
+            buf,
+        }
+    }
+}
+
+impl<T, M> SeqloqVec<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// The maximum number of elements.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    #[inline(always)]
+    fn slot(&self, i: usize) -> *mut T {
+        self.buf[i].get()
+    }
+
+    /// The current number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        read_section(&self.mutex, &self.seqnum, || unsafe {
+            copy::load(self.len.get())
+        }).0
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replace the contents of `out` with a consistent snapshot.
+    ///
+    /// Reusing `out` across calls avoids allocating on every read.
+    pub fn read_into(&self, out: &mut Vec<T>) {
+        read_section(&self.mutex, &self.seqnum, || {
+            let len = unsafe { copy::load(self.len.get()) };
+            // The length may be torn; don't let it take us out of bounds.
+            let len = cmp::min(len, self.capacity());
+
+            out.clear();
+            out.extend((0..len).map(|i| unsafe { copy::load(self.slot(i)) }));
+        });
+    }
+
+    /// Copy out a consistent snapshot.
+    #[inline]
+    pub fn read_to_vec(&self) -> Vec<T> {
+        let mut v = Vec::with_capacity(self.capacity());
+        self.read_into(&mut v);
+        v
+    }
+
+    /// Peek at the elements without copying them, as with `Seqloq::peek`.
+    ///
+    /// With the `atomic-copy` feature, this works on a private copy.
+    pub fn peek<F, R>(&self, mut f: F) -> R
+        where F: FnMut(*const [T]) -> R,
+    {
+        #[cfg(feature = "atomic-copy")]
+        {
+            let v = self.read_to_vec();
+            f(&v[..])
+        }
+
+        #[cfg(not(feature = "atomic-copy"))]
+        read_section(&self.mutex, &self.seqnum, || {
+            let len = cmp::min(unsafe { *self.len.get() }, self.capacity());
+            // UnsafeCell<T> has the same layout as T.
+            let base = self.buf.as_ptr() as *const T;
+            f(ptr::slice_from_raw_parts(base, len))
+        }).0
+    }
+
+    /// Replace the contents with a copy of `items`.
+    ///
+    /// Panics if `items` is longer than the capacity.
+    pub fn write(&self, items: &[T]) {
+        assert!(items.len() <= self.capacity(), "SeqloqVec capacity exceeded");
+
+        let _guard = RawGuard::lock(&self.mutex);
+        self.seqnum.write_begin();
+        unsafe {
+            copy::store(self.len.get(), items.len());
+            for (i, x) in items.iter().enumerate() {
+                copy::store(self.slot(i), *x);
+            }
+        }
+        self.seqnum.write_end();
+    }
+
+    /// Modify the contents through a `Vec`.
+    ///
+    /// The update is staged in a private copy, so readers aren't disturbed
+    /// until it's published, all at once, when `f` returns.  Panics if `f`
+    /// grows the `Vec` beyond the capacity.
+    pub fn update<F>(&self, f: F)
+        where F: FnOnce(&mut Vec<T>),
+    {
+        let _guard = RawGuard::lock(&self.mutex);
+
+        // We hold the writer mutex, so nothing can change under us.
+        let len = unsafe { *self.len.get() };
+        let mut staged: Vec<T> = (0..len).map(|i| unsafe { ptr::read(self.slot(i)) }).collect();
+        f(&mut staged);
+        assert!(staged.len() <= self.capacity(), "SeqloqVec capacity exceeded");
+
+        self.seqnum.write_begin();
+        unsafe {
+            copy::store(self.len.get(), staged.len());
+            for (i, x) in staged.iter().enumerate() {
+                copy::store(self.slot(i), *x);
+            }
+        }
+        self.seqnum.write_end();
+    }
+}
+
+#[test]
+fn smoke_test() {
+    let x: SeqloqVec<u32> = SeqloqVec::from_slice(&[1, 2, 3], 8);
+    assert_eq!(x.capacity(), 8);
+    assert_eq!(x.len(), 3);
+    assert_eq!(x.read_to_vec(), vec![1, 2, 3]);
+    assert_eq!(x.peek(|s| unsafe { (*s).iter().sum::<u32>() }), 6);
+
+    x.write(&[4, 5]);
+    assert_eq!(x.read_to_vec(), vec![4, 5]);
+
+    x.update(|v| v.extend_from_slice(&[6, 7, 8, 9, 10, 11]));
+    let mut out = vec![];
+    x.read_into(&mut out);
+    assert_eq!(out, vec![4, 5, 6, 7, 8, 9, 10, 11]);
+
+    x.update(|v| v.clear());
+    assert!(x.is_empty());
+}
+
+#[test]
+#[should_panic]
+fn over_capacity() {
+    let x: SeqloqVec<u32> = SeqloqVec::new(2);
+    x.write(&[1, 2, 3]);
+}
@@ -0,0 +1,123 @@
+//! Seqlocks over memory that something outside the program writes.
+//!
+//! When the data is written by a device, by DMA, or by another agent the
+//! compiler knows nothing about, an ordinary copy may be merged, split or
+//! cached in ways that assume nobody else touches the memory.
+//! `SeqloqVolatile` copies the data with `read_volatile` and
+//! `write_volatile` instead, which the compiler must leave as they are.
+//!
+//! It has the same layout as `SeqloqC` (see the `layout` module), so it can
+//! be laid over a buffer that a device fills following that protocol:
+//!
+//! ```
+//! use seqloq::volatile::SeqloqVolatile;
+//!
+//! # let mut buf = [0u64; 6];
+//! # let dma_buffer = buf.as_mut_ptr() as *mut SeqloqVolatile<[u64; 4]>;
+//! let status = unsafe { SeqloqVolatile::<[u64; 4]>::from_ptr(dma_buffer) };
+//! let regs = status.read();
+//! # assert_eq!(regs, [0; 4]);
+//! ```
+
+use std::cell::UnsafeCell;
+use std::ptr;
+
+use crate::{SeqCount, SharedLock, RawGuard, read_section};
+
+/// A seqlock whose data is always copied with volatile accesses.
+#[repr(C)]
+pub struct SeqloqVolatile<T> {
+    seqnum: SeqCount,
+    mutex: SharedLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SeqloqVolatile<T> { }
+unsafe impl<T: Send> Sync for SeqloqVolatile<T> { }
+
+impl<T> SeqloqVolatile<T>
+    where T: Send + Copy,
+{
+    #[inline]
+    pub fn new(t: T) -> SeqloqVolatile<T> {
+        SeqloqVolatile {
+            seqnum: SeqCount::new(),
+            mutex: SharedLock::new(),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Use a seqlock at `p`, which may be device or DMA memory.
+    ///
+    /// # Safety
+    ///
+    /// `p` must be aligned, valid for reads and writes for `'a`, and laid out
+    /// as described in the `layout` module, with a `T` at the data offset.
+    #[inline]
+    pub unsafe fn from_ptr<'a>(p: *mut SeqloqVolatile<T>) -> &'a SeqloqVolatile<T> {
+        unsafe { &*p }
+    }
+
+    /// As `Seqloq::read`.
+    #[inline]
+    pub fn read(&self) -> T {
+        self.read_versioned().0
+    }
+
+    /// As `Seqloq::read_versioned`.
+    #[inline]
+    pub fn read_versioned(&self) -> (T, u64) {
+        read_section(&self.mutex, &self.seqnum, || unsafe {
+            ptr::read_volatile(self.data.get())
+        })
+    }
+
+    /// As `Seqloq::version`.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seqnum.version()
+    }
+
+    /// As `Seqloq::write`.
+    #[inline]
+    pub fn write(&self, t: T) {
+        let _guard = RawGuard::lock(&self.mutex);
+        self.seqnum.write_begin();
+        unsafe { ptr::write_volatile(self.data.get(), t) };
+        self.seqnum.write_end();
+    }
+
+    /// As `Seqloq::update`.
+    #[inline]
+    pub fn update<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut T) -> R,
+    {
+        let _guard = RawGuard::lock(&self.mutex);
+        let mut t = unsafe { ptr::read_volatile(self.data.get()) };
+        let r = f(&mut t);
+        self.seqnum.write_begin();
+        unsafe { ptr::write_volatile(self.data.get(), t) };
+        self.seqnum.write_end();
+        r
+    }
+}
+
+#[cfg(not(any(loom, shuttle)))]
+#[test]
+fn volatile() {
+    use std::mem::{offset_of, size_of};
+
+    assert_eq!(offset_of!(SeqloqVolatile<u32>, data), 16);
+    assert_eq!(size_of::<SeqloqVolatile<[u64; 4]>>(), size_of::<crate::SeqloqC<[u64; 4]>>());
+
+    let x = SeqloqVolatile::new([0u64; 4]);
+    x.write([1; 4]);
+    assert_eq!(x.update(|t| { t[0] = 2; t[1] }), 1);
+    assert_eq!(x.read_versioned(), ([2, 1, 1, 1], 4));
+
+    // Written through the C-compatible type, read through this one.
+    let c = crate::SeqloqC::new(7u32);
+    c.write(8);
+    let v = unsafe { SeqloqVolatile::from_ptr(&c as *const _ as *mut SeqloqVolatile<u32>) };
+    assert_eq!(v.read_versioned(), (8, 2));
+}
@@ -0,0 +1,82 @@
+//! Bridging to `tokio::sync::watch` channels.
+//!
+//! A watch channel is Tokio's idiom for "the latest value of something".
+//! `mirror` keeps one up to date with a `Seqloq`; `follow` goes the other
+//! way, so that non-async readers can use the cheap seqlock read path.
+//! Both are plain futures and don't need a Tokio runtime.  Enable the `tokio`
+//! feature to use them.
+
+use tokio::sync::watch;
+
+use crate::{Seqloq, RawMutex};
+
+impl<T, M> Seqloq<T, M>
+    where T: Send + Copy,
+          M: RawMutex,
+{
+    /// Send every published value to `tx`, starting with the current one.
+    ///
+    /// Writes published in quick succession may be coalesced.  Finishes at
+    /// the first write after the last receiver is dropped.
+    pub async fn mirror(&self, tx: watch::Sender<T>) {
+        let (t, mut v) = self.read_versioned();
+        tx.send_replace(t);
+        loop {
+            self.changed_since(v).await;
+            if tx.is_closed() {
+                return;
+            }
+            let (t, nv) = self.read_versioned();
+            v = nv;
+            tx.send_replace(t);
+        }
+    }
+
+    /// Write every value sent on `rx`, starting with the current one.
+    ///
+    /// Finishes when the sender is dropped.
+    pub async fn follow(&self, mut rx: watch::Receiver<T>) {
+        loop {
+            let t = *rx.borrow_and_update();
+            self.write(t);
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn mirror() {
+    use crate::future::block_on;
+
+    let x = Seqloq::new(0u32);
+    let (tx, mut rx) = watch::channel(0);
+    std::thread::scope(|s| {
+        s.spawn(|| block_on(x.mirror(tx)));
+
+        x.write(1);
+        block_on(async {
+            while *rx.borrow_and_update() != 1 {
+                rx.changed().await.unwrap();
+            }
+        });
+        drop(rx);
+        x.write(2);
+    });
+}
+
+#[test]
+fn follow() {
+    use crate::future::block_on;
+
+    let x = Seqloq::new(0u32);
+    let (tx, rx) = watch::channel(1);
+    std::thread::scope(|s| {
+        s.spawn(|| block_on(x.follow(rx)));
+        assert_eq!(x.wait_until(|&v| v == 1), 1);
+        tx.send_replace(2);
+        assert_eq!(x.wait_until(|&v| v == 2), 2);
+        drop(tx);
+    });
+}
@@ -0,0 +1,78 @@
+//! `#[derive(Seqloqed)]`, which needs the `derive` feature.
+
+#![cfg(feature = "derive")]
+
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use seqloq::Seqloqed;
+
+#[derive(Seqloqed, Clone, Copy, Debug, PartialEq)]
+pub struct State {
+    pub position: [f32; 3],
+    pub health: u32,
+}
+
+#[derive(Seqloqed, Clone, Copy)]
+#[seqloqed(name = "Pair")]
+struct Two {
+    a: u64,
+    b: u64,
+}
+
+#[test]
+fn fields() {
+    let s = StateSeqloqed::new(State { position: [0.0; 3], health: 100 });
+    s.set_health(90);
+    assert_eq!(s.health(), 90);
+    assert_eq!(s.position(), [0.0; 3]);
+    s.write(State { position: [1.0; 3], health: 80 });
+    assert_eq!(s.snapshot(), State { position: [1.0; 3], health: 80 });
+    assert_eq!(s.version(), 4);
+}
+
+#[test]
+fn snapshot_consistent() {
+    let p: Pair = Two { a: 0, b: 0 }.into();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 1..=10_000 {
+                p.write(Two { a: i, b: i });
+            }
+        });
+        loop {
+            let t = p.snapshot();
+            assert_eq!(t.a, t.b);
+            if t.a == 10_000 {
+                break;
+            }
+        }
+    });
+    p.set_a(1);
+    assert_eq!((p.a(), p.b()), (1, 10_000));
+}
+
+#[derive(Seqloqed, Clone, Copy)]
+struct Clash {
+    x: u8,
+    set_y: u8,
+}
+
+#[test]
+fn set_prefix_without_field() {
+    let c = ClashSeqloqed::new(Clash { x: 1, set_y: 2 });
+    c.set_set_y(3);
+    assert_eq!((c.x(), c.set_y()), (1, 3));
+}
+
+#[test]
+fn unwind() {
+    let p: Pair = Two { a: 0, b: 0 }.into();
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        p.__seqloq_locked(|| panic!("boom"));
+    }));
+    assert!(r.is_err());
+    assert_eq!(p.version() % 2, 0);
+    p.write(Two { a: 1, b: 1 });
+    assert_eq!(p.snapshot().a, 1);
+}
@@ -0,0 +1,113 @@
+//! Model checking with loom.
+//!
+//! Run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+//!
+//! loom doesn't track plain memory accesses, so the payloads here yield
+//! between fields to give the scheduler a chance to tear them.
+
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+
+use seqloq::Seqloq;
+
+#[test]
+fn reader_never_torn() {
+    loom::model(|| {
+        let x = Arc::new(Seqloq::new((0u32, 0u32)));
+
+        let writer = {
+            let x = x.clone();
+            thread::spawn(move || {
+                let mut g = x.lock();
+                g.0 = 1;
+                thread::yield_now();
+                g.1 = 1;
+            })
+        };
+
+        let (a, b) = x.peek(|p| unsafe {
+            let a = (*p).0;
+            thread::yield_now();
+            (a, (*p).1)
+        });
+        assert_eq!(a, b);
+
+        writer.join().unwrap();
+        assert_eq!(x.read(), (1, 1));
+    });
+}
+
+#[test]
+fn publish_is_consistent() {
+    loom::model(|| {
+        let x = Arc::new(Seqloq::new((0u32, 0u32)));
+
+        let writer = {
+            let x = x.clone();
+            thread::spawn(move || {
+                let mut g = x.lock();
+                g.0 = 1;
+                g.1 = 1;
+                g.publish();
+                g.0 = 2;
+                thread::yield_now();
+                g.1 = 2;
+            })
+        };
+
+        let (a, b) = x.read();
+        assert_eq!(a, b);
+
+        writer.join().unwrap();
+        assert_eq!(x.read(), (2, 2));
+    });
+}
+
+#[test]
+fn writers_are_paired() {
+    loom::model(|| {
+        let x = Arc::new(Seqloq::new(0u32));
+
+        let writers: Vec<_> = (0..2).map(|_| {
+            let x = x.clone();
+            thread::spawn(move || {
+                *x.lock() += 1;
+            })
+        }).collect();
+
+        for w in writers {
+            w.join().unwrap();
+        }
+
+        // Would spin forever if a write section were left open.
+        assert_eq!(x.read(), 2);
+    });
+}
+
+#[test]
+fn commit_detects_conflict() {
+    loom::model(|| {
+        let x = Arc::new(Seqloq::new(0u32));
+
+        let other = {
+            let x = x.clone();
+            thread::spawn(move || {
+                *x.lock() += 10;
+            })
+        };
+
+        let tx = x.begin();
+        let v = tx.value();
+        let committed = tx.commit(v + 1).is_ok();
+
+        other.join().unwrap();
+        let expected = if committed { 11 } else { 10 };
+        assert_eq!(x.read(), expected);
+    });
+}
@@ -0,0 +1,197 @@
+//! Property-based tests: arbitrary sequences of reads and writes, checked
+//! against a model, and against each other when run from several threads.
+//!
+//! These complement the timing-based tests in `seqloq::tests`, which rely
+//! on sleeps to provoke races.  Here the operations are generated, and
+//! proptest shrinks any failing sequence to a minimal one.
+
+use std::collections::HashSet;
+use std::sync::{Barrier, Mutex};
+use std::thread;
+
+use proptest::prelude::*;
+
+use seqloq::Seqloq;
+
+/// Every element equal, so that a torn read shows.
+type Value = [u64; 4];
+
+fn value(v: u64) -> Value {
+    [v; 4]
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Read,
+    ReadVersioned,
+    Write(u64),
+    Update(u64),
+    /// Lock, and write each value in turn, publishing in between.
+    Lock(Vec<u64>),
+    TryLock(u64),
+    /// Stage a value, then commit it or abort.
+    Staged(u64, bool),
+    SetIfChanged(u64),
+}
+
+fn op(values: impl Strategy<Value = u64> + Clone) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        Just(Op::Read),
+        Just(Op::ReadVersioned),
+        values.clone().prop_map(Op::Write),
+        values.clone().prop_map(Op::Update),
+        prop::collection::vec(values.clone(), 0..4).prop_map(Op::Lock),
+        values.clone().prop_map(Op::TryLock),
+        (values.clone(), any::<bool>()).prop_map(|(v, commit)| Op::Staged(v, commit)),
+        values.prop_map(Op::SetIfChanged),
+    ]
+}
+
+/// Apply `op`, returning each value it read and each value it wrote.
+fn apply(x: &Seqloq<Value>, op: &Op, reads: &mut Vec<(Value, u64)>, writes: &mut Vec<u64>) {
+    match *op {
+        Op::Read => reads.push((x.read(), x.version())),
+        Op::ReadVersioned => reads.push(x.read_versioned()),
+        Op::Write(v) => {
+            writes.push(v);
+            x.write(value(v));
+        }
+        Op::Update(v) => {
+            writes.push(v);
+            x.update(|t| *t = value(v));
+        }
+        Op::Lock(ref vs) => {
+            let mut g = x.lock();
+            for &v in vs {
+                writes.push(v);
+                *g = value(v);
+                g.publish();
+            }
+        }
+        Op::TryLock(v) => if let Some(mut g) = x.try_lock() {
+            writes.push(v);
+            *g = value(v);
+        },
+        Op::Staged(v, commit) => {
+            let mut g = x.lock_staged();
+            *g = value(v);
+            if commit {
+                writes.push(v);
+                g.commit();
+            } else {
+                g.abort();
+            }
+        }
+        Op::SetIfChanged(v) => {
+            writes.push(v);
+            x.set_if_changed(value(v));
+        }
+    }
+}
+
+proptest! {
+    /// From one thread, every read sees the last write, and the version
+    /// goes up by two for each write section.
+    #[test]
+    fn single_thread(ops in prop::collection::vec(op(0..8u64), 0..64)) {
+        let x = Seqloq::new(value(0));
+        let (mut current, mut version) = (0, 0);
+        for op in &ops {
+            let (mut reads, mut writes) = (vec![], vec![]);
+            apply(&x, op, &mut reads, &mut writes);
+            if let Op::SetIfChanged(v) = *op && v == current {
+                writes.clear();
+            }
+            version += 2 * writes.len() as u64;
+            if let Some(&v) = writes.last() {
+                current = v;
+            }
+            for (read, v) in reads {
+                prop_assert_eq!(read, value(current));
+                prop_assert_eq!(v, version);
+            }
+            prop_assert_eq!(x.version(), version);
+            prop_assert!(!x.is_locked());
+        }
+        prop_assert_eq!(x.read(), value(current));
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    /// From several threads, started together, every read is untorn and
+    /// equals some value that was written, versions never go backwards
+    /// within a thread, and each writer's values are seen in the order it
+    /// wrote them.
+    #[test]
+    fn threads(ops in prop::collection::vec(prop::collection::vec(op(0..1000u64), 0..32), 2..5)) {
+        let x = Seqloq::new(value(0));
+        let written = Mutex::new(HashSet::from([0]));
+        let barrier = Barrier::new(ops.len());
+
+        // Each thread writes values tagged with its number and a sequence
+        // number, so they're distinct and ordered.
+        let ops: Vec<Vec<Op>> = ops.into_iter().enumerate().map(|(t, ops)| {
+            let mut seq = 0;
+            let mut tag = |_: u64| {
+                seq += 1;
+                ((t as u64 + 1) << 32) | seq
+            };
+            ops.into_iter().map(|op| match op {
+                Op::Write(v) => Op::Write(tag(v)),
+                Op::Update(v) => Op::Update(tag(v)),
+                Op::Lock(vs) => Op::Lock(vs.into_iter().map(&mut tag).collect()),
+                Op::TryLock(v) => Op::TryLock(tag(v)),
+                Op::Staged(v, commit) => Op::Staged(tag(v), commit),
+                Op::SetIfChanged(v) => Op::SetIfChanged(tag(v)),
+                op => op,
+            }).collect()
+        }).collect();
+
+        let (x, written, barrier) = (&x, &written, &barrier);
+        let results: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = ops.iter().map(|ops| s.spawn(move || {
+                let (mut reads, mut writes) = (vec![], vec![]);
+                barrier.wait();
+                for op in ops {
+                    // Record writes before making them, so that a reader
+                    // can never see a value that isn't in the set yet.
+                    let mut pending = vec![];
+                    if let Op::Write(v) | Op::Update(v) | Op::TryLock(v)
+                        | Op::Staged(v, _) | Op::SetIfChanged(v) = *op
+                    {
+                        pending.push(v);
+                    }
+                    if let Op::Lock(vs) = op {
+                        pending.extend(vs);
+                    }
+                    written.lock().unwrap().extend(pending);
+                    apply(x, op, &mut reads, &mut writes);
+                }
+                (reads, writes)
+            })).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let written = written.lock().unwrap();
+        let mut last = vec![0];
+        for (reads, writes) in &results {
+            let mut version = 0;
+            let mut seen = vec![0; results.len() + 1];
+            for (read, v) in reads {
+                prop_assert!(read.iter().all(|&e| e == read[0]), "torn read {:?}", read);
+                prop_assert!(written.contains(&read[0]), "{} was never written", read[0]);
+                prop_assert!(*v >= version, "version went from {} to {}", version, v);
+                version = *v;
+                let (writer, seq) = ((read[0] >> 32) as usize, read[0] & 0xffff_ffff);
+                prop_assert!(seq >= seen[writer], "writer {} went back from {} to {}",
+                    writer, seen[writer], seq);
+                seen[writer] = seq;
+            }
+            last.extend(writes.last());
+        }
+        prop_assert!(last.contains(&x.read()[0]));
+        prop_assert!(x.version() % 2 == 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Randomized scheduling with shuttle.
+//!
+//! Run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg shuttle" cargo test --release --test shuttle
+//! ```
+//!
+//! Unlike loom, shuttle samples interleavings instead of enumerating them,
+//! so it copes with more threads and longer operation sequences.  As with
+//! loom, the payloads yield between fields so that the scheduler can try to
+//! tear them.
+
+#![cfg(shuttle)]
+
+use shuttle::sync::Arc;
+use shuttle::thread;
+
+use seqloq::Seqloq;
+
+const ITERATIONS: usize = 10_000;
+
+/// Three equal fields, written one at a time.
+type Triple = (u32, u32, u32);
+
+fn write_triple(x: &Seqloq<Triple>, v: u32) {
+    let mut g = x.lock();
+    g.0 = v;
+    thread::yield_now();
+    g.1 = v;
+    thread::yield_now();
+    g.2 = v;
+}
+
+fn peek_triple(x: &Seqloq<Triple>) -> Triple {
+    x.peek(|p| unsafe {
+        let a = (*p).0;
+        thread::yield_now();
+        let b = (*p).1;
+        thread::yield_now();
+        (a, b, (*p).2)
+    })
+}
+
+fn readers_and_writers(readers: usize, writers: usize, steps: u32) {
+    let x = Arc::new(Seqloq::new((0, 0, 0)));
+
+    let mut handles = vec![];
+    for w in 0..writers {
+        let x = x.clone();
+        handles.push(thread::spawn(move || {
+            for i in 0..steps {
+                write_triple(&x, (w as u32 + 1) * 100 + i);
+            }
+        }));
+    }
+    for r in 0..readers {
+        let x = x.clone();
+        handles.push(thread::spawn(move || {
+            for _ in 0..steps {
+                let (a, b, c) = if r % 2 == 0 { peek_triple(&x) } else { x.read() };
+                assert!(a == b && b == c, "torn read: {:?}", (a, b, c));
+            }
+        }));
+    }
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let (a, b, c) = x.read();
+    assert!(a == b && b == c);
+}
+
+#[test]
+fn one_writer() {
+    shuttle::check_random(|| readers_and_writers(2, 1, 3), ITERATIONS);
+}
+
+#[test]
+fn two_writers() {
+    shuttle::check_random(|| readers_and_writers(2, 2, 2), ITERATIONS);
+}
+
+#[test]
+fn pct() {
+    shuttle::check_pct(|| readers_and_writers(2, 2, 2), ITERATIONS, 3);
+}
+
+#[test]
+fn publish_and_staged() {
+    shuttle::check_random(|| {
+        let x = Arc::new(Seqloq::new((0u32, 0u32, 0u32)));
+
+        let writer = {
+            let x = x.clone();
+            thread::spawn(move || {
+                let mut g = x.lock();
+                g.0 = 1;
+                g.1 = 1;
+                g.2 = 1;
+                g.publish();
+                g.0 = 2;
+                thread::yield_now();
+                g.1 = 2;
+                g.2 = 2;
+                drop(g);
+
+                let mut s = x.lock_staged();
+                *s = (3, 3, 3);
+                s.commit();
+            })
+        };
+
+        for _ in 0..3 {
+            let (a, b, c) = peek_triple(&x);
+            assert!(a == b && b == c, "torn read: {:?}", (a, b, c));
+        }
+
+        writer.join().unwrap();
+        assert_eq!(x.read(), (3, 3, 3));
+    }, ITERATIONS);
+}
@@ -0,0 +1,54 @@
+//! Readers overlapping a writer, as ThreadSanitizer sees them.
+//!
+//! Run with
+//!
+//! ```text
+//! RUSTFLAGS="-Zsanitizer=thread" cargo +nightly test -Zbuild-std \
+//!     --target x86_64-unknown-linux-gnu --features tsan --test tsan
+//! ```
+//!
+//! which should report nothing.  Without the `tsan` feature, the reads that
+//! overlap a write section are data races, and TSan says so.
+
+use std::cell::UnsafeCell;
+use std::thread;
+
+use seqloq::Seqloq;
+
+const WRITES: usize = 1000;
+
+struct Slots([UnsafeCell<u64>; WRITES]);
+
+unsafe impl Sync for Slots { }
+
+/// The writer fills in a slot with plain stores, then publishes its index.
+/// Readers yield in the middle of `peek`, to let the writer in.  A reader
+/// which sees the index may read the slot: the write happens before the
+/// read.
+#[test]
+fn publish_index() {
+    let slots = &Slots(std::array::from_fn(|_| UnsafeCell::new(0)));
+    let published = &Seqloq::new([0usize; 2]);
+    thread::scope(|s| {
+        s.spawn(move || {
+            for i in 1..WRITES {
+                unsafe { *slots.0[i].get() = i as u64 * 10 };
+                published.write([i; 2]);
+            }
+        });
+        for _ in 0..2 {
+            s.spawn(move || {
+                let mut i = 0;
+                while i < WRITES - 1 {
+                    let [a, b] = published.peek(|p| {
+                        thread::yield_now();
+                        unsafe { *p }
+                    });
+                    assert_eq!(a, b);
+                    i = a;
+                    assert_eq!(unsafe { *slots.0[i].get() }, i as u64 * 10);
+                }
+            });
+        }
+    });
+}